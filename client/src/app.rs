@@ -1,28 +1,70 @@
+use crate::chunk_generation::ChunkGenerationQueue;
+use crate::config::ClientConfig;
 use crate::graphic;
+use crate::graphic::terrain::TextureAtlasBuilder;
 use crate::graphic::ui::GUIWrapper;
 use crate::graphic::FrameRenderer;
-use crate::networking::ClientNetworkHandler;
+use crate::networking::{ClientNetworkHandler, NetEvent, NetworkError};
+use crate::prediction::MovementPrediction;
+use crate::time_of_day::TimeOfDay;
 use egui_winit::winit::event::{DeviceEvent, ElementState, Event, MouseScrollDelta, RawKeyEvent, WindowEvent};
 use egui_winit::winit::event_loop::{EventLoop, EventLoopWindowTarget};
 use egui_winit::winit::keyboard::{KeyCode, PhysicalKey};
 use egui_winit::winit::window::WindowBuilder;
-use gen::Generator;
-use math::positions::{BlockPos, ChunkPos, EntityPos};
+use math::positions::{ChunkPos, EntityPos};
 use math::{DVec3, Vec3};
 use std::f32::consts::{FRAC_PI_2, PI};
 use std::time::{Duration, Instant};
-use world_core::{Chunk, ChunkManager, MEMORY_MANAGER};
+use world_core::block_registry::BlockRegistry;
+use world_core::{ChunkManager, ChunkManagerStats, MEMORY_MANAGER};
 use rand::Rng;
 
+///always displayed on top of the current menu
+fn hud(ctx: &egui::Context, data: &GUIData) {
+    if !data.show_hud {
+        return;
+    }
+    if let Some(targeted_block) = &data.targeted_block {
+        egui::Area::new(egui::Id::new("hud"))
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 10.0))
+            .show(ctx, |ui| ui.label(targeted_block.as_str()));
+    }
+}
+
 fn main_menu(gui_wrapper: &mut GUIWrapper<GUIData>, ctx: &egui::Context, data: &mut GUIData) {
+    hud(ctx, data);
     egui::Window::new("Tool box").show(ctx, |ui| {
         let fps = 1.0 / data.second_per_frame;
 
         let (used_memory, pre_allocated_memory) = MEMORY_MANAGER.stats();
-        ui.label(format!("fps: {:.2}", fps));
-        ui.label(format!("used memory: {}", used_memory));
-
-        ui.label(format!("pre-allocated memory: {}", pre_allocated_memory));
+        match data.latency_ms {
+            Some(latency_ms) => ui.label(format!("fps: {:.2}, ping: {:.1} ms", fps, latency_ms)),
+            None => ui.label(format!("fps: {:.2}", fps)),
+        };
+        let allocated_memory = used_memory + pre_allocated_memory;
+        ui.label(format!(
+            "used memory: {} / {} ({:.0}%)",
+            used_memory,
+            allocated_memory,
+            used_memory.ratio(&allocated_memory) * 100.0
+        ));
+        for (format, stats) in MEMORY_MANAGER.stats_by_format() {
+            ui.label(format!(
+                "  {}: {} chunks, {} / {}",
+                format,
+                stats.chunk_count,
+                stats.used,
+                stats.used + stats.free
+            ));
+        }
+        let stats = &data.chunk_manager_stats;
+        ui.label(format!(
+            "sections: {}, chunks: {}, fill: {:.1} avg / {} max",
+            stats.section_count,
+            stats.chunk_count,
+            stats.average_section_fill(),
+            stats.max_section_fill
+        ));
         if ui.button("more options").clicked() {
             gui_wrapper.set_gui(other_gui);
         }
@@ -36,17 +78,52 @@ fn main_menu(gui_wrapper: &mut GUIWrapper<GUIData>, ctx: &egui::Context, data: &
             data.yaw * 180.0 / PI,
             data.pitch * 180.0 / PI
         ));
-        ui.label(format!("rendered mesh count: {}", data.rendered_mesh_count));
+        ui.label(format!(
+            "rendered mesh count: {}, draw calls: {}",
+            data.rendered_mesh_count, data.draw_call_count
+        ));
+        ui.label(format!(
+            "built meshes: {}, cached meshes: {}",
+            data.built_mesh_count, data.cached_mesh_count
+        ));
         ui.label(format!("world seed: {}", data.world_seed));
+        if data.pending_chunk_count > 0 {
+            ui.label(format!("chunks to generate: {}", data.pending_chunk_count));
+        }
+        ui.checkbox(&mut data.show_hud, "show the crosshair and the HUD");
+        ui.add(
+            egui::Slider::new(&mut data.speed, 0.0..=CameraController::MAX_SPEED)
+                .text("speed (m/s)"),
+        );
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut data.movement_mode, MovementMode::Fly, "fly");
+            ui.radio_value(&mut data.movement_mode, MovementMode::Walk, "walk");
+        });
+        ui.label(format!("server: {}", data.connection_status));
     });
 }
 
 fn other_gui(gui_wrapper: &mut GUIWrapper<GUIData>, ctx: &egui::Context, guidata: &mut GUIData) {
+    hud(ctx, guidata);
     egui::Window::new("Options").show(ctx, |ui| {
         ui.label("world options");
         if ui.button("regenerate cube").clicked() {
             guidata.regenerate = true;
         }
+        ui.add(egui::Slider::new(&mut guidata.time_hours, 0.0..=24.0).text("time (h)"));
+        ui.add(
+            egui::Slider::new(&mut guidata.day_length_secs, 1.0..=3600.0)
+                .logarithmic(true)
+                .text("day length (s)"),
+        );
+        ui.horizontal(|ui| {
+            ui.label("sky zenith color");
+            ui.color_edit_button_rgb(&mut guidata.sky_zenith_color);
+        });
+        ui.horizontal(|ui| {
+            ui.label("sky horizon color");
+            ui.color_edit_button_rgb(&mut guidata.sky_horizon_color);
+        });
 
         if ui.button("back").clicked() {
             gui_wrapper.set_gui(main_menu);
@@ -61,7 +138,34 @@ struct GUIData {
     yaw: f32,
     pitch: f32,
     rendered_mesh_count: usize,
+    draw_call_count: usize,
+    ///the meshes built since the start, the meshes taken from the cache aren't counted
+    built_mesh_count: u64,
+    cached_mesh_count: usize,
+    chunk_manager_stats: ChunkManagerStats,
     world_seed: i64,
+    ///the chunks waiting to be generated
+    pending_chunk_count: usize,
+    connection_status: String,
+    latency_ms: Option<f32>,
+    ///the name of the block under the crosshair
+    targeted_block: Option<String>,
+    show_hud: bool,
+    sky_zenith_color: [f32; 3],
+    sky_horizon_color: [f32; 3],
+    ///the time of day, in hours
+    time_hours: f32,
+    day_length_secs: f32,
+    speed: f32,
+    movement_mode: MovementMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MovementMode {
+    ///free movement, space and shift move up and down
+    Fly,
+    ///the camera only moves on the XZ plane
+    Walk,
 }
 
 struct CameraController {
@@ -74,9 +178,12 @@ struct CameraController {
     mouse_x: f64,
     mouse_y: f64,
     speed: f32,
+    movement_mode: MovementMode,
 }
 
 impl CameraController {
+    const MAX_SPEED: f32 = 400.0;
+
     fn new() -> Self {
         Self {
             is_front_pressed: false,
@@ -88,6 +195,7 @@ impl CameraController {
             mouse_x: 0.0,
             mouse_y: 0.0,
             speed: 40.0, // m/s
+            movement_mode: MovementMode::Fly,
         }
     }
 
@@ -101,7 +209,7 @@ impl CameraController {
                     MouseScrollDelta::LineDelta(_, y) => -y / 25.0,
                     MouseScrollDelta::PixelDelta(_) => 0.0,
                 };
-                self.speed = self.speed.clamp(0.0, 400.0);
+                self.speed = self.speed.clamp(0.0, Self::MAX_SPEED);
             }
             DeviceEvent::MouseMotion { delta } => {
                 self.mouse_input(delta);
@@ -131,7 +239,12 @@ impl CameraController {
         self.mouse_y += delta.1;
     }
 
-    fn update_camera(&mut self, camera: &mut graphic::camera::Camera, delta_time: Duration) {
+    ///move the camera with the inputs since the last update, return the displacement applied
+    fn update_camera(
+        &mut self,
+        camera: &mut graphic::camera::Camera,
+        delta_time: Duration,
+    ) -> Vec3 {
         //update camera yaw and pitch
         camera.yaw += self.mouse_x as f32 * 0.0025;
 
@@ -164,14 +277,18 @@ impl CameraController {
             direction += Vec3::new(-camera.yaw.cos(), 0.0, -camera.yaw.sin());
         }
 
-        if self.is_up_pressed {
-            direction += Vec3::Y;
-        }
-        if self.is_down_pressed {
-            direction -= Vec3::Y;
+        if self.movement_mode == MovementMode::Fly {
+            if self.is_up_pressed {
+                direction += Vec3::Y;
+            }
+            if self.is_down_pressed {
+                direction -= Vec3::Y;
+            }
         }
-        camera.position += direction.normalize_or_zero() * self.speed * delta_time;
+        let displacement = direction.normalize_or_zero() * self.speed * delta_time;
+        camera.position += displacement;
         camera.position.try_shrink();
+        displacement
     }
 }
 
@@ -183,38 +300,30 @@ pub struct App {
     gui_handler: graphic::ui::GuiHandler<GUIData>,
     camera: graphic::camera::Camera,
     terrain_renderer: graphic::terrain::TerrainRenderer,
+    crosshair_renderer: graphic::crosshair::CrosshairRenderer,
+    sky_renderer: graphic::sky::SkyRenderer,
+    time_of_day: TimeOfDay,
     camera_controller: CameraController,
+    movement_prediction: MovementPrediction,
     chunk_manager: ChunkManager,
+    block_registry: BlockRegistry,
+    ///the local generation of the world, None when the chunks come from a server
+    chunk_generation: Option<ChunkGenerationQueue>,
     seed: i64,
+    connection_status: String,
 }
 
 impl App {
-    fn regenerate_cube(chunk_manager: &mut ChunkManager, generator: &mut Generator) {
-        //make a platform
-        let mut build_chunk = |x: i32, z: i32, y: i32| {
-            let mut chunk = Chunk::new(ChunkPos::new(x, y, z));
-
-            for ix in 0..16 {
-                for iz in 0..16 {
-                    for iy in 0..16 {
-                        let block =
-                            generator.get_block(ix + x * 16, iy + y * 16, iz + z * 16) as u16;
-                        chunk.set_block(BlockPos::new(ix, iy, iz), block);
-                    }
-                }
-            }
-            chunk_manager.insert_chunk(chunk);
-        };
+    ///the maximum distance of the targeted block, in blocks
+    const REACH: f32 = 16.0;
 
-        for x in -20..20 {
-            for z in -20..20 {
-                for y in -5..5 {
-                    build_chunk(x, z, y);
-                }
-            }
-        }
+    ///the chunks of the platform generated when playing offline
+    fn cube_positions() -> impl Iterator<Item = ChunkPos> {
+        (-20..20)
+            .flat_map(|x| (-20..20).flat_map(move |z| (-5..5).map(move |y| ChunkPos::new(x, y, z))))
     }
-    pub fn new() -> anyhow::Result<(Self, EventLoop<()>)> {
+
+    pub fn new(config: ClientConfig) -> anyhow::Result<(Self, EventLoop<()>)> {
         let event_loop = EventLoop::new()?;
         let window = WindowBuilder::new()
             .with_title("my super minecraft a bit empty")
@@ -223,7 +332,8 @@ impl App {
         let ratio = window.inner_size().width as f32 / window.inner_size().height as f32;
 
         let wgpu_instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let (window, graphic_context) = graphic::Window::new(window, wgpu_instance)?;
+        let (window, graphic_context) =
+            graphic::Window::new(window, wgpu_instance, config.msaa_samples)?;
 
         let mut gui_handler = graphic::ui::GuiHandler::new(&window, &graphic_context);
         gui_handler.set_gui(main_menu);
@@ -237,29 +347,67 @@ impl App {
             &graphic_context,
         );
 
-        //todo: move this to a better place, when the network will be implemented
-        let mut chunk_manager = ChunkManager::new();
+        let chunk_manager = ChunkManager::new();
 
+        //when connected to a server, the chunks are streamed by the server instead of generated locally
         let seed = rand::thread_rng().gen();
-        let mut generator = Generator::new("crates/gen/build/libs/generator-1.0.0.jar", seed)?;
+        let chunk_generation = config.server_address.is_none().then(|| {
+            //the jar of the Java generator is loaded once in the JVM, each worker only creates its own generator
+            let worker_count =
+                std::thread::available_parallelism().map_or(1, |count| (count.get() - 1).max(1));
+            let generator_path = config.generator_path.clone();
+            let queue = ChunkGenerationQueue::new(worker_count, move || {
+                gen::load_generator(Some(&generator_path), seed)
+            });
+            queue.request(Self::cube_positions());
+            queue
+        });
+
+        let (atlas_builder, texture_layers) = TextureAtlasBuilder::from_dir(&config.textures_path)?;
+        let mut block_registry = BlockRegistry::default();
+        block_registry.assign_textures(&texture_layers);
+        let terrain_renderer = graphic::terrain::TerrainRenderer::new(
+            &camera,
+            16,
+            config.mesh_cache_margin,
+            &chunk_manager,
+            &block_registry,
+            atlas_builder,
+            window.get_sample_count(),
+            &graphic_context,
+        );
 
-        Self::regenerate_cube(&mut chunk_manager, &mut generator);
+        let crosshair_renderer =
+            graphic::crosshair::CrosshairRenderer::new(&window, &graphic_context);
+        let sky_renderer = graphic::sky::SkyRenderer::new(&window, &graphic_context);
 
-        let terrain_renderer =
-            graphic::terrain::TerrainRenderer::new(&camera, 16, &chunk_manager, &graphic_context);
+        let (client_network_handler, connection_status) = match config.server_address {
+            Some(server_address) => (
+                Some(ClientNetworkHandler::new(server_address, &config)?),
+                format!("connecting to {}", server_address),
+            ),
+            None => (None, "offline".to_string()),
+        };
 
         Ok((
             Self {
                 window,
                 graphic_context,
-                client_network_handler: None,
+                client_network_handler,
                 last_update: Instant::now(),
                 gui_handler,
                 camera,
                 terrain_renderer,
+                crosshair_renderer,
+                sky_renderer,
+                time_of_day: TimeOfDay::new(config.day_length),
                 camera_controller: CameraController::new(),
+                movement_prediction: MovementPrediction::new(),
                 chunk_manager,
+                block_registry,
+                chunk_generation,
                 seed,
+                connection_status,
             },
             event_loop,
         ))
@@ -298,7 +446,7 @@ impl App {
                 });
             }
             WindowEvent::Resized(size) => {
-                self.window.resize(size, &self.graphic_context);
+                self.window.resize(size);
             }
             _ => (),
         }
@@ -312,13 +460,92 @@ impl App {
     }
 
     fn tick(&mut self, delta_time: Duration) -> anyhow::Result<()> {
-        if self.client_network_handler.is_some() {
-            self.client_network_handler
-                .as_mut()
-                .unwrap()
-                .tick(delta_time)?;
+        self.time_of_day.advance(delta_time);
+
+        if let Some(client_network_handler) = &mut self.client_network_handler {
+            client_network_handler.send_player_move(
+                self.movement_prediction.last_sequence(),
+                self.camera.position,
+                self.camera.yaw,
+                self.camera.pitch,
+            );
+            //a network error must not close the window, only the fatal ones are propagated
+            match client_network_handler.tick(delta_time) {
+                Ok(()) => (),
+                Err(NetworkError::Recoverable(e)) => println!("network error: {}", e),
+                Err(e) => return Err(e.into()),
+            }
+
+            for event in client_network_handler.poll_events() {
+                self.connection_status = match event {
+                    NetEvent::Connected => "connected".to_string(),
+                    NetEvent::Reconnecting => "reconnecting".to_string(),
+                    NetEvent::Disconnected {
+                        reason: Some(reason),
+                    } => format!("disconnected: {}", reason),
+                    NetEvent::Disconnected { reason: None } => "disconnected".to_string(),
+                };
+                println!("{}", self.connection_status);
+            }
+
+            if let Some((sequence, server_pos)) = client_network_handler.take_acknowledged_move() {
+                if let Some(corrected_pos) =
+                    self.movement_prediction
+                        .reconcile(sequence, server_pos, self.camera.position)
+                {
+                    self.camera.position = corrected_pos;
+                }
+            }
+
+            let received_chunks = client_network_handler.take_received_chunks();
+            let mut positions = received_chunks
+                .iter()
+                .map(|chunk| chunk.position())
+                .collect::<Vec<_>>();
+            for chunk in received_chunks {
+                if let Err(e) = self.chunk_manager.insert_chunk(chunk) {
+                    println!("invalid chunk from the server: {}", e);
+                }
+            }
+            //the server only sends the edits of the chunks the client has
+            for (pos, delta) in client_network_handler.take_block_edits() {
+                if let Some(mut chunk) = self.chunk_manager.get_chunk_mut(pos) {
+                    chunk.apply_delta(&delta);
+                    positions.push(pos);
+                }
+            }
+            self.terrain_renderer.update_chunks(
+                &self.chunk_manager,
+                &self.block_registry,
+                &positions,
+            );
+        }
+
+        if let Some(chunk_generation) = &self.chunk_generation {
+            chunk_generation.set_center(self.camera.position.chunk_pos);
+            let generated_chunks = chunk_generation.take_generated();
+            if !generated_chunks.is_empty() {
+                let positions = generated_chunks
+                    .iter()
+                    .map(|chunk| chunk.position())
+                    .collect::<Vec<_>>();
+                self.chunk_manager.insert_chunks(generated_chunks)?;
+                self.terrain_renderer.update_chunks(
+                    &self.chunk_manager,
+                    &self.block_registry,
+                    &positions,
+                );
+            }
         }
 
+        //the blocks are drawn between z - 1 and z (see the mesher), so the ray is moved to match what is displayed
+        let eye = self.camera.position + Vec3::Z;
+        let targeted_block = self
+            .chunk_manager
+            .raycast(eye, self.camera.get_forward(), Self::REACH)
+            .and_then(|hit| self.block_registry.get(hit.state))
+            .map(|block| block.name.clone());
+
         let mut gui_data = GUIData {
             second_per_frame: delta_time.as_secs_f32(),
             regenerate: false,
@@ -326,18 +553,54 @@ impl App {
             yaw: self.camera.yaw,
             pitch: self.camera.pitch,
             rendered_mesh_count: self.terrain_renderer.rendered_mesh_count(),
+            draw_call_count: self.terrain_renderer.draw_call_count(),
+            built_mesh_count: self.terrain_renderer.built_mesh_count(),
+            cached_mesh_count: self.terrain_renderer.cached_mesh_count(),
+            chunk_manager_stats: self.chunk_manager.debug_stats(),
             world_seed: self.seed,
+            pending_chunk_count: self
+                .chunk_generation
+                .as_ref()
+                .map_or(0, |chunk_generation| chunk_generation.pending_count()),
+            connection_status: self.connection_status.clone(),
+            latency_ms: self
+                .client_network_handler
+                .as_ref()
+                .and_then(|handler| handler.latency_ms()),
+            targeted_block,
+            show_hud: self.crosshair_renderer.visible,
+            sky_zenith_color: self.sky_renderer.zenith_color,
+            sky_horizon_color: self.sky_renderer.horizon_color,
+            time_hours: self.time_of_day.get_time() * 24.0,
+            day_length_secs: self.time_of_day.day_length.as_secs_f32(),
+            speed: self.camera_controller.speed,
+            movement_mode: self.camera_controller.movement_mode,
         };
 
-        self.camera_controller
+        let displacement = self
+            .camera_controller
             .update_camera(&mut self.camera, delta_time);
+        self.movement_prediction.record(displacement);
         self.gui_handler
             .update_gui(&self.window, &self.graphic_context, &mut gui_data);
+        self.crosshair_renderer.visible = gui_data.show_hud;
+        self.sky_renderer.zenith_color = gui_data.sky_zenith_color;
+        self.sky_renderer.horizon_color = gui_data.sky_horizon_color;
+        self.time_of_day.set_time(gui_data.time_hours / 24.0);
+        self.time_of_day.day_length = Duration::from_secs_f32(gui_data.day_length_secs);
+        self.camera_controller.speed = gui_data.speed;
+        self.camera_controller.movement_mode = gui_data.movement_mode;
 
         if gui_data.regenerate {
-            //Self::regenerate_cube(&mut self.chunk_manager); //todo: move this to a better place
+            if let Some(chunk_generation) = &self.chunk_generation {
+                chunk_generation.request(Self::cube_positions());
+            }
         }
 
+        if let Some(size) = self.window.apply_pending_resize(&self.graphic_context) {
+            self.crosshair_renderer
+                .resize(size.width, size.height, &self.graphic_context);
+        }
         if self.window.should_be_rendered() {
             self.redraw()?;
         }
@@ -346,16 +609,34 @@ impl App {
 
     fn redraw(&mut self) -> anyhow::Result<()> {
         self.camera.update(&self.graphic_context);
-        let renderer = FrameRenderer::new(&self.window, &self.graphic_context)?;
+        self.terrain_renderer
+            .update_light(&self.time_of_day.light(), &self.graphic_context);
+        self.sky_renderer.brightness = self.time_of_day.sky_brightness();
+        self.sky_renderer
+            .update_uniform(&self.camera, &self.graphic_context);
+        let clear_color = self.sky_renderer.get_clear_color();
+        let renderer = match FrameRenderer::new(&self.window, &self.graphic_context) {
+            Ok(renderer) => renderer,
+            //the surface changed (minimized, resized...), the frame is skipped and the next one uses the new surface
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.window.reconfigure(&self.graphic_context);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
         let render_jobs = (
+            &mut self.sky_renderer,
             self.terrain_renderer.build_render_job(
                 &mut self.chunk_manager,
+                &self.block_registry,
                 &self.camera,
                 &self.graphic_context,
             ),
+            &mut self.crosshair_renderer,
             &mut self.gui_handler,
         );
-        renderer.render(render_jobs);
+        renderer.render(clear_color, render_jobs);
         Ok(())
     }
 }
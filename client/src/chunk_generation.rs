@@ -0,0 +1,129 @@
+use gen::WorldGenerator;
+use math::positions::ChunkPos;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use world_core::Chunk;
+
+struct PendingChunks {
+    ///sorted from the farthest to the closest to the center, so the closest is popped first
+    positions: Vec<ChunkPos>,
+    center: ChunkPos,
+    stopped: bool,
+}
+
+impl PendingChunks {
+    fn sort(&mut self) {
+        let center = self.center;
+        self.positions
+            .sort_by_key(|pos| std::cmp::Reverse((*pos - center).length_squared()));
+    }
+}
+
+struct SharedQueue {
+    pending: Mutex<PendingChunks>,
+    new_chunks: Condvar,
+}
+
+///generate the chunks on worker threads, the closest chunks to the center are generated first
+///the generated chunks are taken by the main thread each frame, so the window isn't frozen during the generation
+pub struct ChunkGenerationQueue {
+    queue: Arc<SharedQueue>,
+    generated_chunks: Receiver<Chunk>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ChunkGenerationQueue {
+    ///each worker calls the factory once, on its own thread, to create its generator
+    pub fn new(
+        worker_count: usize,
+        generator_factory: impl Fn() -> Box<dyn WorldGenerator> + Send + Sync + 'static,
+    ) -> Self {
+        let queue = Arc::new(SharedQueue {
+            pending: Mutex::new(PendingChunks {
+                positions: Vec::new(),
+                center: ChunkPos::ZERO,
+                stopped: false,
+            }),
+            new_chunks: Condvar::new(),
+        });
+        let (sender, generated_chunks) = mpsc::channel();
+        let generator_factory = Arc::new(generator_factory);
+
+        let workers = (0..worker_count.max(1))
+            .map(|_| {
+                let queue = queue.clone();
+                let sender = sender.clone();
+                let generator_factory = generator_factory.clone();
+                std::thread::spawn(move || {
+                    let mut generator = generator_factory();
+                    Self::work(&queue, generator.as_mut(), &sender);
+                })
+            })
+            .collect();
+
+        Self {
+            queue,
+            generated_chunks,
+            workers,
+        }
+    }
+
+    fn work(queue: &SharedQueue, generator: &mut dyn WorldGenerator, sender: &Sender<Chunk>) {
+        loop {
+            let pos = {
+                let mut pending = queue.pending.lock().unwrap();
+                loop {
+                    if pending.stopped {
+                        return;
+                    }
+                    if let Some(pos) = pending.positions.pop() {
+                        break pos;
+                    }
+                    pending = queue.new_chunks.wait(pending).unwrap();
+                }
+            };
+            if sender.send(gen::generate_chunk(generator, pos)).is_err() {
+                return; //the queue is dropped
+            }
+        }
+    }
+
+    ///add chunks to generate, a chunk already waiting is generated twice
+    pub fn request(&self, positions: impl IntoIterator<Item = ChunkPos>) {
+        let mut pending = self.queue.pending.lock().unwrap();
+        pending.positions.extend(positions);
+        pending.sort();
+        self.queue.new_chunks.notify_all();
+    }
+
+    ///the chunks closest to the center are generated first, usually the chunk of the camera
+    pub fn set_center(&self, center: ChunkPos) {
+        let mut pending = self.queue.pending.lock().unwrap();
+        if pending.center != center {
+            pending.center = center;
+            pending.sort();
+        }
+    }
+
+    ///the number of chunks not generated yet, the chunks being generated aren't counted
+    pub fn pending_count(&self) -> usize {
+        self.queue.pending.lock().unwrap().positions.len()
+    }
+
+    ///the chunks generated since the last call, without waiting for the others
+    pub fn take_generated(&self) -> Vec<Chunk> {
+        self.generated_chunks.try_iter().collect()
+    }
+}
+
+impl Drop for ChunkGenerationQueue {
+    ///the workers finish their current chunk, the pending ones are dropped
+    fn drop(&mut self) {
+        self.queue.pending.lock().unwrap().stopped = true;
+        self.queue.new_chunks.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use networking::packets::PROTOCOL_ID;
+use networking::socket::DEFAULT_SOCKET_BUFFER_SIZE;
+use rand::Rng;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use utils::config_file::ConfigFile;
+
+///how the client authenticates itself to the server, must match the mode of the server
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthenticationMode {
+    ///anyone knowing the protocol id can connect, only for local testing
+    Unsecure,
+    ///present the connect token stored in the given file, the token is generated by the server with its private key
+    Secure { connect_token: PathBuf },
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    ///the server to connect to, the client stays offline if there is none
+    pub server_address: Option<SocketAddr>,
+    ///the id of this client on the server, random by default
+    pub client_id: u64,
+    pub protocol_id: u64,
+    pub authentication: AuthenticationMode,
+    ///the MSAA sample count, 1 disable the anti-aliasing
+    pub msaa_samples: u32,
+    ///the jar of the Java world generator, the native generator is used if it can't be loaded
+    pub generator_path: PathBuf,
+    ///the directory of the block textures, each block uses the png with its name
+    pub textures_path: PathBuf,
+    ///the real duration of a day and night cycle
+    pub day_length: Duration,
+    ///in chunks, the meshes of the chunks out of the view are kept while they are within the render distance plus this margin
+    pub mesh_cache_margin: i32,
+    ///the sizes of the receive and send buffers of the socket in bytes, the OS may cap them
+    pub socket_recv_buffer_size: usize,
+    pub socket_send_buffer_size: usize,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            server_address: None,
+            client_id: rand::thread_rng().gen_range(0..u64::MAX),
+            protocol_id: PROTOCOL_ID,
+            authentication: AuthenticationMode::Unsecure,
+            msaa_samples: 1,
+            generator_path: PathBuf::from("crates/gen/build/libs/generator-1.0.0.jar"),
+            textures_path: PathBuf::from("client/assets/textures"),
+            day_length: Duration::from_secs(600),
+            mesh_cache_margin: 4,
+            socket_recv_buffer_size: DEFAULT_SOCKET_BUFFER_SIZE,
+            socket_send_buffer_size: DEFAULT_SOCKET_BUFFER_SIZE,
+        }
+    }
+}
+
+impl ClientConfig {
+    ///load the config from a `key = value` file, missing keys (or a missing file) keep their default value
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = ConfigFile::load(path)?;
+        let default = Self::default();
+
+        let authentication = match file.get::<String>("authentication")?.as_deref() {
+            None | Some("unsecure") => AuthenticationMode::Unsecure,
+            Some("secure") => {
+                let Some(connect_token) = file.get::<PathBuf>("connect_token")? else {
+                    anyhow::bail!("the secure authentication mode requires a connect_token file");
+                };
+                AuthenticationMode::Secure { connect_token }
+            }
+            Some(mode) => anyhow::bail!("unknown authentication mode: {}", mode),
+        };
+
+        let msaa_samples = file.get_or("msaa_samples", default.msaa_samples)?;
+        if !matches!(msaa_samples, 1 | 4) {
+            anyhow::bail!("msaa_samples must be 1 or 4, not {}", msaa_samples);
+        }
+
+        //in seconds in the file
+        let day_length = file.get_or("day_length", default.day_length.as_secs_f32())?;
+        if !(day_length > 0.0 && day_length.is_finite()) {
+            anyhow::bail!(
+                "day_length must be a positive number of seconds, not {}",
+                day_length
+            );
+        }
+
+        let mesh_cache_margin = file.get_or("mesh_cache_margin", default.mesh_cache_margin)?;
+        if mesh_cache_margin < 0 {
+            anyhow::bail!(
+                "mesh_cache_margin can't be negative, not {}",
+                mesh_cache_margin
+            );
+        }
+
+        let socket_recv_buffer_size =
+            file.get_or("socket_recv_buffer_size", default.socket_recv_buffer_size)?;
+        let socket_send_buffer_size =
+            file.get_or("socket_send_buffer_size", default.socket_send_buffer_size)?;
+        if socket_recv_buffer_size == 0 || socket_send_buffer_size == 0 {
+            anyhow::bail!("the socket buffer sizes must be at least 1 byte");
+        }
+
+        Ok(Self {
+            server_address: file.get("server_address")?,
+            client_id: file.get_or("client_id", default.client_id)?,
+            protocol_id: file.get_or("protocol_id", default.protocol_id)?,
+            authentication,
+            msaa_samples,
+            generator_path: file.get_or("generator_path", default.generator_path)?,
+            textures_path: file.get_or("textures_path", default.textures_path)?,
+            day_length: Duration::from_secs_f32(day_length),
+            mesh_cache_margin,
+            socket_recv_buffer_size,
+            socket_send_buffer_size,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use super::{Context, RenderJob, Window};
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CrosshairVertex {
+    position: [f32; 2],
+}
+
+impl CrosshairVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+    ];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<CrosshairVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+///draw a cross at the center of the screen, the colors behind it are inverted to keep it visible
+///it should be drawn after the terrain and before the GUI
+pub struct CrosshairRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    pub visible: bool,
+}
+
+impl CrosshairRenderer {
+    //in pixels, so the crosshair keeps the same size whatever the resolution
+    const HALF_LENGTH: f32 = 10.0;
+    const HALF_THICKNESS: f32 = 1.0;
+    //3 quads, the vertical bar is split in two to not overlap the horizontal one, the overlap would be inverted twice
+    const VERTEX_COUNT: u32 = 18;
+
+    pub fn new(window: &Window, context: &Context) -> Self {
+        let shader = context
+            .wgpu_device
+            .create_shader_module(wgpu::include_wgsl!("crosshair.wgsl"));
+        let render_pipeline_layout =
+            context
+                .wgpu_device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Crosshair Render Pipeline Layout"),
+                    bind_group_layouts: &[],
+                    push_constant_ranges: &[],
+                });
+
+        let render_pipeline =
+            context
+                .wgpu_device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Crosshair Render Pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[CrosshairVertex::desc()],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: window.get_surface_config().format,
+                            blend: Some(wgpu::BlendState {
+                                //1 - destination color
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::OneMinusDst,
+                                    dst_factor: wgpu::BlendFactor::Zero,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent::OVER,
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: None,
+                        ..Default::default()
+                    },
+                    //the render pass has a depth buffer, but the crosshair is always on top
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: Window::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: window.get_sample_count(),
+                        ..Default::default()
+                    },
+                    multiview: None,
+                });
+
+        let surface_config = window.get_surface_config();
+        let vertices = Self::get_vertices(surface_config.width, surface_config.height);
+        let vertex_buffer =
+            context
+                .wgpu_device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Crosshair Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+
+        Self {
+            render_pipeline,
+            vertex_buffer,
+            visible: true,
+        }
+    }
+
+    ///the quads in clip space for a surface of the given size
+    fn get_vertices(width: u32, height: u32) -> [CrosshairVertex; Self::VERTEX_COUNT as usize] {
+        let to_clip_x = |pixels: f32| pixels * 2.0 / width.max(1) as f32;
+        let to_clip_y = |pixels: f32| pixels * 2.0 / height.max(1) as f32;
+        let quad = |x1: f32, y1: f32, x2: f32, y2: f32| {
+            let (x1, x2) = (to_clip_x(x1), to_clip_x(x2));
+            let (y1, y2) = (to_clip_y(y1), to_clip_y(y2));
+            [[x1, y1], [x2, y1], [x2, y2], [x1, y1], [x2, y2], [x1, y2]]
+        };
+
+        let (length, thickness) = (Self::HALF_LENGTH, Self::HALF_THICKNESS);
+        let quads = [
+            quad(-length, -thickness, length, thickness),
+            quad(-thickness, thickness, thickness, length),
+            quad(-thickness, -length, thickness, -thickness),
+        ];
+        let mut vertices = [CrosshairVertex {
+            position: [0.0, 0.0],
+        }; Self::VERTEX_COUNT as usize];
+        for (vertex, position) in vertices.iter_mut().zip(quads.iter().flatten()) {
+            vertex.position = *position;
+        }
+        vertices
+    }
+
+    ///to call when the window is resized, to keep the size of the crosshair in pixels
+    pub fn resize(&self, width: u32, height: u32, context: &Context) {
+        let vertices = Self::get_vertices(width, height);
+        context
+            .wgpu_queue
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+}
+
+impl RenderJob for CrosshairRenderer {
+    fn update(&mut self, _command_encoder: &mut wgpu::CommandEncoder, _render_context: &Context) {
+        //the vertices only change when the window is resized
+    }
+
+    fn draw<'pass>(&'pass mut self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        if !self.visible {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..Self::VERTEX_COUNT, 0..1);
+    }
+}
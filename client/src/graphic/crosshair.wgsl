@@ -0,0 +1,17 @@
+
+struct VertexInput {
+    @location(0) position: vec2<f32>, //already in clip space
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(model.position, 0.0, 1.0);
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    //white, the blending invert the color behind the crosshair so it is visible on any block
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
@@ -1,4 +1,6 @@
 pub mod camera;
+pub mod crosshair;
+pub mod sky;
 pub mod terrain;
 pub mod ui;
 
@@ -32,34 +34,111 @@ impl Context {
             wgpu_queue: queue,
         })
     }
+
+    ///1 is always supported, 4 is supported by most of the formats
+    pub fn supports_sample_count(&self, format: wgpu::TextureFormat, sample_count: u32) -> bool {
+        self.wgpu_adapter
+            .get_texture_format_features(format)
+            .flags
+            .sample_count_supported(sample_count)
+    }
+}
+
+///the textures the frame is rendered to before being presented, they must be the same size as the swapchain
+///with MSAA the frame is rendered to a multisampled color target, then resolved into the swapchain texture
+struct RenderTargets {
+    depth_buffer: wgpu::Texture,
+    ///None when there is only one sample, the frame is rendered directly to the swapchain texture
+    msaa_color_buffer: Option<wgpu::Texture>,
+}
+
+impl RenderTargets {
+    fn new(
+        surface_config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        context: &Context,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: surface_config.width,
+            height: surface_config.height,
+            depth_or_array_layers: 1,
+        };
+        let create_target = |label, format, usage| {
+            context
+                .wgpu_device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size,
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                })
+        };
+
+        let depth_usage = if sample_count == 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT //a multisampled depth buffer can't be sampled as a regular texture
+        };
+        let depth_buffer = create_target("Depth Buffer", Window::DEPTH_FORMAT, depth_usage);
+        let msaa_color_buffer = (sample_count > 1).then(|| {
+            create_target(
+                "MSAA Color Buffer",
+                surface_config.format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+        });
+        Self {
+            depth_buffer,
+            msaa_color_buffer,
+        }
+    }
 }
 
-//for now, the depth buffer is in the swapchain object, since it need to be the same size as the swapchain
-//this might change in the future...
 pub struct Window {
     window: winit::window::Window,
     surface: wgpu::Surface,
     surface_config: wgpu::SurfaceConfiguration,
-    depth_buffer: wgpu::Texture,
+    ///the number of samples per pixel, the pipelines drawing in the frame must use the same count
+    sample_count: u32,
+    render_targets: RenderTargets,
+    ///the last size given by the resize events, applied once per frame, see [`Window::apply_pending_resize`]
+    pending_size: Option<winit::dpi::PhysicalSize<u32>>,
 }
 
 impl Window {
+    ///sample_count is the MSAA sample count, 1 disable the anti-aliasing
     pub fn new(
         window: winit::window::Window,
         wgpu_instance: wgpu::Instance,
+        sample_count: u32,
     ) -> anyhow::Result<(Self, Context)> {
         let surface = unsafe { wgpu_instance.create_surface(&window)? };
 
         let context = pollster::block_on(Context::new(&surface, wgpu_instance))?;
         let window_size = window.inner_size();
         let surface_config = Self::get_surface_configuration(&surface, window_size, &context);
-        let depth_buffer = Self::get_depth_buffer(window_size, &context);
+        for format in [surface_config.format, Self::DEPTH_FORMAT] {
+            if !context.supports_sample_count(format, sample_count) {
+                anyhow::bail!(
+                    "{} samples are not supported for {:?}",
+                    sample_count,
+                    format
+                );
+            }
+        }
+        let render_targets = RenderTargets::new(&surface_config, sample_count, &context);
 
         let window = Self {
             window,
             surface,
             surface_config,
-            depth_buffer,
+            sample_count,
+            render_targets,
+            pending_size: None,
         };
         Ok((window, context))
     }
@@ -93,26 +172,6 @@ impl Window {
     }
 
     const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
-    fn get_depth_buffer(size: winit::dpi::PhysicalSize<u32>, context: &Context) -> wgpu::Texture {
-        let size = wgpu::Extent3d {
-            width: size.width,
-            height: size.height,
-            depth_or_array_layers: 1,
-        };
-
-        let desc = wgpu::TextureDescriptor {
-            label: Some("Depth Buffer"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        };
-        let texture = context.wgpu_device.create_texture(&desc);
-        texture
-    }
 
     pub fn as_winit_window(&self) -> &winit::window::Window {
         &self.window
@@ -120,17 +179,48 @@ impl Window {
     pub fn get_surface_config(&self) -> &wgpu::SurfaceConfiguration {
         &self.surface_config
     }
+    pub fn get_sample_count(&self) -> u32 {
+        self.sample_count
+    }
 
-    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>, render_context: &Context) {
+    ///store the new size of the window, the surface and the depth buffer are only recreated on the next frame
+    ///a drag resize sends many events per frame, only the last one is applied
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        self.pending_size = Some(size);
+    }
+
+    ///apply the size of the last resize event, return it if the surface was configured again
+    ///nothing is recreated if the size is the one already configured
+    pub fn apply_pending_resize(
+        &mut self,
+        render_context: &Context,
+    ) -> Option<winit::dpi::PhysicalSize<u32>> {
+        let size = self.pending_size.take()?;
+        if size.width == self.surface_config.width && size.height == self.surface_config.height {
+            return None;
+        }
+        self.configure(size, render_context);
+        Some(size)
+    }
+
+    fn configure(&mut self, size: winit::dpi::PhysicalSize<u32>, render_context: &Context) {
         self.surface_config.width = size.width;
         self.surface_config.height = size.height;
         if size.width > 0 && size.height > 0 {
             self.surface
                 .configure(&render_context.wgpu_device, &self.surface_config);
-            self.depth_buffer = Self::get_depth_buffer(size, render_context);
+            self.render_targets =
+                RenderTargets::new(&self.surface_config, self.sample_count, render_context);
         }
     }
 
+    ///configure the surface again with the current size of the window, when it is lost or outdated
+    pub fn reconfigure(&mut self, render_context: &Context) {
+        let size = self.window.inner_size();
+        self.pending_size = None;
+        self.configure(size, render_context);
+    }
+
     pub fn should_be_rendered(&self) -> bool {
         self.surface_config.width > 0 && self.surface_config.height > 0
     }
@@ -185,6 +275,7 @@ pub struct FrameRenderer<'a> {
     context: &'a Context,
     surface_texture: wgpu::SurfaceTexture,
     output_view: wgpu::TextureView,
+    msaa_color_view: Option<wgpu::TextureView>,
     depth_buffer: wgpu::TextureView,
 }
 
@@ -194,13 +285,19 @@ impl<'a> FrameRenderer<'a> {
         context: &'a Context,
     ) -> Result<FrameRenderer<'a>, wgpu::SurfaceError> {
         let (surface_texture, output_view) = Self::get_surface_texture(&window.surface)?;
-        let depth_buffer = window
+        let render_targets = &window.render_targets;
+        let msaa_color_view = render_targets
+            .msaa_color_buffer
+            .as_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let depth_buffer = render_targets
             .depth_buffer
             .create_view(&wgpu::TextureViewDescriptor::default());
         Ok(Self {
             context,
             surface_texture,
             output_view,
+            msaa_color_view,
             depth_buffer,
         })
     }
@@ -223,7 +320,8 @@ impl<'a> FrameRenderer<'a> {
         Ok((surface_texture, output_view))
     }
 
-    pub fn render<T>(self, job_list: T)
+    ///clear the frame with the given color, then draw the jobs in the order of the tuple
+    pub fn render<T>(self, clear_color: wgpu::Color, job_list: T)
     where
         T: Tuple,
         <T as Tuple>::TupleList: RenderJob,
@@ -233,19 +331,23 @@ impl<'a> FrameRenderer<'a> {
         let mut command_encoder = Self::get_command_encoder(&self);
         tuple_list.update(&mut command_encoder, &self.context);
 
+        //with MSAA, the samples are resolved into the swapchain texture at the end of the pass and can be discarded
+        let (view, resolve_target, store) = match &self.msaa_color_view {
+            Some(msaa_color_view) => (
+                msaa_color_view,
+                Some(&self.output_view),
+                wgpu::StoreOp::Discard,
+            ),
+            None => (&self.output_view, None, wgpu::StoreOp::Store),
+        };
         let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &self.output_view,
-                resolve_target: None,
+                view,
+                resolve_target,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
-                    store: wgpu::StoreOp::Store,
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store,
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
@@ -0,0 +1,162 @@
+use super::camera::Camera;
+use super::{Context, RenderJob, Window};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inverse_rotation_proj: [[f32; 4]; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+}
+
+///draw a vertical gradient from the horizon color to the zenith color behind everything
+///it must be the first render job, the other ones are drawn on top of it
+pub struct SkyRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    ///the colors are in linear space
+    pub zenith_color: [f32; 3],
+    pub horizon_color: [f32; 3],
+    ///the factor applied to both colors, to darken the sky at night
+    pub brightness: f32,
+}
+
+impl SkyRenderer {
+    pub fn new(window: &Window, context: &Context) -> Self {
+        let uniform_buffer = context.wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sky Buffer"),
+            size: std::mem::size_of::<SkyUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout =
+            context
+                .wgpu_device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    }],
+                    label: Some("Sky Bind Group Layout"),
+                });
+
+        let bind_group = context
+            .wgpu_device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }],
+                label: Some("Sky Bind Group"),
+            });
+
+        let shader = context
+            .wgpu_device
+            .create_shader_module(wgpu::include_wgsl!("sky.wgsl"));
+        let render_pipeline_layout =
+            context
+                .wgpu_device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Sky Render Pipeline Layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let render_pipeline =
+            context
+                .wgpu_device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Sky Render Pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: window.get_surface_config().format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: None,
+                        ..Default::default()
+                    },
+                    //the sky is infinitely far, it doesn't write the depth so everything is drawn on top of it
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: Window::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: window.get_sample_count(),
+                        ..Default::default()
+                    },
+                    multiview: None,
+                });
+
+        Self {
+            render_pipeline,
+            uniform_buffer,
+            bind_group,
+            zenith_color: [0.1, 0.2, 0.3],
+            horizon_color: [0.5, 0.6, 0.7],
+            brightness: 1.0,
+        }
+    }
+
+    ///the color the frame is cleared with, visible where nothing is drawn
+    pub fn get_clear_color(&self) -> wgpu::Color {
+        let [r, g, b] = self
+            .horizon_color
+            .map(|channel| (channel * self.brightness) as f64);
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+
+    ///upload the colors and the orientation of the camera, to call before each frame
+    pub fn update_uniform(&self, camera: &Camera, context: &Context) {
+        let [r, g, b] = self.zenith_color.map(|channel| channel * self.brightness);
+        let zenith_color = [r, g, b, 1.0];
+        let [r, g, b] = self.horizon_color.map(|channel| channel * self.brightness);
+        let horizon_color = [r, g, b, 1.0];
+        let uniform = SkyUniform {
+            inverse_rotation_proj: camera
+                .get_rotation_proj_matrix()
+                .inverse()
+                .to_cols_array_2d(),
+            zenith_color,
+            horizon_color,
+        };
+        context
+            .wgpu_queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+impl RenderJob for SkyRenderer {
+    fn update(&mut self, _command_encoder: &mut wgpu::CommandEncoder, _render_context: &Context) {
+        //the uniform is updated with the camera, see SkyRenderer::update_uniform
+    }
+
+    fn draw<'pass>(&'pass mut self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,37 @@
+
+struct SkyUniform {
+    inverse_rotation_proj: mat4x4<f32>,
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> sky: SkyUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+//a single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let x = f32(i32(vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(vertex_index >> 1u) * 4 - 1);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    //any point of the pixel ray in front of the camera gives the view direction, the camera being at the origin
+    let point = sky.inverse_rotation_proj * vec4<f32>(in.ndc, 0.5, 1.0);
+    let direction = normalize(point.xyz / point.w);
+    //below the horizon, the sky keeps the horizon color
+    let height = clamp(direction.y, 0.0, 1.0);
+    return mix(sky.horizon_color, sky.zenith_color, sqrt(height));
+}
@@ -0,0 +1,218 @@
+use crate::graphic::terrain::chunk_mesh::ChunkMesh;
+use crate::graphic::terrain::ordered_chunk_pos::OrderedChunkPos;
+use crate::graphic::terrain::{ChunkPosAttribute, Vertex};
+use math::consts::CHUNK_SIZE;
+use math::positions::ChunkPos;
+use math::IVec3;
+use std::collections::{BTreeMap, BTreeSet};
+use wgpu::util::DeviceExt;
+
+///the side of a batch in chunks, a batch of 4^3 chunks is drawn with a single draw call
+const BATCH_SIDE: i32 = 4;
+
+///the meshes of the chunks of a batch merged in the same buffers
+struct MeshBatch {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+///store the chunk meshes and merge the ones close to each other, so the CPU submits one draw call per batch instead of one per chunk
+///the GPU buffers of a batch are rebuilt in [`BatchedMeshes::update_batches`] when one of its meshes changes
+pub struct BatchedMeshes {
+    meshes: BTreeMap<OrderedChunkPos, ChunkMesh>,
+    batches: BTreeMap<OrderedChunkPos, MeshBatch>,
+    dirty_batches: BTreeSet<OrderedChunkPos>,
+    ///the origin of each batch, in the order of the batches map, it's the instance attribute of the draw calls
+    pos_buffer: Option<wgpu::Buffer>,
+}
+
+impl BatchedMeshes {
+    pub fn new() -> Self {
+        Self {
+            meshes: BTreeMap::new(),
+            batches: BTreeMap::new(),
+            dirty_batches: BTreeSet::new(),
+            pos_buffer: None,
+        }
+    }
+
+    fn get_batch_pos(pos: ChunkPos) -> OrderedChunkPos {
+        OrderedChunkPos(pos.div_euclid(IVec3::splat(BATCH_SIDE)))
+    }
+
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    ///the number of draw calls needed to draw all the meshes
+    pub fn draw_call_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    ///insert or replace the mesh of a chunk
+    pub fn insert(&mut self, pos: ChunkPos, mesh: ChunkMesh) {
+        self.meshes.insert(OrderedChunkPos(pos), mesh);
+        self.dirty_batches.insert(Self::get_batch_pos(pos));
+    }
+
+    pub fn remove(&mut self, pos: ChunkPos) -> Option<ChunkMesh> {
+        let mesh = self.meshes.remove(&OrderedChunkPos(pos));
+        if mesh.is_some() {
+            self.dirty_batches.insert(Self::get_batch_pos(pos));
+        }
+        mesh
+    }
+
+    ///rebuild the buffers of the batches modified since the last call
+    ///a mesh inserted or removed, like when a chunk enters or leaves the frustum, merges and uploads its whole batch again,
+    ///up to 64 meshes, and the position buffer of all the batches is recreated
+    ///it's cheaper to draw than a buffer per chunk but costs more when the camera turns, updating the range of the chunk
+    ///in place would need free space in the batch buffers and a way to reuse the holes of the removed meshes
+    pub fn update_batches(&mut self, device: &wgpu::Device) {
+        if self.dirty_batches.is_empty() {
+            return;
+        }
+
+        for batch_pos in std::mem::take(&mut self.dirty_batches) {
+            match self.build_batch(batch_pos.0, device) {
+                Some(batch) => self.batches.insert(batch_pos, batch),
+                None => self.batches.remove(&batch_pos),
+            };
+        }
+
+        let positions = self
+            .batches
+            .keys()
+            .map(|batch_pos| {
+                let origin = batch_pos.0 * BATCH_SIDE;
+                ChunkPosAttribute {
+                    position: [origin.x, origin.y, origin.z],
+                }
+            })
+            .collect::<Vec<_>>();
+        self.pos_buffer = if positions.is_empty() {
+            None
+        } else {
+            Some(
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Batch Position Buffer"),
+                    contents: bytemuck::cast_slice(&positions),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }),
+            )
+        };
+    }
+
+    ///merge the meshes of a batch and upload them, None if the batch has no face
+    fn build_batch(&self, batch_pos: ChunkPos, device: &wgpu::Device) -> Option<MeshBatch> {
+        let (vertices, indices) = self.merge_batch(batch_pos)?;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Batch Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Batch Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Some(MeshBatch {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+        })
+    }
+
+    ///merge the meshes of a batch, the vertices are moved to be relative to the batch origin
+    fn merge_batch(&self, batch_pos: ChunkPos) -> Option<(Vec<Vertex>, Vec<u32>)> {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let origin = batch_pos * BATCH_SIDE;
+        for x in 0..BATCH_SIDE {
+            for y in 0..BATCH_SIDE {
+                for z in 0..BATCH_SIDE {
+                    let offset = IVec3::new(x, y, z);
+                    let Some(mesh) = self.meshes.get(&OrderedChunkPos(origin + offset)) else {
+                        continue;
+                    };
+                    let offset = (offset * CHUNK_SIZE).as_vec3();
+                    let first_index = vertices.len() as u32;
+                    vertices.extend(mesh.vertices().iter().map(|vertex| {
+                        let mut vertex = *vertex;
+                        vertex.position[0] += offset.x;
+                        vertex.position[1] += offset.y;
+                        vertex.position[2] += offset.z;
+                        vertex
+                    }));
+                    indices.extend(mesh.indices().iter().map(|index| index + first_index));
+                }
+            }
+        }
+
+        if indices.is_empty() {
+            return None;
+        }
+        Some((vertices, indices))
+    }
+
+    ///draw all the batches, the pipeline and the bind groups have to be set before
+    pub fn draw<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        let Some(pos_buffer) = &self.pos_buffer else {
+            return;
+        };
+        render_pass.set_vertex_buffer(1, pos_buffer.slice(..));
+        for (batch_index, batch) in self.batches.values().enumerate() {
+            let batch_index = batch_index as u32;
+            render_pass.set_vertex_buffer(0, batch.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(batch.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..batch.index_count, 0, batch_index..batch_index + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::graphic::terrain::batched_meshes::BatchedMeshes;
+    use crate::graphic::terrain::chunk_mesh::ChunkMesh;
+    use crate::graphic::terrain::texture_atlas::AtlasLayout;
+    use math::positions::ChunkPos;
+    use world_core::block_registry::{BlockInfo, BlockRegistry};
+    use world_core::{Chunk, ChunkManager};
+
+    #[test]
+    pub fn a_draw_call_per_batch_instead_of_per_chunk() {
+        //a layer of 16x4x16 chunks with a block each, every chunk has a mesh
+        let mut registry = BlockRegistry::new();
+        let stone = registry.register(BlockInfo::cube("stone", 0));
+        let mut chunk_manager = ChunkManager::new();
+        let positions = (0..16)
+            .flat_map(|x| (0..4).flat_map(move |y| (0..16).map(move |z| ChunkPos::new(x, y, z))))
+            .collect::<Vec<_>>();
+        for pos in &positions {
+            let mut chunk = Chunk::new(*pos);
+            chunk.set_block_at(3, 4, 5, stone);
+            chunk_manager.insert_chunk(chunk).unwrap();
+        }
+
+        let mut meshes = BatchedMeshes::new();
+        let mut index_count = 0;
+        for pos in &positions {
+            let mesh = ChunkMesh::build_from(&chunk_manager, *pos, &registry, AtlasLayout::Layers)
+                .unwrap();
+            index_count += mesh.indices().len();
+            meshes.insert(*pos, mesh);
+        }
+
+        //before the batching each mesh was drawn on its own
+        assert_eq!(meshes.mesh_count(), 1024);
+        let batches = meshes
+            .dirty_batches
+            .iter()
+            .filter_map(|batch_pos| meshes.merge_batch(batch_pos.0))
+            .collect::<Vec<_>>();
+        assert_eq!(batches.len(), 16);
+        let merged_index_count: usize = batches.iter().map(|(_, indices)| indices.len()).sum();
+        assert_eq!(merged_index_count, index_count);
+    }
+}
@@ -1,48 +1,36 @@
-use crate::graphic::terrain::texture_atlas::{TextureAtlas, TextureCoordinates};
+use crate::graphic::terrain::texture_atlas::{AtlasLayout, TextureCoordinates};
 use crate::graphic::terrain::Vertex;
-use crate::graphic::Context;
 use math::consts::CHUNK_SIZE;
 use math::positions::ChunkPos;
-use wgpu::util::DeviceExt;
+use world_core::block_registry::{BlockFace, BlockRegistry};
 use world_core::block_state::AIR;
 use world_core::ChunkManager;
 
+///the geometry of a chunk, it is uploaded to the GPU with the other meshes of its batch, see [`super::batched_meshes::BatchedMeshes`]
 pub struct ChunkMesh {
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    index_count: u32,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
 }
 
 impl ChunkMesh {
+    ///compute the faces of the chunk next to a transparent block, None if no face is visible
+    ///it doesn't touch the GPU, only the layout of the atlas is needed, so it can be built on any thread
     pub fn build_from(
         chunk_manager: &ChunkManager,
         pos: ChunkPos,
-        texture_atlas: &TextureAtlas,
-        context: &Context,
+        block_registry: &BlockRegistry,
+        atlas_layout: AtlasLayout,
     ) -> Option<Self> {
         let chunk = chunk_manager.get_chunk(pos)?;
         if chunk.is_empty() {
             return None;
         }
-        let top_chunk = chunk_manager.get_chunk(pos + ChunkPos::Y);
-        let bottom_chunk = chunk_manager.get_chunk(pos + ChunkPos::NEG_Y);
-        let west_chunk = chunk_manager.get_chunk(pos + ChunkPos::NEG_X);
-        let east_chunk = chunk_manager.get_chunk(pos + ChunkPos::X);
-        let north_chunk = chunk_manager.get_chunk(pos + ChunkPos::NEG_Z);
-        let south_chunk = chunk_manager.get_chunk(pos + ChunkPos::Z);
+        let [top_chunk, bottom_chunk, west_chunk, east_chunk, north_chunk, south_chunk] =
+            chunk_manager.neighbors(pos);
 
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
 
-        enum Face {
-            Top,
-            Bottom,
-            West,  //x-
-            East,  //X+
-            North, //z-
-            South, //z+
-        }
-
         let get_block_at = |x: i32, y: i32, z: i32| {
             if x >= 0 && x < CHUNK_SIZE && y >= 0 && y < CHUNK_SIZE && z >= 0 && z < CHUNK_SIZE {
                 return chunk.get_block_at(x, y, z);
@@ -71,27 +59,31 @@ impl ChunkMesh {
         //no clue why but if (0, 0, 0) is the first corner of the block in minecraft
         //then the second one is at (1, 1, -1), why the z is negative is beyond me
         let mut add_face =
-            |x, y, z, face: Face, texture: TextureCoordinates, texture_index: u32| match face {
-                Face::Top => {
+            |x, y, z, face: BlockFace, texture: TextureCoordinates, texture_index| match face {
+                BlockFace::Top => {
                     vertices.push(Vertex {
                         position: [x, y + 1.0, z - 1.0],
                         texture_coords: [texture.x1, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y + 1.0, z - 1.0],
                         texture_coords: [texture.x2, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y + 1.0, z],
                         texture_coords: [texture.x2, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y + 1.0, z],
                         texture_coords: [texture.x1, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 3);
@@ -101,26 +93,30 @@ impl ChunkMesh {
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 4);
                 }
-                Face::Bottom => {
+                BlockFace::Bottom => {
                     vertices.push(Vertex {
                         position: [x, y, z - 1.0],
                         texture_coords: [texture.x1, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y, z - 1.0],
                         texture_coords: [texture.x2, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y, z],
                         texture_coords: [texture.x2, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y, z],
                         texture_coords: [texture.x1, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     indices.push(vertices.len() as u32 - 4);
                     indices.push(vertices.len() as u32 - 3);
@@ -130,26 +126,30 @@ impl ChunkMesh {
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 1);
                 }
-                Face::West => {
+                BlockFace::West => {
                     vertices.push(Vertex {
                         position: [x, y, z - 1.0],
                         texture_coords: [texture.x2, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y + 1.0, z - 1.0],
                         texture_coords: [texture.x2, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y + 1.0, z],
                         texture_coords: [texture.x1, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y, z],
                         texture_coords: [texture.x1, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 3);
@@ -159,26 +159,30 @@ impl ChunkMesh {
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 4);
                 }
-                Face::East => {
+                BlockFace::East => {
                     vertices.push(Vertex {
                         position: [x + 1.0, y, z - 1.0],
                         texture_coords: [texture.x1, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y + 1.0, z - 1.0],
                         texture_coords: [texture.x1, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y + 1.0, z],
                         texture_coords: [texture.x2, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y, z],
                         texture_coords: [texture.x2, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     indices.push(vertices.len() as u32 - 4);
                     indices.push(vertices.len() as u32 - 3);
@@ -188,26 +192,30 @@ impl ChunkMesh {
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 1);
                 }
-                Face::North => {
+                BlockFace::North => {
                     vertices.push(Vertex {
                         position: [x, y, z - 1.0],
                         texture_coords: [texture.x1, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y, z - 1.0],
                         texture_coords: [texture.x2, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y + 1.0, z - 1.0],
                         texture_coords: [texture.x2, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y + 1.0, z - 1.0],
                         texture_coords: [texture.x1, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 3);
@@ -217,26 +225,30 @@ impl ChunkMesh {
                     indices.push(vertices.len() as u32 - 2);
                     indices.push(vertices.len() as u32 - 4);
                 }
-                Face::South => {
+                BlockFace::South => {
                     vertices.push(Vertex {
                         position: [x, y, z],
                         texture_coords: [texture.x2, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y, z],
                         texture_coords: [texture.x1, texture.y1],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x + 1.0, y + 1.0, z],
                         texture_coords: [texture.x1, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     vertices.push(Vertex {
                         position: [x, y + 1.0, z],
                         texture_coords: [texture.x2, texture.y2],
                         texture_index,
+                        face: face as u32,
                     });
                     indices.push(vertices.len() as u32 - 4);
                     indices.push(vertices.len() as u32 - 3);
@@ -248,37 +260,28 @@ impl ChunkMesh {
                 }
             };
 
-        for y in 0..CHUNK_SIZE {
-            for z in 0..CHUNK_SIZE {
-                for x in 0..CHUNK_SIZE {
-                    let blockstate = chunk.get_block_at(x, y, z);
-                    if blockstate == AIR {
-                        continue;
-                    }
-                    let blockstate = (blockstate - 1) as u32;
-
-                    let texture_coordinates = texture_atlas.get_texture_coordinates();
-                    let fx = x as f32;
-                    let fy = y as f32;
-                    let fz = z as f32;
-                    if get_block_at(x, y + 1, z) == AIR {
-                        add_face(fx, fy, fz, Face::Top, texture_coordinates, blockstate);
-                    }
-                    if get_block_at(x, y - 1, z) == AIR {
-                        add_face(fx, fy, fz, Face::Bottom, texture_coordinates, blockstate);
-                    }
-                    if get_block_at(x - 1, y, z) == AIR {
-                        add_face(fx, fy, fz, Face::West, texture_coordinates, blockstate);
-                    }
-                    if get_block_at(x + 1, y, z) == AIR {
-                        add_face(fx, fy, fz, Face::East, texture_coordinates, blockstate);
-                    }
-                    if get_block_at(x, y, z - 1) == AIR {
-                        add_face(fx, fy, fz, Face::North, texture_coordinates, blockstate);
-                    }
-                    if get_block_at(x, y, z + 1) == AIR {
-                        add_face(fx, fy, fz, Face::South, texture_coordinates, blockstate);
-                    }
+        //the air blocks are skipped by the iterator
+        for (pos, blockstate) in chunk.iter_blocks() {
+            let (x, y, z) = (pos.x, pos.y, pos.z);
+            let Some(block) = block_registry.get(blockstate) else {
+                continue; //unknown blocks are not rendered
+            };
+            let fx = x as f32;
+            let fy = y as f32;
+            let fz = z as f32;
+            let neighbours = [
+                (BlockFace::Top, get_block_at(x, y + 1, z)),
+                (BlockFace::Bottom, get_block_at(x, y - 1, z)),
+                (BlockFace::West, get_block_at(x - 1, y, z)),
+                (BlockFace::East, get_block_at(x + 1, y, z)),
+                (BlockFace::North, get_block_at(x, y, z - 1)),
+                (BlockFace::South, get_block_at(x, y, z + 1)),
+            ];
+            for (face, neighbour) in neighbours {
+                if block_registry.is_face_visible(blockstate, neighbour) {
+                    let texture_index = block.texture_index(face);
+                    let texture_coordinates = atlas_layout.texture_coordinates(texture_index);
+                    add_face(fx, fy, fz, face, texture_coordinates, texture_index);
                 }
             }
         }
@@ -287,32 +290,98 @@ impl ChunkMesh {
             return None;
         }
 
-        Some(Self::new(&context.wgpu_device, &vertices, &indices))
+        Some(Self { vertices, indices })
     }
 
-    fn new(device: &wgpu::Device, vertices: &[Vertex], indices: &[u32]) -> Self {
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let index_count = indices.len() as u32;
-        Self {
-            vertex_buffer,
-            index_buffer,
-            index_count,
+    ///the vertices positions are relative to the chunk origin
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::graphic::terrain::chunk_mesh::ChunkMesh;
+    use crate::graphic::terrain::texture_atlas::AtlasLayout;
+    use math::consts::CHUNK_SIZE;
+    use math::positions::ChunkPos;
+    use world_core::block_registry::{BlockInfo, BlockRegistry};
+    use world_core::block_state::BlockState;
+    use world_core::{Chunk, ChunkManager};
+
+    fn full_chunk(pos: ChunkPos, state: BlockState) -> Chunk {
+        Chunk::from_block_array(pos, &[state; Chunk::BLOCK_COUNT])
+    }
+
+    #[test]
+    pub fn an_isolated_block_has_six_faces() {
+        let mut registry = BlockRegistry::new();
+        let stone = registry.register(BlockInfo::cube("stone", 0));
+        let mut chunk_manager = ChunkManager::new();
+        let mut chunk = Chunk::new(ChunkPos::ZERO);
+        chunk.set_block_at(3, 4, 5, stone);
+        chunk_manager.insert_chunk(chunk).unwrap();
+
+        let mesh = ChunkMesh::build_from(
+            &chunk_manager,
+            ChunkPos::ZERO,
+            &registry,
+            AtlasLayout::Layers,
+        )
+        .unwrap();
+        assert_eq!(mesh.vertices().len(), 24);
+        assert_eq!(mesh.indices().len(), 36);
+
+        //the block spans z - 1 to z, see the comment of add_face
+        for vertex in mesh.vertices() {
+            let [x, y, z] = vertex.position;
+            assert!((3.0..=4.0).contains(&x) && (4.0..=5.0).contains(&y));
+            assert!((4.0..=5.0).contains(&z));
         }
     }
 
-    pub fn draw<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>, pos_index: usize) {
-        let pos_index = pos_index as u32;
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        render_pass.draw_indexed(0..self.index_count, 0, pos_index..pos_index + 1);
+    #[test]
+    pub fn the_faces_between_opaque_blocks_are_culled() {
+        let mut registry = BlockRegistry::new();
+        let stone = registry.register(BlockInfo::cube("stone", 0));
+        let mut chunk_manager = ChunkManager::new();
+        chunk_manager
+            .insert_chunk(full_chunk(ChunkPos::ZERO, stone))
+            .unwrap();
+
+        //only the sides of the chunk are visible, the neighbors aren't loaded
+        let mesh = ChunkMesh::build_from(
+            &chunk_manager,
+            ChunkPos::ZERO,
+            &registry,
+            AtlasLayout::Layers,
+        )
+        .unwrap();
+        let face_count = 6 * (CHUNK_SIZE * CHUNK_SIZE) as usize;
+        assert_eq!(mesh.vertices().len(), 4 * face_count);
+        assert_eq!(mesh.indices().len(), 6 * face_count);
+
+        //the neighbors hide the sides of the chunk
+        for pos in [
+            ChunkPos::X,
+            ChunkPos::NEG_X,
+            ChunkPos::Y,
+            ChunkPos::NEG_Y,
+            ChunkPos::Z,
+            ChunkPos::NEG_Z,
+        ] {
+            chunk_manager.insert_chunk(full_chunk(pos, stone)).unwrap();
+        }
+        assert!(ChunkMesh::build_from(
+            &chunk_manager,
+            ChunkPos::ZERO,
+            &registry,
+            AtlasLayout::Layers
+        )
+        .is_none());
     }
 }
@@ -0,0 +1,113 @@
+use crate::graphic::Context;
+use math::Vec3;
+
+///a directional light, like the sun, and an ambient light lighting all the faces
+///the colors are in linear space
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    ///the direction pointing to the light, normalized
+    pub direction: Vec3,
+    pub color: [f32; 3],
+    pub ambient_color: [f32; 3],
+}
+
+impl Default for Light {
+    ///a white light from above
+    fn default() -> Self {
+        Self {
+            direction: Vec3::Y,
+            color: [0.7; 3],
+            ambient_color: [0.3; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    direction: [f32; 4],
+    color: [f32; 4],
+    ambient_color: [f32; 4],
+}
+
+impl From<&Light> for LightUniform {
+    fn from(light: &Light) -> Self {
+        let [r, g, b] = light.color;
+        let [ambient_r, ambient_g, ambient_b] = light.ambient_color;
+        Self {
+            direction: light.direction.extend(0.0).to_array(),
+            color: [r, g, b, 1.0],
+            ambient_color: [ambient_r, ambient_g, ambient_b, 1.0],
+        }
+    }
+}
+
+///the uniform buffer of the light and its bind group, used by the terrain shader
+pub struct LightBinding {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl LightBinding {
+    pub fn new(context: &Context) -> Self {
+        let uniform_buffer = context.wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: std::mem::size_of::<LightUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout =
+            context
+                .wgpu_device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    }],
+                    label: Some("Light Bind Group Layout"),
+                });
+
+        let bind_group = context
+            .wgpu_device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }],
+                label: Some("Light Bind Group"),
+            });
+
+        let light_binding = Self {
+            uniform_buffer,
+            bind_group,
+            bind_group_layout,
+        };
+        light_binding.update(&Light::default(), context);
+        light_binding
+    }
+
+    pub fn update(&self, light: &Light, context: &Context) {
+        context.wgpu_queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[LightUniform::from(light)]),
+        );
+    }
+
+    pub fn get_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn get_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -1,54 +1,54 @@
+mod batched_meshes;
 mod chunk_mesh;
+mod light;
 mod ordered_chunk_pos;
 mod texture_atlas;
 
-use super::camera::{Camera, CameraFrustum};
+pub use light::Light;
+pub use texture_atlas::{TextureAtlasBuilder, TextureAtlasError};
+
+use super::camera::Camera;
 use super::{Context, RenderJob};
+use crate::graphic::terrain::batched_meshes::BatchedMeshes;
 use crate::graphic::terrain::chunk_mesh::ChunkMesh;
+use crate::graphic::terrain::light::LightBinding;
 use crate::graphic::terrain::ordered_chunk_pos::OrderedChunkPos;
-use crate::graphic::terrain::texture_atlas::{TextureAtlas, TextureAtlasBuilder};
-use std::collections::BTreeMap;
+use crate::graphic::terrain::texture_atlas::TextureAtlas;
+use math::aabb::AABB;
+use math::frustum::CameraFrustum;
+use math::positions::ChunkPos;
+use std::collections::BTreeSet;
 use utils::spare_set::{Id, SparseSet};
-use wgpu::util::DeviceExt;
-use world_core::{Chunk, ChunkManager};
+use world_core::block_registry::BlockRegistry;
+use world_core::{predicates, Chunk, ChunkManager};
 
 pub struct TerrainRenderer {
     render_pipeline: wgpu::RenderPipeline,
     texture_atlas: TextureAtlas,
-    chunks_meshes: BTreeMap<OrderedChunkPos, ChunkMesh>,
+    light_binding: LightBinding,
+    chunks_meshes: BatchedMeshes,
     cache: MeshCache,
     render_distance: i32,
     last_frustum: CameraFrustum,
+    ///the number of meshes built for the chunks entering the frustum, the cached meshes aren't counted
+    built_mesh_count: u64,
 }
 
 impl TerrainRenderer {
+    ///the meshes of the chunks out of the frustum are kept while they are within render_distance + cache_margin of the camera
     pub fn new(
         camera: &Camera,
         render_distance: i32,
+        cache_margin: i32,
         chunk_manager: &ChunkManager,
+        block_registry: &BlockRegistry,
+        atlas_builder: TextureAtlasBuilder,
+        sample_count: u32,
         context: &Context,
     ) -> Self {
-        //todo: change that to a proper resource manager
-
-        let load_texture = |buffer: &[u8]| image::load_from_memory(buffer).unwrap().to_rgba8();
-
-        let builder = TextureAtlasBuilder {
-            vec: vec![
-                load_texture(include_bytes!("textures/stone.png")),
-                load_texture(include_bytes!("textures/diamond_block.png")),
-                load_texture(include_bytes!("textures/emerald_block.png")),
-                load_texture(include_bytes!("textures/lapis_block.png")),
-                load_texture(include_bytes!("textures/gold_block.png")),
-                load_texture(include_bytes!("textures/iron_block.png")),
-                load_texture(include_bytes!("textures/coal_block.png")),
-                load_texture(include_bytes!("textures/wool_colored_red.png")),
-                load_texture(include_bytes!("textures/hay_block_top.png")),
-                load_texture(include_bytes!("textures/hay_block_side.png")),
-                load_texture(include_bytes!("textures/grass_block_top.png")),
-            ],
-        };
-
-        let texture_atlas = TextureAtlas::new_exp(builder, 16, context);
+        let texture_size = atlas_builder.texture_size();
+        let texture_atlas = TextureAtlas::new_exp(atlas_builder, texture_size, context);
+        let light_binding = LightBinding::new(context);
 
         let shader = context
             .wgpu_device
@@ -61,6 +61,7 @@ impl TerrainRenderer {
                     bind_group_layouts: &[
                         camera.get_bind_group_layout(),        //0
                         texture_atlas.get_bind_group_layout(), //1
+                        light_binding.get_bind_group_layout(), //2
                     ],
                     push_constant_ranges: &[],
                 });
@@ -100,126 +101,187 @@ impl TerrainRenderer {
                         stencil: wgpu::StencilState::default(),
                         bias: wgpu::DepthBiasState::default(),
                     }),
-                    multisample: wgpu::MultisampleState::default(),
+                    multisample: wgpu::MultisampleState {
+                        count: sample_count, //must match the render targets of the window
+                        ..Default::default()
+                    },
                     multiview: None,
                 });
 
-        let mut chunks_meshes = BTreeMap::new();
+        let mut chunks_meshes = BatchedMeshes::new();
         let frustum = camera.get_frustum(render_distance);
         let chunks_to_display = chunk_manager
-            .get_chunk_with_predicate(frustum.get_aabb(), |aabb| frustum.contains(&aabb));
+            .get_chunk_with_predicate(frustum.get_aabb(), predicates::frustum(&frustum));
         for chunk in chunks_to_display {
-            if let Some(mesh) =
-                ChunkMesh::build_from(chunk_manager, chunk.position(), &texture_atlas, context)
-            {
-                chunks_meshes.insert(chunk.position().into(), mesh);
+            if let Some(mesh) = ChunkMesh::build_from(
+                chunk_manager,
+                chunk.position(),
+                block_registry,
+                texture_atlas.layout(),
+            ) {
+                chunks_meshes.insert(chunk.position(), mesh);
             }
         }
 
-        let cache_size = (render_distance as usize * 2).pow(3);
-
         Self {
             render_distance,
             render_pipeline,
             texture_atlas,
+            light_binding,
             chunks_meshes,
             last_frustum: frustum,
-            cache: MeshCache::new(cache_size),
+            cache: MeshCache::for_render_distance(
+                render_distance,
+                cache_margin,
+                camera.position.chunk_pos,
+            ),
+            built_mesh_count: 0,
         }
     }
 
     pub fn rendered_mesh_count(&self) -> usize {
-        self.chunks_meshes.len()
+        self.chunks_meshes.mesh_count()
+    }
+
+    pub fn built_mesh_count(&self) -> u64 {
+        self.built_mesh_count
+    }
+
+    pub fn cached_mesh_count(&self) -> usize {
+        self.cache.len()
+    }
+
+    ///set the light of the terrain, it is kept until the next call
+    pub fn update_light(&self, light: &Light, context: &Context) {
+        self.light_binding.update(light, context);
+    }
+
+    ///the meshes are batched, so there are less draw calls than meshes
+    pub fn draw_call_count(&self) -> usize {
+        self.chunks_meshes.draw_call_count()
+    }
+
+    ///rebuild the meshes of the given chunks and of their neighbours, to call when chunks are inserted or modified
+    pub fn update_chunks(
+        &mut self,
+        chunk_manager: &ChunkManager,
+        block_registry: &BlockRegistry,
+        positions: &[ChunkPos],
+    ) {
+        //the faces of the neighbours depend on the chunk too
+        let mut to_update = BTreeSet::new();
+        for pos in positions {
+            to_update.insert(OrderedChunkPos(*pos));
+            for offset in [
+                ChunkPos::X,
+                ChunkPos::NEG_X,
+                ChunkPos::Y,
+                ChunkPos::NEG_Y,
+                ChunkPos::Z,
+                ChunkPos::NEG_Z,
+            ] {
+                to_update.insert(OrderedChunkPos(*pos + offset));
+            }
+        }
+
+        //the cached meshes would be outdated when the chunks come back in the frustum
+        self.cache.remove_meshes_at(&to_update);
+
+        let frustum = &self.last_frustum;
+        for pos in to_update {
+            let aabb = AABB::new(pos.0, pos.0 + ChunkPos::ONE);
+            if !(frustum.get_aabb().intersects(&aabb) && frustum.contains(&aabb)) {
+                continue;
+            }
+            let mesh = ChunkMesh::build_from(
+                chunk_manager,
+                pos.0,
+                block_registry,
+                self.texture_atlas.layout(),
+            );
+            match mesh {
+                Some(mesh) => self.chunks_meshes.insert(pos.0, mesh),
+                None => {
+                    self.chunks_meshes.remove(pos.0);
+                }
+            }
+        }
     }
 
     pub fn build_render_job<'a>(
         &'a mut self,
         chunk_manager: &'a mut ChunkManager,
+        block_registry: &'a BlockRegistry,
         camera: &'a Camera,
         context: &'a Context,
     ) -> TerrainRenderJob<'a> {
         let old_frustum = &self.last_frustum;
         let new_frustum = camera.get_frustum(self.render_distance);
 
-        //difference between two frustum
-        let frustum_diff = |aabb, frustum1: &CameraFrustum, frustum2: &CameraFrustum| {
-            frustum1.contains(&aabb)
-                && if aabb.is_unit() {
-                    !(frustum2.contains(&aabb) && frustum2.get_aabb().intersects(&aabb))
-                } else {
-                    true
-                }
-        };
+        //the meshes too far from the camera won't come back in the frustum soon
+        self.cache.set_center(camera.position.chunk_pos);
 
         //add new visible chunks
         {
             let add_chunk = |id, chunk: &Chunk| {
                 let mesh = self.cache.get_mesh(id).unwrap_or_else(|| {
+                    self.built_mesh_count += 1;
                     ChunkMesh::build_from(
                         chunk_manager,
                         chunk.position(),
-                        &self.texture_atlas,
-                        context,
+                        block_registry,
+                        self.texture_atlas.layout(),
                     )
                 });
                 if let Some(mesh) = mesh {
-                    self.chunks_meshes.insert(chunk.position().into(), mesh);
+                    self.chunks_meshes.insert(chunk.position(), mesh);
                 }
             };
-            chunk_manager.foreach_chunk_with_predicate(
-                new_frustum.get_aabb(),
-                |aabb| frustum_diff(aabb, &new_frustum, old_frustum),
-                add_chunk,
-            );
+            foreach_chunk_not_in(chunk_manager, &new_frustum, old_frustum, add_chunk);
         }
 
         //remove old visible chunks
         {
             let remove_chunk = |id, chunk: &Chunk| {
-                let mesh = self.chunks_meshes.remove(&chunk.position().into());
-                self.cache.add_mesh(id, mesh);
+                let mesh = self.chunks_meshes.remove(chunk.position());
+                self.cache.add_mesh(id, chunk.position(), mesh);
             };
-            chunk_manager.foreach_chunk_with_predicate(
-                old_frustum.get_aabb(),
-                |aabb| frustum_diff(aabb, old_frustum, &new_frustum),
-                remove_chunk,
-            );
+            foreach_chunk_not_in(chunk_manager, old_frustum, &new_frustum, remove_chunk);
         }
 
         self.last_frustum = new_frustum;
 
-        let pos = self
-            .chunks_meshes
-            .keys()
-            .map(|pos| {
-                let pos = pos.0;
-                ChunkPosAttribute {
-                    position: [pos.x, pos.y, pos.z],
-                }
-            })
-            .collect::<Vec<_>>();
-
-        let pos_buffer =
-            context
-                .wgpu_device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Chunk Position Buffer"),
-                    contents: bytemuck::cast_slice(&pos),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
+        self.chunks_meshes.update_batches(&context.wgpu_device);
 
         TerrainRenderJob {
             terrain_renderer: self,
             camera,
-            pos_buffer,
         }
     }
 }
 
+///give the chunks in `frustum` that aren't in `other`, the chunks entering the view when `frustum` is the new one
+///and the chunks leaving it when `frustum` is the old one
+fn foreach_chunk_not_in<'a>(
+    chunk_manager: &'a ChunkManager,
+    frustum: &CameraFrustum,
+    other: &CameraFrustum,
+    out_func: impl FnMut(Id, &'a Chunk),
+) {
+    let predicate = |aabb: AABB| {
+        frustum.contains(&aabb)
+            && if aabb.is_unit() {
+                !(other.contains(&aabb) && other.get_aabb().intersects(&aabb))
+            } else {
+                true
+            }
+    };
+    chunk_manager.foreach_chunk_with_predicate(frustum.get_aabb(), predicate, out_func);
+}
+
 pub struct TerrainRenderJob<'a> {
     terrain_renderer: &'a TerrainRenderer,
     camera: &'a Camera,
-    pos_buffer: wgpu::Buffer,
 }
 
 impl RenderJob for TerrainRenderJob<'_> {
@@ -231,14 +293,9 @@ impl RenderJob for TerrainRenderJob<'_> {
         let terrain_renderer = &self.terrain_renderer;
         render_pass.set_bind_group(0, &self.camera.get_bind_group(), &[]);
         render_pass.set_bind_group(1, terrain_renderer.texture_atlas.get_bind_group(), &[]);
+        render_pass.set_bind_group(2, terrain_renderer.light_binding.get_bind_group(), &[]);
         render_pass.set_pipeline(&self.terrain_renderer.render_pipeline);
-
-        for (chunk_index, (_pos, chunk_mesh)) in
-            self.terrain_renderer.chunks_meshes.iter().enumerate()
-        {
-            render_pass.set_vertex_buffer(1, self.pos_buffer.slice(..));
-            chunk_mesh.draw(render_pass, chunk_index);
-        }
+        terrain_renderer.chunks_meshes.draw(render_pass);
     }
 }
 
@@ -248,13 +305,17 @@ struct Vertex {
     position: [f32; 3],
     texture_coords: [f32; 2],
     texture_index: u32,
+    ///the face of the block the vertex belongs to, see [`world_core::block_registry::BlockFace`], the shader gets the normal from it
+    face: u32,
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    //the location 3 is used by the chunk position of the batch
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
         0 => Float32x3,
         1 => Float32x2,
         2 => Uint32,
+        4 => Uint32,
     ];
 
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -286,46 +347,149 @@ impl ChunkPosAttribute {
     }
 }
 
+///the meshes of the chunks that left the frustum, so they aren't built again when the chunks come back in it, like when the camera turns around
+///the meshes within the retention distance of the camera are kept, the farthest ones are dropped first when the cache is full
 struct MeshCache {
-    cached_meshes: SparseSet<(u16, Option<ChunkMesh>)>,
+    cached_meshes: SparseSet<(ChunkPos, Option<ChunkMesh>)>,
     size: usize,
-    date: u16,
-    oldest: u16,
+    ///in chunks, the meshes farther from the center are dropped
+    retention_distance: i32,
+    ///the chunk of the camera
+    center: ChunkPos,
 }
 
 impl MeshCache {
-    fn new(size: usize) -> Self {
+    fn new(size: usize, retention_distance: i32, center: ChunkPos) -> Self {
         Self {
             cached_meshes: SparseSet::with_capacity(size),
             size,
-            date: 0,
-            oldest: 0,
+            retention_distance,
+            center,
         }
     }
 
+    ///the cache of a terrain renderer, see [`TerrainRenderer::new`]
+    fn for_render_distance(render_distance: i32, cache_margin: i32, center: ChunkPos) -> Self {
+        let size = (render_distance as usize * 2).pow(3);
+        Self::new(size, render_distance + cache_margin, center)
+    }
+
+    fn len(&self) -> usize {
+        self.cached_meshes.len()
+    }
+
+    fn is_retained(&self, pos: ChunkPos) -> bool {
+        (pos - self.center).length_squared() <= self.retention_distance * self.retention_distance
+    }
+
     ///get the mesh from the cache and remove if it exists
     fn get_mesh(&mut self, chunk_id: Id) -> Option<Option<ChunkMesh>> {
         self.cached_meshes.remove(chunk_id).map(|(_, mesh)| mesh)
     }
 
-    fn add_mesh(&mut self, chunk_id: Id, mesh: Option<ChunkMesh>) {
+    ///a chunk beyond the retention distance isn't cached
+    fn add_mesh(&mut self, chunk_id: Id, pos: ChunkPos, mesh: Option<ChunkMesh>) {
+        if !self.is_retained(pos) {
+            return;
+        }
         if self.cached_meshes.len() >= self.size {
-            self.remove_oldest_mesh();
+            self.remove_farthest_mesh();
         }
+        self.cached_meshes.insert(chunk_id, (pos, mesh));
+    }
 
-        self.cached_meshes.insert(chunk_id, (self.date, mesh));
-        self.date = self.date.wrapping_add(1);
+    ///move the center and drop the meshes that are now beyond the retention distance
+    fn set_center(&mut self, center: ChunkPos) {
+        if self.center == center {
+            return;
+        }
+        self.center = center;
+        let far_ids: Vec<Id> = self
+            .cached_meshes
+            .iter()
+            .filter(|(_, (pos, _))| !self.is_retained(*pos))
+            .map(|(id, _)| id)
+            .collect();
+        for id in far_ids {
+            self.cached_meshes.remove(id);
+        }
     }
 
-    fn remove_oldest_mesh(&mut self) {
-        let mut oldest_id = None;
-        for (id, (date, _)) in self.cached_meshes.iter() {
-            if *date == self.oldest {
-                oldest_id = Some(id);
-                break;
-            }
+    fn remove_meshes_at(&mut self, positions: &BTreeSet<OrderedChunkPos>) {
+        let ids: Vec<Id> = self
+            .cached_meshes
+            .iter()
+            .filter(|(_, (pos, _))| positions.contains(&OrderedChunkPos(*pos)))
+            .map(|(id, _)| id)
+            .collect();
+        for id in ids {
+            self.cached_meshes.remove(id);
+        }
+    }
+
+    fn remove_farthest_mesh(&mut self) {
+        let farthest = self
+            .cached_meshes
+            .iter()
+            .max_by_key(|(_, (pos, _))| (*pos - self.center).length_squared())
+            .map(|(id, _)| id);
+        if let Some(id) = farthest {
+            self.cached_meshes.remove(id);
         }
-        self.cached_meshes.remove(oldest_id.unwrap());
-        self.oldest = self.oldest.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::graphic::terrain::{foreach_chunk_not_in, MeshCache};
+    use math::frustum::{compute_frustum, CameraFrustum};
+    use math::positions::{ChunkPos, EntityPos};
+    use math::Vec3;
+    use std::f32::consts::{FRAC_PI_2, TAU};
+    use world_core::{Chunk, ChunkManager};
+
+    const RENDER_DISTANCE: i32 = 8;
+
+    fn frustum_at(yaw: f32) -> CameraFrustum {
+        let position = EntityPos::new(ChunkPos::ZERO, Vec3::splat(8.0));
+        compute_frustum(0.0, yaw, position, FRAC_PI_2, 16.0 / 9.0, RENDER_DISTANCE)
+    }
+
+    ///turn the camera twice over the same chunks, the frustum changes are processed like in build_render_job without the meshes
+    ///give the number of chunks entering the frustum during the second turn that aren't cached, their meshes are built again
+    fn rebuilt_during_second_turn(cache_margin: i32) -> u32 {
+        const STEPS: u32 = 64;
+        let side = RENDER_DISTANCE + 2;
+        let mut chunk_manager = ChunkManager::new();
+        let chunks = (-side..=side).flat_map(|x| {
+            (-side..=side)
+                .flat_map(move |y| (-side..=side).map(move |z| Chunk::new(ChunkPos::new(x, y, z))))
+        });
+        chunk_manager.insert_chunks(chunks).unwrap();
+
+        let mut cache =
+            MeshCache::for_render_distance(RENDER_DISTANCE, cache_margin, ChunkPos::ZERO);
+        let mut frustum = frustum_at(0.0);
+        let mut rebuilt = 0;
+        for step in 1..=2 * STEPS {
+            let new_frustum = frustum_at(step as f32 * TAU / STEPS as f32);
+            foreach_chunk_not_in(&chunk_manager, &new_frustum, &frustum, |id, _| {
+                if cache.get_mesh(id).is_none() && step > STEPS {
+                    rebuilt += 1;
+                }
+            });
+            foreach_chunk_not_in(&chunk_manager, &frustum, &new_frustum, |id, chunk| {
+                cache.add_mesh(id, chunk.position(), None);
+            });
+            frustum = new_frustum;
+        }
+        rebuilt
+    }
+
+    #[test]
+    pub fn the_cache_margin_keeps_the_meshes_while_the_camera_turns() {
+        assert_eq!(rebuilt_during_second_turn(4), 0);
+        //the far corners of the frustum are beyond the render distance, their chunks aren't cached without a margin
+        assert_eq!(rebuilt_during_second_turn(0), 484);
     }
 }
@@ -13,6 +13,7 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) texture_coord: vec2<f32>,
     @location(2) texture_index: u32,
+    @location(4) face: u32, //the BlockFace of the vertex, the normal is deduced from it
     @location(3) chunk_pos: vec3<i32>, //chunk_pos in the world, can be seen as a dynamic origin
 };
 
@@ -20,8 +21,21 @@ struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) texture_coord: vec2<f32>,
     @location(1) texture_index: u32,
+    @location(2) normal: vec3<f32>,
 };
 
+//the normals in the order of the BlockFace enum: Top, Bottom, West, East, North, South
+fn face_normal(face: u32) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 1u: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 2u: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 3u: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 4u: { return vec3<f32>(0.0, 0.0, -1.0); }
+        default: { return vec3<f32>(0.0, 0.0, 1.0); }
+    }
+}
+
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -31,6 +45,7 @@ fn vs_main(
     out.clip_position = camera.view_proj * vec4<f32>(model.position + displacement, 1.0);
     out.texture_coord = model.texture_coord;
     out.texture_index = model.texture_index;
+    out.normal = face_normal(model.face);
     return out;
 }
 
@@ -41,9 +56,21 @@ var texture: texture_2d_array<f32>;
 @group(1) @binding(1)
 var texture_sampler: sampler;
 
+struct LightUniform {
+    direction: vec4<f32>, //pointing to the light
+    color: vec4<f32>,
+    ambient_color: vec4<f32>,
+};
+
+@group(2) @binding(0) //group is define in the Pipeline Layout, binding is defined in the Light layout
+var<uniform> light: LightUniform;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, texture_sampler, in.texture_coord, in.texture_index);
+    let color = textureSample(texture, texture_sampler, in.texture_coord, in.texture_index);
+    let diffuse = max(dot(in.normal, light.direction.xyz), 0.0);
+    let lighting = light.ambient_color.rgb + light.color.rgb * diffuse;
+    return vec4<f32>(color.rgb * lighting, color.a);
 }
 
 
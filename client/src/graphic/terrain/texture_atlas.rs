@@ -1,11 +1,118 @@
 use crate::graphic::Context;
 use image::RgbaImage;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum TextureAtlasError {
+    Io(std::io::Error),
+    ///the texture file can't be decoded
+    Image(String, image::ImageError),
+    ///all the textures must have the size of the first one
+    SizeMismatch {
+        name: String,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    ///the layers of the atlas are squares
+    NotSquare(String),
+    ///the directory doesn't contain any png
+    Empty,
+}
+
+impl Error for TextureAtlasError {}
+
+impl Display for TextureAtlasError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureAtlasError::Io(e) => write!(f, "can't read the textures: {}", e),
+            TextureAtlasError::Image(name, e) => {
+                write!(f, "can't decode the texture {}: {}", name, e)
+            }
+            TextureAtlasError::SizeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the texture {} is {}x{}, but the textures are {}x{}",
+                name, found.0, found.1, expected.0, expected.1
+            ),
+            TextureAtlasError::NotSquare(name) => write!(f, "the texture {} is not a square", name),
+            TextureAtlasError::Empty => write!(f, "there is no texture in the directory"),
+        }
+    }
+}
+
+impl From<std::io::Error> for TextureAtlasError {
+    fn from(e: std::io::Error) -> Self {
+        TextureAtlasError::Io(e)
+    }
+}
 
 //first we need to know all existing textures to create a texture atlas
 pub struct TextureAtlasBuilder {
     pub vec: Vec<RgbaImage>,
 }
 
+impl TextureAtlasBuilder {
+    ///load all the png of a directory, the layer of each texture is given by the name of its file, without the extension
+    ///the files are sorted by name, so the layers don't depend on the order of the directory entries
+    pub fn from_dir(
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, HashMap<String, u32>), TextureAtlasError> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            let is_png = path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+            if !is_png {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            files.push((name.to_string(), path));
+        }
+        files.sort();
+
+        let mut vec: Vec<RgbaImage> = Vec::with_capacity(files.len());
+        let mut layers = HashMap::with_capacity(files.len());
+        for (name, path) in files {
+            let texture = match image::open(&path) {
+                Ok(texture) => texture.to_rgba8(),
+                Err(e) => return Err(TextureAtlasError::Image(name, e)),
+            };
+            if texture.width() != texture.height() {
+                return Err(TextureAtlasError::NotSquare(name));
+            }
+            if let Some(first) = vec.first() {
+                if first.dimensions() != texture.dimensions() {
+                    return Err(TextureAtlasError::SizeMismatch {
+                        name,
+                        expected: first.dimensions(),
+                        found: texture.dimensions(),
+                    });
+                }
+            }
+            layers.insert(name, vec.len() as u32);
+            vec.push(texture);
+        }
+        if vec.is_empty() {
+            return Err(TextureAtlasError::Empty);
+        }
+        Ok((Self { vec }, layers))
+    }
+
+    ///the size of a side of the textures, they are all squares of the same size
+    pub fn texture_size(&self) -> u32 {
+        self.vec.first().map_or(0, |texture| texture.width())
+    }
+}
+
 //store all texture blocks in a single texture
 //responsible for creating the texture and the bind group
 //map block id to texture coordinates //TODO support multiple textures per block
@@ -14,9 +121,34 @@ pub struct TextureAtlas {
     _texture_sampler: wgpu::Sampler,
     bind_group: wgpu::BindGroup,
     bind_group_layout: wgpu::BindGroupLayout,
+    layout: AtlasLayout,
 }
 
 impl TextureAtlas {
+    ///the number of mip levels down to 1x1, 5 for 16x16 textures
+    fn get_mip_level_count(block_texture_size: u32) -> u32 {
+        u32::BITS - block_texture_size.leading_zeros()
+    }
+
+    ///halve the size of the image with a box filter, the last row or column of an odd sized image is averaged with the previous one
+    fn downsample(image: &RgbaImage) -> RgbaImage {
+        let width = (image.width() / 2).max(1);
+        let height = (image.height() / 2).max(1);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let x0 = (2 * x).min(image.width() - 1);
+            let y0 = (2 * y).min(image.height() - 1);
+            let x1 = (2 * x + 1).min(image.width() - 1);
+            let y1 = (2 * y + 1).min(image.height() - 1);
+            let mut sum = [0u32; 4];
+            for (px, py) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+                for (channel_sum, channel) in sum.iter_mut().zip(image.get_pixel(px, py).0) {
+                    *channel_sum += channel as u32;
+                }
+            }
+            image::Rgba(sum.map(|channel_sum| ((channel_sum + 2) / 4) as u8))
+        })
+    }
+
     fn create_texture(
         block_texture_size: u32,
         block_texture_count: u32,
@@ -33,7 +165,7 @@ impl TextureAtlas {
             .create_texture(&wgpu::TextureDescriptor {
                 label: Some("Texture Atlas"),
                 size: texture_size,
-                mip_level_count: 1,
+                mip_level_count: Self::get_mip_level_count(block_texture_size),
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8UnormSrgb, //because of rgba8
@@ -53,6 +185,7 @@ impl TextureAtlas {
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
                 mag_filter: wgpu::FilterMode::Nearest,
                 min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear, //blend the mip levels to avoid the shimmering of distant blocks
                 ..Default::default()
             })
     }
@@ -90,33 +223,39 @@ impl TextureAtlas {
     ) -> Self {
         let atlas = Self::create_texture(block_texture_size, builder.vec.len() as u32, context);
 
-        let block_texture_size = wgpu::Extent3d {
-            width: block_texture_size,
-            height: block_texture_size,
-            depth_or_array_layers: 1,
-        };
-        //pos in item to the next texture to copy
+        let mip_level_count = Self::get_mip_level_count(block_texture_size);
+        //each texture is a layer of the array, so the mip levels are computed per layer and can't bleed between textures
         for (i, block_texture) in builder.vec.iter().enumerate() {
-            //could be more efficient to use CommandEncoder::write_texture(self) instead, queue create multiple command encoder...
-            context.wgpu_queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &atlas,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d {
-                        x: 0,
-                        y: 0,
-                        z: i as u32,
+            let mut mip = block_texture.clone();
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    mip = Self::downsample(&mip);
+                }
+                //could be more efficient to use CommandEncoder::write_texture(self) instead, queue create multiple command encoder...
+                context.wgpu_queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &atlas,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: i as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &mip,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * mip.width()),
+                        rows_per_image: Some(mip.height()),
                     },
-                    aspect: wgpu::TextureAspect::All,
-                },
-                &block_texture,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * block_texture_size.width),
-                    rows_per_image: Some(block_texture_size.height),
-                },
-                block_texture_size,
-            );
+                    wgpu::Extent3d {
+                        width: mip.width(),
+                        height: mip.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
         }
 
         let texture_sampler = Self::create_sampler(context);
@@ -129,6 +268,7 @@ impl TextureAtlas {
             _texture_sampler: texture_sampler,
             bind_group_layout,
             bind_group,
+            layout: AtlasLayout::Layers,
         }
     }
 
@@ -166,21 +306,90 @@ impl TextureAtlas {
         &self.bind_group
     }
 
-    pub fn get_texture_coordinates(&self) -> TextureCoordinates {
-        TextureCoordinates {
-            x2: 1.0,
-            y2: 1.0,
-            x1: 0.0,
-            y1: 0.0,
+    ///how the textures are packed, it gives the rect of each texture without the GPU, see [`AtlasLayout::texture_coordinates`]
+    pub fn layout(&self) -> AtlasLayout {
+        self.layout
+    }
+}
+
+///how the textures are packed in the atlas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtlasLayout {
+    ///each texture is a layer of the array and covers all of it, the block textures use this one
+    Layers,
+    ///the textures are the tiles of a grid in a single layer, indexed row by row from the top left, like a font or an icon sheet
+    #[allow(dead_code)] //for the packed sheets of the HUD
+    Grid { columns: u32, rows: u32 },
+}
+
+impl AtlasLayout {
+    pub fn texture_coordinates(&self, index: u32) -> TextureCoordinates {
+        match *self {
+            AtlasLayout::Layers => TextureCoordinates {
+                x2: 1.0,
+                y2: 1.0,
+                x1: 0.0,
+                y1: 0.0,
+            },
+            AtlasLayout::Grid { columns, rows } => {
+                debug_assert!(index < columns * rows, "the tile is out of the grid");
+                let (column, row) = (index % columns, index / columns);
+                let (width, height) = (1.0 / columns as f32, 1.0 / rows as f32);
+                TextureCoordinates {
+                    x1: column as f32 * width,
+                    y1: row as f32 * height,
+                    x2: (column + 1) as f32 * width,
+                    y2: (row + 1) as f32 * height,
+                }
+            }
         }
     }
 }
 
 ///x1, y1 is the top left corner, x2, y2 is the bottom right corner
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TextureCoordinates {
     pub x1: f32,
     pub y1: f32,
     pub x2: f32,
     pub y2: f32,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::graphic::terrain::texture_atlas::{AtlasLayout, TextureCoordinates};
+
+    #[test]
+    pub fn the_tiles_are_indexed_row_by_row() {
+        let full = TextureCoordinates {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 1.0,
+        };
+        assert_eq!(AtlasLayout::Layers.texture_coordinates(7), full);
+
+        let grid = AtlasLayout::Grid {
+            columns: 4,
+            rows: 2,
+        };
+        assert_eq!(
+            grid.texture_coordinates(0),
+            TextureCoordinates {
+                x1: 0.0,
+                y1: 0.0,
+                x2: 0.25,
+                y2: 0.5,
+            }
+        );
+        assert_eq!(
+            grid.texture_coordinates(6),
+            TextureCoordinates {
+                x1: 0.5,
+                y1: 0.5,
+                x2: 0.75,
+                y2: 1.0,
+            }
+        );
+    }
+}
@@ -0,0 +1,19 @@
+@group(0) @binding(0)
+var gui_texture: texture_2d<f32>;
+
+//a single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    //the texture has the size of the screen, so the pixel is read directly without sampler
+    //the colors are premultiplied by the alpha, like egui renders them
+    return textureLoad(gui_texture, vec2<i32>(position.xy), 0);
+}
@@ -1,4 +1,5 @@
 use crate::graphic::RenderJob;
+use egui::epaint::ClippedShape;
 use egui::{ClippedPrimitive, ViewportInfo};
 use egui_wgpu::renderer::ScreenDescriptor;
 use egui_winit::winit::event::WindowEvent;
@@ -33,17 +34,75 @@ impl<DataObject> GUIWrapper<DataObject> {
     }
 }
 
-//could be improved by drawing the GUI into a texture when the gui is updated and then drawing the texture on the screen
-//this would allow to draw the GUI only when it is updated
-//but this mean using a new texture and resizing it when the window is resized
+///the texture the GUI is rendered to, it has the size of the window
+struct GuiTexture {
+    size: [u32; 2],
+    _texture: wgpu::Texture, //kept alive with its view
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GuiTexture {
+    fn new(
+        size: [u32; 2],
+        format: wgpu::TextureFormat,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        graphic_context: &super::Context,
+    ) -> Self {
+        let texture = graphic_context
+            .wgpu_device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("GUI Texture"),
+                size: wgpu::Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group =
+            graphic_context
+                .wgpu_device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    }],
+                    label: Some("GUI Bind Group"),
+                });
+        Self {
+            size,
+            _texture: texture,
+            view,
+            bind_group,
+        }
+    }
+}
 
 ///DataObject is an object modified by the GUI, it must be used to get the GUI entries, can be the main app as reference
+///the GUI is rendered to a texture only when its shapes change, the texture is drawn on the screen each frame
 pub struct GuiHandler<DataObject> {
     context: egui::Context,
     state: egui_winit::State,
     renderer: egui_wgpu::Renderer,
     gui_pointer: GUIWrapper<DataObject>,
     draw_data: Option<DrawData>,
+    ///the shapes of the last update, to know if the texture must be rendered again
+    last_shapes: Vec<ClippedShape>,
+    ///true when the draw data changed since the texture was rendered
+    needs_redraw: bool,
+    gui_texture: Option<GuiTexture>,
+    texture_format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    render_pipeline: wgpu::RenderPipeline,
 }
 
 pub struct DrawData {
@@ -61,12 +120,28 @@ impl<DataObject> GuiHandler<DataObject> {
             None,
             None,
         );
-        let renderer = egui_wgpu::Renderer::new(
-            &graphic_context.wgpu_device,
-            window.get_surface_config().format,
-            Some(super::Window::DEPTH_FORMAT),
-            1,
+        //the texture has no depth buffer and a single sample, the GUI doesn't need anti-aliasing
+        let texture_format = window.get_surface_config().format;
+        let renderer =
+            egui_wgpu::Renderer::new(&graphic_context.wgpu_device, texture_format, None, 1);
+
+        let bind_group_layout = graphic_context.wgpu_device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                }],
+                label: Some("GUI Bind Group Layout"),
+            },
         );
+        let render_pipeline =
+            Self::create_render_pipeline(window, graphic_context, &bind_group_layout);
 
         Self {
             context,
@@ -74,9 +149,73 @@ impl<DataObject> GuiHandler<DataObject> {
             renderer,
             gui_pointer: GUIWrapper::default(),
             draw_data: None,
+            last_shapes: Vec::new(),
+            needs_redraw: false,
+            gui_texture: None,
+            texture_format,
+            bind_group_layout,
+            render_pipeline,
         }
     }
 
+    ///the pipeline drawing the GUI texture on the screen
+    fn create_render_pipeline(
+        window: &super::Window,
+        graphic_context: &super::Context,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let shader = graphic_context
+            .wgpu_device
+            .create_shader_module(wgpu::include_wgsl!("gui.wgsl"));
+        let render_pipeline_layout =
+            graphic_context
+                .wgpu_device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("GUI Render Pipeline Layout"),
+                    bind_group_layouts: &[bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        graphic_context
+            .wgpu_device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("GUI Render Pipeline"),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: window.get_surface_config().format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                //the render pass has a depth buffer, but the GUI is always on top
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: super::Window::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: window.get_sample_count(),
+                    ..Default::default()
+                },
+                multiview: None,
+            })
+    }
+
     pub fn set_gui(&mut self, gui: GUIPointer<DataObject>) {
         self.gui_pointer.set_gui(gui);
     }
@@ -86,6 +225,7 @@ impl<DataObject> GuiHandler<DataObject> {
         response.consumed
     }
 
+    ///run the GUI, it is tessellated only if its shapes changed since the last update
     pub fn update_gui(
         &mut self,
         window: &super::Window,
@@ -93,6 +233,7 @@ impl<DataObject> GuiHandler<DataObject> {
         data_object: &mut DataObject,
     ) {
         let surface_config = window.get_surface_config();
+        let size = [surface_config.width, surface_config.height];
         let window = window.as_winit_window();
 
         let mut viewport_info = ViewportInfo::default();
@@ -119,27 +260,58 @@ impl<DataObject> GuiHandler<DataObject> {
                 image_delta,
             );
         }
-        let clipped_primitives = self.context.tessellate(shapes, pixels_per_point);
+
+        //the texture is recreated when the window is resized, a minimized window keeps the old one
+        let resized = self
+            .gui_texture
+            .as_ref()
+            .map_or(true, |texture| texture.size != size);
+        if resized && size[0] > 0 && size[1] > 0 {
+            self.gui_texture = Some(GuiTexture::new(
+                size,
+                self.texture_format,
+                &self.bind_group_layout,
+                graphic_context,
+            ));
+        }
 
         let screen_descriptor = ScreenDescriptor {
-            size_in_pixels: [surface_config.width, surface_config.height],
+            size_in_pixels: size,
             pixels_per_point,
         };
+        let unchanged = self.draw_data.as_ref().is_some_and(|draw_data| {
+            !resized
+                && textures_delta.set.is_empty()
+                && draw_data.screen_descriptor.pixels_per_point == pixels_per_point
+                && shapes == self.last_shapes
+        });
+        if unchanged {
+            return;
+        }
 
+        let clipped_primitives = self.context.tessellate(shapes.clone(), pixels_per_point);
+        self.last_shapes = shapes;
         self.draw_data = Some(DrawData {
             clipped_primitives,
             screen_descriptor,
         });
+        self.needs_redraw = true;
     }
 }
 
 impl<DataObject> RenderJob for GuiHandler<DataObject> {
+    ///render the GUI to its texture if it changed
     fn update(
         &mut self,
         command_encoder: &mut wgpu::CommandEncoder,
         graphic_context: &super::Context,
     ) {
-        let draw_data = self.draw_data.as_ref().unwrap();
+        let (Some(draw_data), Some(gui_texture)) = (&self.draw_data, &self.gui_texture) else {
+            return;
+        };
+        if !self.needs_redraw {
+            return;
+        }
         self.renderer.update_buffers(
             &graphic_context.wgpu_device,
             &graphic_context.wgpu_queue,
@@ -147,14 +319,36 @@ impl<DataObject> RenderJob for GuiHandler<DataObject> {
             &draw_data.clipped_primitives,
             &draw_data.screen_descriptor,
         );
-    }
 
-    fn draw<'pass>(&'pass mut self, render_pass: &mut wgpu::RenderPass<'pass>) {
-        let draw_data = self.draw_data.as_ref().unwrap();
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("GUI Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &gui_texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
         self.renderer.render(
-            render_pass,
+            &mut render_pass,
             &draw_data.clipped_primitives,
             &draw_data.screen_descriptor,
         );
+        drop(render_pass);
+        self.needs_redraw = false;
+    }
+
+    fn draw<'pass>(&'pass mut self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        let Some(gui_texture) = &self.gui_texture else {
+            return;
+        };
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &gui_texture.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
     }
 }
@@ -1,9 +1,15 @@
 mod app;
+mod chunk_generation;
+mod config;
 mod graphic;
 mod networking;
+mod prediction;
+mod time_of_day;
 use app::App;
+use config::ClientConfig;
 
 fn main() -> anyhow::Result<()> {
-    let (app, event_loop) = App::new()?;
+    let config = ClientConfig::load("client.cfg")?;
+    let (app, event_loop) = App::new(config)?;
     app.run(event_loop)
 }
@@ -1,58 +1,356 @@
-use rand::Rng;
-use renet::transport::{ClientAuthentication, NetcodeClientTransport, NetcodeTransportError};
-use renet::{DefaultChannel, RenetClient};
+use crate::config::{AuthenticationMode, ClientConfig};
+use math::positions::{BlockPos, ChunkPos, EntityPos};
+use networking::c2s::{PingPacket, PlayerMovePacket};
+use networking::packets::{ByteBuf, Dispatcher, Packet};
+use networking::s2c::{BlockBatchPacket, ChunkDataPacket, PlayerPositionPacket, PongPacket};
+use networking::socket::bind_udp_socket;
+use renet::transport::{
+    ClientAuthentication, ConnectToken, NetcodeClientTransport, NetcodeTransportError,
+};
+use renet::{DefaultChannel, DisconnectReason, RenetClient};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
 use std::net::{Ipv4Addr, SocketAddr};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use world_core::block_state::BlockState;
+use world_core::Chunk;
+
+///data given to the packet handlers
+pub struct PacketContext {
+    ///the chunks received since the last call to [`ClientNetworkHandler::take_received_chunks`]
+    pub received_chunks: Vec<Chunk>,
+    ///the blocks changed in each chunk, relative to the chunk, since the last call to [`ClientNetworkHandler::take_block_edits`]
+    pub block_edits: Vec<(ChunkPos, Vec<(BlockPos, BlockState)>)>,
+    ///the origin of the ping timestamps
+    pub start: Instant,
+    ///the round trip time smoothed over the last pongs
+    pub latency_ms: Option<f32>,
+    ///the newest move acknowledged by the server since the last call to [`ClientNetworkHandler::take_acknowledged_move`]
+    pub acknowledged_move: Option<(u32, EntityPos)>,
+}
+
+impl PacketContext {
+    fn new() -> Self {
+        Self {
+            received_chunks: Vec::new(),
+            block_edits: Vec::new(),
+            start: Instant::now(),
+            latency_ms: None,
+            acknowledged_move: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+///a change of the connection state, see [`ClientNetworkHandler::poll_events`]
+pub enum NetEvent {
+    Connected,
+    ///a new connection attempt started after a disconnection
+    Reconnecting,
+    ///the reason is None if renet doesn't know it
+    Disconnected {
+        reason: Option<DisconnectReason>,
+    },
+}
+
+///an error of the networking layer, only the fatal ones should stop the client
+#[derive(Debug)]
+pub enum NetworkError {
+    ///the packet or the connection may be lost, but the client will reconnect by itself
+    Recoverable(NetcodeTransportError),
+    ///the networking can't work anymore, like when the socket can't be used
+    Fatal(anyhow::Error),
+}
+
+impl Error for NetworkError {}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Recoverable(e) => write!(f, "recoverable network error: {}", e),
+            NetworkError::Fatal(e) => write!(f, "fatal network error: {}", e),
+        }
+    }
+}
+
+impl From<NetcodeTransportError> for NetworkError {
+    fn from(error: NetcodeTransportError) -> Self {
+        //the protocol errors only lose the connection, but a broken socket won't come back
+        let is_fatal = match &error {
+            NetcodeTransportError::IO(e) => !matches!(
+                e.kind(),
+                ErrorKind::WouldBlock
+                    | ErrorKind::Interrupted
+                    | ErrorKind::TimedOut
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionAborted
+            ),
+            _ => false,
+        };
+        if is_fatal {
+            NetworkError::Fatal(error.into())
+        } else {
+            NetworkError::Recoverable(error)
+        }
+    }
+}
 
 pub struct ClientNetworkHandler {
     packet_transporter: NetcodeClientTransport,
     renet_client: RenetClient,
+    dispatcher: Dispatcher<PacketContext>,
+    context: PacketContext,
+    state: ConnectionState,
+    events: Vec<NetEvent>,
+    server_addr: SocketAddr,
+    config: ClientConfig,
+    ///time spent disconnected since the last connection attempt
+    reconnect_timer: Duration,
+    ///time since the last ping
+    ping_timer: Duration,
 }
 
 impl ClientNetworkHandler {
-    pub fn new(server_addr: SocketAddr) -> anyhow::Result<Self> {
-        let udp_socket = std::net::UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+    ///delay between two connection attempts when the connection is lost
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+    ///delay between two pings to measure the latency
+    const PING_DELAY: Duration = Duration::from_secs(1);
+
+    pub fn new(server_addr: SocketAddr, config: &ClientConfig) -> anyhow::Result<Self> {
+        let (packet_transporter, renet_client) = Self::connect(server_addr, config)?;
+
+        let mut dispatcher = Dispatcher::new();
+        Self::register_handlers(&mut dispatcher);
+
+        Ok(Self {
+            packet_transporter,
+            renet_client,
+            dispatcher,
+            context: PacketContext::new(),
+            state: ConnectionState::Connecting,
+            events: Vec::new(),
+            server_addr,
+            config: config.clone(),
+            reconnect_timer: Duration::ZERO,
+            ping_timer: Duration::ZERO,
+        })
+    }
+
+    fn connect(
+        server_addr: SocketAddr,
+        config: &ClientConfig,
+    ) -> anyhow::Result<(NetcodeClientTransport, RenetClient)> {
+        let udp_socket = bind_udp_socket(
+            SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            config.socket_recv_buffer_size,
+            config.socket_send_buffer_size,
+        )?;
         let current_time = std::time::SystemTime::now()
             .duration_since(std::time::SystemTime::UNIX_EPOCH)
             .unwrap();
 
-        let authentication = ClientAuthentication::Unsecure {
-            server_addr,
-            client_id: rand::thread_rng().gen_range(0..u64::MAX),
-            user_data: None,
-            protocol_id: 0,
+        let authentication = match config.authentication {
+            AuthenticationMode::Unsecure => ClientAuthentication::Unsecure {
+                server_addr,
+                client_id: config.client_id,
+                user_data: None,
+                protocol_id: config.protocol_id,
+            },
+            AuthenticationMode::Secure { ref connect_token } => {
+                let connect_token = ConnectToken::read(&mut std::fs::File::open(connect_token)?)?;
+                if connect_token.protocol_id != config.protocol_id {
+                    anyhow::bail!(
+                        "the connect token is for the protocol {}, but the client uses the protocol {}",
+                        connect_token.protocol_id,
+                        config.protocol_id
+                    );
+                }
+                ClientAuthentication::Secure { connect_token }
+            }
         };
 
         let packet_transporter =
             NetcodeClientTransport::new(current_time, authentication, udp_socket)?;
         let renet_client = RenetClient::new(Default::default());
+        Ok((packet_transporter, renet_client))
+    }
 
-        Ok(Self {
-            packet_transporter,
-            renet_client,
-        })
+    fn register_handlers(dispatcher: &mut Dispatcher<PacketContext>) {
+        dispatcher.register_handler(|packet: ChunkDataPacket, context: &mut PacketContext| {
+            match Chunk::deserialize(packet.pos, &packet.data) {
+                Ok(chunk) => context.received_chunks.push(chunk),
+                Err(e) => println!("malformed chunk received at {}: {}", packet.pos, e),
+            }
+        });
+        dispatcher.register_handler(|packet: BlockBatchPacket, context: &mut PacketContext| {
+            let delta = packet
+                .changes
+                .into_iter()
+                .map(|(index, state)| (Chunk::block_pos(index), BlockState::new(state)))
+                .collect();
+            context.block_edits.push((packet.chunk, delta));
+        });
+        dispatcher.register_handler(
+            |packet: PlayerPositionPacket, context: &mut PacketContext| {
+                //the acknowledgements can arrive out of order, only the newest one matters
+                let is_newer = match context.acknowledged_move {
+                    Some((sequence, _)) => packet.sequence > sequence,
+                    None => true,
+                };
+                if is_newer {
+                    context.acknowledged_move = Some((packet.sequence, packet.pos));
+                }
+            },
+        );
+        dispatcher.register_handler(|packet: PongPacket, context: &mut PacketContext| {
+            let now = context.start.elapsed().as_micros() as u64;
+            let round_trip_ms = now.saturating_sub(packet.timestamp) as f32 / 1000.0;
+            //exponential moving average, to not display the jitter
+            context.latency_ms = Some(match context.latency_ms {
+                Some(latency_ms) => latency_ms * 0.875 + round_trip_ms * 0.125,
+                None => round_trip_ms,
+            });
+        });
+    }
+
+    ///the smoothed round trip time to the server, None when not connected
+    pub fn latency_ms(&self) -> Option<f32> {
+        if self.state == ConnectionState::Connected {
+            self.context.latency_ms
+        } else {
+            None
+        }
     }
 
-    pub fn tick(&mut self, delta_time: Duration) -> Result<(), NetcodeTransportError> {
+    ///return the chunks received from the server since the last call, they have to be inserted in the world
+    pub fn take_received_chunks(&mut self) -> Vec<Chunk> {
+        std::mem::take(&mut self.context.received_chunks)
+    }
+
+    ///return the sequence of the newest move acknowledged by the server since the last call, with the position of the player after it
+    pub fn take_acknowledged_move(&mut self) -> Option<(u32, EntityPos)> {
+        self.context.acknowledged_move.take()
+    }
+
+    ///return the blocks changed by the server since the last call, to apply on the chunks with [`Chunk::apply_delta`]
+    pub fn take_block_edits(&mut self) -> Vec<(ChunkPos, Vec<(BlockPos, BlockState)>)> {
+        std::mem::take(&mut self.context.block_edits)
+    }
+
+    ///a recoverable error doesn't stop the handler, it can be ticked again
+    pub fn tick(&mut self, delta_time: Duration) -> Result<(), NetworkError> {
+        if self.state == ConnectionState::Disconnected {
+            return self.try_reconnect(delta_time);
+        }
+
         self.renet_client.update(delta_time);
-        self.packet_transporter
-            .update(delta_time, &mut self.renet_client)?;
+        let result = self
+            .packet_transporter
+            .update(delta_time, &mut self.renet_client);
+        self.update_connection_state(); //the error may have closed the connection
+        result?;
         self.process_packet();
+        self.send_ping(delta_time);
         self.packet_transporter
             .send_packets(&mut self.renet_client)?;
         Ok(())
     }
 
+    ///open a new connection once the reconnection delay is elapsed
+    fn try_reconnect(&mut self, delta_time: Duration) -> Result<(), NetworkError> {
+        self.reconnect_timer += delta_time;
+        if self.reconnect_timer < Self::RECONNECT_DELAY {
+            return Ok(());
+        }
+        self.reconnect_timer = Duration::ZERO;
+
+        let (packet_transporter, renet_client) =
+            Self::connect(self.server_addr, &self.config).map_err(NetworkError::Fatal)?;
+        self.packet_transporter = packet_transporter;
+        self.renet_client = renet_client;
+        self.state = ConnectionState::Connecting;
+        self.events.push(NetEvent::Reconnecting);
+        Ok(())
+    }
+
+    fn update_connection_state(&mut self) {
+        let state = if self.renet_client.is_connected() {
+            ConnectionState::Connected
+        } else if self.renet_client.is_disconnected() {
+            ConnectionState::Disconnected
+        } else {
+            ConnectionState::Connecting
+        };
+        if state == self.state {
+            return;
+        }
+
+        self.state = state;
+        match state {
+            ConnectionState::Connected => self.events.push(NetEvent::Connected),
+            ConnectionState::Disconnected => {
+                self.reconnect_timer = Duration::ZERO;
+                self.context.latency_ms = None;
+                self.events.push(NetEvent::Disconnected {
+                    reason: self.renet_client.disconnect_reason(),
+                });
+            }
+            ConnectionState::Connecting => (),
+        }
+    }
+
+    ///return the connection events that happened since the last call
+    pub fn poll_events(&mut self) -> Vec<NetEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     pub fn process_packet(&mut self) {
         if self.renet_client.is_connected() {
-            while let Some(_message) = self
-                .renet_client
-                .receive_message(DefaultChannel::ReliableOrdered)
-            {
-                //process incoming packets
+            let channels = [DefaultChannel::ReliableOrdered, DefaultChannel::Unreliable];
+            for channel in channels {
+                while let Some(message) = self.renet_client.receive_message(channel) {
+                    let data = message.to_vec().into_boxed_slice();
+                    self.dispatcher.dispatch_packet(data, &mut self.context);
+                }
+            }
+        }
+    }
+
+    fn send_ping(&mut self, delta_time: Duration) {
+        self.ping_timer += delta_time;
+        if self.ping_timer < Self::PING_DELAY || !self.renet_client.is_connected() {
+            return;
+        }
+        self.ping_timer = Duration::ZERO;
+
+        let timestamp = self.context.start.elapsed().as_micros() as u64;
+        let packet: ByteBuf = PingPacket { timestamp }.serialize().into();
+        self.renet_client
+            .send_message(DefaultChannel::Unreliable, packet);
+    }
+
+    ///send the position of the player to the server, it is used to know which chunks to send
+    ///the packet is unreliable since a new one is sent every tick, the sequence is the one of the last move included in the position
+    pub fn send_player_move(&mut self, sequence: u32, pos: EntityPos, yaw: f32, pitch: f32) {
+        if self.renet_client.is_connected() {
+            let packet: ByteBuf = PlayerMovePacket {
+                sequence,
+                pos,
+                yaw,
+                pitch,
             }
+            .serialize()
+            .into();
             self.renet_client
-                .send_message(DefaultChannel::Unreliable, "test");
+                .send_message(DefaultChannel::Unreliable, packet);
         }
     }
 
@@ -0,0 +1,124 @@
+use math::positions::EntityPos;
+use math::{DVec3, Vec3};
+use std::collections::VecDeque;
+
+///a move of the player applied locally but not yet acknowledged by the server
+struct PredictedMove {
+    sequence: u32,
+    displacement: Vec3,
+}
+
+///the moves of the player are applied immediately, then reconciled with the position acknowledged by the server
+///the server position is the position after the acknowledged move, so the newer moves are replayed on top of it
+pub struct MovementPrediction {
+    ///the moves not acknowledged yet, the oldest first
+    pending: VecDeque<PredictedMove>,
+    last_sequence: u32,
+}
+
+impl MovementPrediction {
+    ///the number of moves kept, around 2 seconds at 60 fps, the older moves are forgotten
+    const CAPACITY: usize = 128;
+    ///the distance in blocks under which the predicted position is kept, to not snap the camera because of the rounding
+    const TOLERANCE: f64 = 0.01;
+
+    pub fn new() -> Self {
+        Self {
+            pending: VecDeque::with_capacity(Self::CAPACITY),
+            last_sequence: 0,
+        }
+    }
+
+    ///record a move applied locally and return its sequence, the sequences start at 1
+    pub fn record(&mut self, displacement: Vec3) -> u32 {
+        self.last_sequence += 1;
+        if self.pending.len() == Self::CAPACITY {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(PredictedMove {
+            sequence: self.last_sequence,
+            displacement,
+        });
+        self.last_sequence
+    }
+
+    ///the sequence of the last recorded move, the position sent to the server includes all the moves up to it
+    pub fn last_sequence(&self) -> u32 {
+        self.last_sequence
+    }
+
+    ///forget the moves acknowledged by the server and replay the others on top of its position
+    ///return the corrected position if it is too far from the current one, None if the prediction was right
+    ///the acknowledgement is also ignored if it is older than the oldest move kept, since the moves in between are lost
+    pub fn reconcile(
+        &mut self,
+        sequence: u32,
+        server_pos: EntityPos,
+        current_pos: EntityPos,
+    ) -> Option<EntityPos> {
+        if sequence > self.last_sequence {
+            return None; //the server can't know a move that wasn't sent
+        }
+        if let Some(oldest) = self.pending.front() {
+            if sequence + 1 < oldest.sequence {
+                return None;
+            }
+        }
+        while self
+            .pending
+            .front()
+            .is_some_and(|predicted| predicted.sequence <= sequence)
+        {
+            self.pending.pop_front();
+        }
+
+        let mut predicted_pos = server_pos;
+        for predicted in &self.pending {
+            predicted_pos += predicted.displacement;
+        }
+        predicted_pos.try_shrink();
+
+        let error = DVec3::from(predicted_pos).distance(DVec3::from(current_pos));
+        (error > Self::TOLERANCE).then_some(predicted_pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prediction::MovementPrediction;
+    use math::positions::{ChunkPos, EntityPos};
+    use math::Vec3;
+
+    #[test]
+    pub fn the_unacknowledged_moves_are_replayed() {
+        let mut prediction = MovementPrediction::new();
+        let start = EntityPos::new(ChunkPos::ZERO, Vec3::new(1.0, 2.0, 3.0));
+        let mut current = start;
+        for _ in 0..3 {
+            prediction.record(Vec3::X);
+            current += Vec3::X;
+        }
+        assert_eq!(prediction.last_sequence(), 3);
+
+        //the server agrees with the first move, the two others are still predicted
+        assert_eq!(prediction.reconcile(1, start + Vec3::X, current), None);
+
+        //the server moved the player back to the start on the second move
+        let corrected = prediction.reconcile(2, start, current).unwrap();
+        assert_eq!(corrected, start + Vec3::X);
+    }
+
+    #[test]
+    pub fn the_acknowledgements_of_forgotten_moves_are_ignored() {
+        let mut prediction = MovementPrediction::new();
+        let start = EntityPos::new(ChunkPos::ZERO, Vec3::ZERO);
+        for _ in 0..MovementPrediction::CAPACITY + 10 {
+            prediction.record(Vec3::Y);
+        }
+        let current = start + Vec3::Y * 8.0;
+
+        assert_eq!(prediction.reconcile(5, start, current), None);
+        assert_eq!(prediction.reconcile(1000, start, current), None);
+        assert!(prediction.reconcile(11, start, current).is_some());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::graphic::terrain::Light;
+use math::Vec3;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+///the ambient light of the night and of the day, the ambient light goes from one to the other with the daylight
+const NIGHT_AMBIENT: f32 = 0.08;
+const DAY_AMBIENT: f32 = 0.3;
+///the intensity of the sun at noon
+const SUN_INTENSITY: f32 = 0.7;
+
+///the time in the day, it cycles over the day length and drives the sun and the sky brightness
+///the time is a fraction of the day: 0 is midnight, 0.25 the sunrise, 0.5 noon and 0.75 the sunset
+pub struct TimeOfDay {
+    time: f32,
+    ///the real duration of a whole day
+    pub day_length: Duration,
+}
+
+impl TimeOfDay {
+    pub fn new(day_length: Duration) -> Self {
+        Self {
+            time: 0.5,
+            day_length,
+        }
+    }
+
+    ///move the time forward, a null day length freezes the time
+    pub fn advance(&mut self, delta_time: Duration) {
+        if self.day_length.is_zero() {
+            return;
+        }
+        let elapsed = delta_time.as_secs_f32() / self.day_length.as_secs_f32();
+        self.time = (self.time + elapsed).rem_euclid(1.0);
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    ///set the time, as a fraction of the day, it is wrapped in [0, 1)
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.rem_euclid(1.0);
+    }
+
+    ///the direction pointing to the sun, the sun rises at x+ and sets at x-
+    ///it is slightly tilted to z+ so the north and south faces aren't lit the same way
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.time - 0.25) * TAU;
+        Vec3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+
+    ///0 at night, 1 at day, it goes from one to the other while the sun is close to the horizon
+    pub fn daylight(&self) -> f32 {
+        ((self.sun_direction().y + 0.1) / 0.4).clamp(0.0, 1.0)
+    }
+
+    ///the light of the sun, warmer at the sunrise and the sunset
+    pub fn light(&self) -> Light {
+        let daylight = self.daylight();
+        let intensity = SUN_INTENSITY * daylight;
+        let ambient = NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * daylight;
+        Light {
+            direction: self.sun_direction(),
+            color: [
+                intensity,
+                intensity * (0.8 + 0.2 * daylight),
+                intensity * (0.6 + 0.4 * daylight),
+            ],
+            ambient_color: [ambient, ambient, ambient * 1.2],
+        }
+    }
+
+    ///the factor applied to the colors of the sky
+    pub fn sky_brightness(&self) -> f32 {
+        0.05 + 0.95 * self.daylight()
+    }
+}
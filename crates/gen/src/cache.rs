@@ -0,0 +1,159 @@
+use crate::WorldGenerator;
+use math::consts::CHUNK_SIZE;
+use math::positions::ChunkPos;
+use math::IVec3;
+use std::collections::HashMap;
+
+const BLOCK_COUNT: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+struct CachedChunk {
+    last_use: u64,
+    blocks: Box<[i32; BLOCK_COUNT]>,
+}
+
+///keep the last generated chunks in memory, so the blocks asked many times are generated once
+///a miss generates the whole chunk of the block, it is faster when the blocks are asked chunk by chunk, like the meshing or the world generation does
+///[`crate::generate_chunk`] reads a chunk once, so its blocks only hit when the chunk is generated again, like when the terrain is regenerated
+///the least recently used chunk is dropped when the cache is full
+pub struct CachedGenerator<G: WorldGenerator> {
+    generator: G,
+    chunks: HashMap<ChunkPos, CachedChunk>,
+    capacity: usize,
+    date: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<G: WorldGenerator> CachedGenerator<G> {
+    ///capacity is the number of chunks kept in memory, each one uses 16 KB
+    pub fn new(generator: G, capacity: usize) -> Self {
+        assert!(capacity > 0, "the cache must hold at least one chunk");
+        Self {
+            generator,
+            chunks: HashMap::with_capacity(capacity),
+            capacity,
+            date: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    ///the number of blocks found in the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    ///the number of blocks whose chunk had to be generated
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    ///forget the cached chunks, the counters are kept
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    fn generate_chunk(&mut self, chunk_pos: ChunkPos) -> Box<[i32; BLOCK_COUNT]> {
+        let origin = chunk_pos * CHUNK_SIZE;
+        let mut blocks = Box::new([0; BLOCK_COUNT]);
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    blocks[(x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE) as usize] = self
+                        .generator
+                        .get_block(origin.x + x, origin.y + y, origin.z + z);
+                }
+            }
+        }
+        blocks
+    }
+
+    fn remove_least_recently_used(&mut self) {
+        let oldest = self
+            .chunks
+            .iter()
+            .min_by_key(|(_, chunk)| chunk.last_use)
+            .map(|(pos, _)| *pos);
+        if let Some(oldest) = oldest {
+            self.chunks.remove(&oldest);
+        }
+    }
+}
+
+impl<G: WorldGenerator> WorldGenerator for CachedGenerator<G> {
+    fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32 {
+        let pos = IVec3::new(x, y, z);
+        let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE));
+        let local_pos = pos.rem_euclid(IVec3::splat(CHUNK_SIZE));
+        let index = (local_pos.x + local_pos.y * CHUNK_SIZE + local_pos.z * CHUNK_SIZE * CHUNK_SIZE)
+            as usize;
+
+        self.date += 1;
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+            self.hits += 1;
+            chunk.last_use = self.date;
+            return chunk.blocks[index];
+        }
+
+        self.misses += 1;
+        if self.chunks.len() >= self.capacity {
+            self.remove_least_recently_used();
+        }
+        let blocks = self.generate_chunk(chunk_pos);
+        let block = blocks[index];
+        self.chunks.insert(
+            chunk_pos,
+            CachedChunk {
+                last_use: self.date,
+                blocks,
+            },
+        );
+        block
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CachedGenerator, NoiseGenerator, WorldGenerator};
+
+    ///count the calls to the generator
+    struct CountingGenerator {
+        calls: usize,
+    }
+
+    impl WorldGenerator for CountingGenerator {
+        fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32 {
+            self.calls += 1;
+            x + y + z
+        }
+    }
+
+    #[test]
+    pub fn a_chunk_is_generated_once() {
+        let mut generator = CachedGenerator::new(CountingGenerator { calls: 0 }, 2);
+        for x in 0..16 {
+            assert_eq!(generator.get_block(x, -1, 5), x + 4);
+        }
+        assert_eq!(generator.generator.calls, 16 * 16 * 16);
+        assert_eq!((generator.hits(), generator.misses()), (15, 1));
+
+        //the chunk (0, -1, 0) is the least recently used when the third chunk is generated
+        generator.get_block(16, 0, 0);
+        generator.get_block(0, 0, 0);
+        generator.get_block(32, 0, 0);
+        generator.get_block(0, 0, 0);
+        assert_eq!(generator.misses(), 4);
+        generator.get_block(0, -1, 0);
+        assert_eq!(generator.misses(), 5);
+    }
+
+    #[test]
+    pub fn the_cache_gives_the_generated_blocks() {
+        let mut generator = NoiseGenerator::new(7);
+        let mut cached = CachedGenerator::new(NoiseGenerator::new(7), 4);
+        for (x, y, z) in [(0, 0, 0), (-1, -30, 17), (100, 5, -100), (-1, -29, 17)] {
+            assert_eq!(cached.get_block(x, y, z), generator.get_block(x, y, z));
+        }
+        assert_eq!(cached.hits(), 1);
+    }
+}
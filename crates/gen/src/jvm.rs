@@ -0,0 +1,120 @@
+use crate::WorldGenerator;
+use jni::objects::{JMethodID, JObject, JValue};
+use jni::signature::{Primitive, ReturnType};
+use jni::sys::jvalue;
+use jni::{InitArgsBuilder, JNIEnv, JNIVersion, JavaVM};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+///the JVM is only started by the first Generator, the native generators don't need it
+static JVM: OnceLock<Result<JavaVM, String>> = OnceLock::new();
+
+fn jvm() -> anyhow::Result<&'static JavaVM> {
+    let jvm = JVM.get_or_init(|| {
+        let jvm_args = InitArgsBuilder::new()
+            .version(JNIVersion::V8)
+            //.option("-Xcheck:jni")
+            .build()
+            .map_err(|e| e.to_string())?;
+        JavaVM::new(jvm_args).map_err(|e| e.to_string())
+    });
+    jvm.as_ref()
+        .map_err(|e| anyhow::anyhow!("can't start the JVM: {}", e))
+}
+
+///the classes of the jar are defined once in the JVM, defining them again is a LinkageError
+///the path of the jar loaded, the generators created after it share its classes
+static LOADED_JAR: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+///the generator of the Java project, it requires a JVM (java 17) and the jar of the project
+pub struct Generator<'a> {
+    generator_java_instance: JObject<'a>,
+    get_block_method: JMethodID,
+}
+
+impl<'a> Generator<'a> {
+    fn load_jar(env: &mut JNIEnv, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = std::fs::File::open(path)?;
+
+        let mut archive = zip::ZipArchive::new(file)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let file_name = file.name();
+            if !file_name.ends_with(".class") {
+                continue;
+            }
+
+            let java_path = file_name.replace(".class", "");
+            println!("loading: {}", java_path);
+
+            let mut class_data = Vec::new();
+            file.read_to_end(&mut class_data)?;
+            env.define_class(&java_path, &JObject::null(), &class_data)?;
+        }
+        Ok(())
+    }
+
+    ///load the jar in the JVM the first time, the next calls only check they ask for the same jar
+    fn load_jar_once(env: &mut JNIEnv, path: &Path) -> anyhow::Result<()> {
+        let loaded = LOADED_JAR.get_or_init(|| {
+            Self::load_jar(env, path)
+                .map(|()| path.to_path_buf())
+                .map_err(|e| e.to_string())
+        });
+        match loaded {
+            Ok(loaded) if loaded == path => Ok(()),
+            Ok(loaded) => anyhow::bail!(
+                "the jar {} is already loaded in the JVM, can't load {}",
+                loaded.display(),
+                path.display()
+            ),
+            Err(e) => anyhow::bail!("can't load the jar: {}", e),
+        }
+    }
+
+    ///the jar is only loaded by the first call, so each thread can have its own generator
+    pub fn new(path: impl AsRef<Path>, seed: i64) -> anyhow::Result<Self> {
+        let jvm = jvm()?;
+        jvm.attach_current_thread_as_daemon()?;
+
+        let mut env = jvm.get_env()?;
+
+        Self::load_jar_once(&mut env, path.as_ref())?;
+
+        let generator_class = env.find_class("org/archipel/generator/Generator")?;
+        let jvalue = JValue::from(seed);
+        let generator_java_instance = env.new_object(&generator_class, "(J)V", &[jvalue])?;
+        let get_block_method = env.get_method_id(generator_class, "getBlock", "(III)I")?;
+
+        Ok(Self {
+            generator_java_instance,
+            get_block_method,
+        })
+    }
+
+    pub fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32 {
+        let jvm = jvm().expect("the JVM is started by Generator::new");
+        let mut env = jvm.get_env().unwrap();
+        unsafe {
+            let x = jvalue { i: x };
+            let y = jvalue { i: y };
+            let z = jvalue { i: z };
+            env.call_method_unchecked(
+                &self.generator_java_instance,
+                self.get_block_method,
+                ReturnType::Primitive(Primitive::Int),
+                &[x, y, z],
+            )
+            .unwrap()
+            .i()
+            .unwrap()
+        }
+    }
+}
+
+impl WorldGenerator for Generator<'_> {
+    fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32 {
+        Generator::get_block(self, x, y, z)
+    }
+}
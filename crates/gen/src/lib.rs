@@ -1,85 +1,84 @@
 #![doc = include_str!("../README.md")]
 
-use ctor::ctor;
-use jni::objects::{JMethodID, JObject, JValue};
-use jni::signature::{Primitive, ReturnType};
-use jni::sys::jvalue;
-use jni::{InitArgsBuilder, JNIEnv, JNIVersion, JavaVM};
-use std::io::Read;
-use std::path::Path;
+mod cache;
+#[cfg(feature = "jvm")]
+mod jvm;
+mod noise;
 
-#[ctor]
-static JVM: JavaVM = {
-    let jvm_args = InitArgsBuilder::new()
-        .version(JNIVersion::V8)
-        //.option("-Xcheck:jni")
-        .build()
-        .unwrap();
+pub use cache::CachedGenerator;
+#[cfg(feature = "jvm")]
+pub use jvm::Generator;
+pub use noise::NoiseGenerator;
 
-    let jvm = JavaVM::new(jvm_args).unwrap();
-    jvm
-};
+use math::consts::CHUNK_SIZE;
+use math::positions::ChunkPos;
+use std::path::Path;
+use world_core::block_state::{BlockState, AIR};
+use world_core::Chunk;
 
-pub struct Generator<'a> {
-    generator_java_instance: JObject<'a>,
-    get_block_method: JMethodID,
+///give the block state at each position of the world, the invalid states are replaced by air by the callers
+pub trait WorldGenerator {
+    fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32;
 }
 
-impl<'a> Generator<'a> {
-    fn load_jar(env: &mut JNIEnv, path: impl AsRef<Path>) -> anyhow::Result<()> {
-        let file = std::fs::File::open(path)?;
+///the generator of the Java project if the jar and the JVM are available, else the native port of it
+///without the jvm feature, the native generator is always used
+pub fn load_generator(jar_path: Option<&Path>, seed: i64) -> Box<dyn WorldGenerator> {
+    #[cfg(feature = "jvm")]
+    if let Some(jar_path) = jar_path {
+        match Generator::new(jar_path, seed) {
+            Ok(generator) => return Box::new(generator),
+            Err(e) => println!("can't load the java generator, using the native one: {}", e),
+        }
+    }
+    #[cfg(not(feature = "jvm"))]
+    let _ = jar_path;
+    Box::new(NoiseGenerator::new(seed))
+}
 
-        let mut archive = zip::ZipArchive::new(file)?;
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).unwrap();
-            let file_name = file.name();
-            if !file_name.ends_with(".class") {
-                continue;
+///generate all the blocks of a chunk, the invalid states given by the generator are replaced by air
+pub fn generate_chunk(generator: &mut dyn WorldGenerator, pos: ChunkPos) -> Chunk {
+    let mut blocks = [AIR; Chunk::BLOCK_COUNT];
+    let origin = pos * CHUNK_SIZE;
+    for z in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let block = generator.get_block(origin.x + x, origin.y + y, origin.z + z);
+                blocks[(x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE) as usize] =
+                    BlockState::try_from(block).unwrap_or(AIR);
             }
-
-            let java_path = file_name.replace(".class", "");
-            println!("loading: {}", java_path);
-
-            let mut class_data = Vec::new();
-            file.read_to_end(&mut class_data)?;
-            env.define_class(&java_path, &JObject::null(), &class_data)?;
         }
-        Ok(())
     }
+    Chunk::from_block_array(pos, &blocks)
+}
 
-    pub fn new(path: impl AsRef<Path>, seed: i64) -> anyhow::Result<Self> {
-        JVM.attach_current_thread_as_daemon().unwrap();
-
-        let mut env = JVM.get_env()?;
-
-        Self::load_jar(&mut env, path)?;
+#[cfg(test)]
+mod test {
+    use crate::{generate_chunk, WorldGenerator};
+    use math::positions::{BlockPos, ChunkPos};
+    use world_core::block_state::{BlockState, AIR};
 
-        let generator_class = env.find_class("org/archipel/generator/Generator")?;
-        let jvalue = JValue::from(seed);
-        let generator_java_instance = env.new_object(&generator_class, "(J)V", &[jvalue])?;
-        let get_block_method = env.get_method_id(generator_class, "getBlock", "(III)I")?;
+    ///stone under y = 0, an invalid state above
+    struct FlatGenerator;
 
-        Ok(Self {
-            generator_java_instance,
-            get_block_method,
-        })
+    impl WorldGenerator for FlatGenerator {
+        fn get_block(&mut self, _x: i32, y: i32, _z: i32) -> i32 {
+            if y < 0 {
+                1
+            } else {
+                -1
+            }
+        }
     }
 
-    pub fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32 {
-        let mut env = JVM.get_env().unwrap();
-        unsafe {
-            let x = jvalue { i: x };
-            let y = jvalue { i: y };
-            let z = jvalue { i: z };
-            env.call_method_unchecked(
-                &self.generator_java_instance,
-                self.get_block_method,
-                ReturnType::Primitive(Primitive::Int),
-                &[x, y, z],
-            )
-            .unwrap()
-            .i()
-            .unwrap()
-        }
+    #[test]
+    pub fn the_chunk_has_the_blocks_of_the_generator() {
+        let pos = ChunkPos::new(2, -1, 3);
+        let chunk = generate_chunk(&mut FlatGenerator, pos);
+        assert_eq!(chunk.position(), pos);
+        assert_eq!(chunk.get_block(BlockPos::new(5, 7, 9)), BlockState::new(1));
+
+        let chunk = generate_chunk(&mut FlatGenerator, ChunkPos::new(0, 0, 0));
+        assert_eq!(chunk.get_block(BlockPos::new(5, 7, 9)), AIR);
     }
 }
@@ -0,0 +1,201 @@
+use crate::WorldGenerator;
+
+///the random generator of java (java.util.Random), needed to shuffle the permutations like the Java project
+struct JavaRandom {
+    seed: i64,
+}
+
+impl JavaRandom {
+    const MULTIPLIER: i64 = 0x5DEECE66D;
+    const MASK: i64 = (1 << 48) - 1;
+
+    fn new(seed: i64) -> Self {
+        Self {
+            seed: (seed ^ Self::MULTIPLIER) & Self::MASK,
+        }
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.seed = (self.seed.wrapping_mul(Self::MULTIPLIER).wrapping_add(0xB)) & Self::MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+
+    ///a random number in [0, bound[, bound must be positive
+    fn next_int(&mut self, bound: i32) -> i32 {
+        debug_assert!(bound > 0);
+        if bound & (bound - 1) == 0 {
+            return ((bound as i64 * self.next(31) as i64) >> 31) as i32;
+        }
+        loop {
+            let bits = self.next(31);
+            let value = bits % bound;
+            //reject the last incomplete range, like java does with the overflow of the int
+            if bits
+                .checked_sub(value)
+                .and_then(|n| n.checked_add(bound - 1))
+                .is_some()
+            {
+                return value;
+            }
+        }
+    }
+}
+
+///the 2d Perlin noise of the Java project
+struct PerlinNoise {
+    permutations: [u8; 512],
+}
+
+impl PerlinNoise {
+    fn new(random: &mut JavaRandom) -> Self {
+        //same as Collections.shuffle
+        let mut list: [u8; 256] = std::array::from_fn(|i| i as u8);
+        for i in (2..=list.len()).rev() {
+            let j = random.next_int(i as i32) as usize;
+            list.swap(i - 1, j);
+        }
+        Self {
+            permutations: std::array::from_fn(|i| list[i % 256]),
+        }
+    }
+
+    fn fade(t: f32) -> f32 {
+        ((6.0 * t - 15.0) * t + 10.0) * t * t * t
+    }
+
+    fn lerp(t: f32, a: f32, b: f32) -> f32 {
+        a + t * (b - a)
+    }
+
+    fn constant_vector(value: u8) -> (f32, f32) {
+        match value & 3 {
+            0 => (1.0, 1.0),
+            1 => (-1.0, 1.0),
+            2 => (-1.0, -1.0),
+            _ => (1.0, -1.0),
+        }
+    }
+
+    fn dot(a: (f32, f32), b: (f32, f32)) -> f32 {
+        a.0 * b.0 + a.1 * b.1
+    }
+
+    fn noise2d(&self, x: f32, y: f32) -> f32 {
+        let floor_x = (x as i32) - (x < (x as i32) as f32) as i32;
+        let floor_y = (y as i32) - (y < (y as i32) as f32) as i32;
+        let cell_x = (floor_x & 255) as usize;
+        let cell_y = (floor_y & 255) as usize;
+        let xf = x - floor_x as f32;
+        let yf = y - floor_y as f32;
+
+        let p = &self.permutations;
+        let value = |dx: usize, dy: usize| p[p[cell_x + dx] as usize + cell_y + dy];
+        let dot_top_right = Self::dot((xf - 1.0, yf - 1.0), Self::constant_vector(value(1, 1)));
+        let dot_top_left = Self::dot((xf, yf - 1.0), Self::constant_vector(value(0, 1)));
+        let dot_bottom_right = Self::dot((xf - 1.0, yf), Self::constant_vector(value(1, 0)));
+        let dot_bottom_left = Self::dot((xf, yf), Self::constant_vector(value(0, 0)));
+
+        let u = Self::fade(xf);
+        let v = Self::fade(yf);
+        Self::lerp(
+            u,
+            Self::lerp(v, dot_bottom_left, dot_top_left),
+            Self::lerp(v, dot_bottom_right, dot_top_right),
+        )
+    }
+
+    fn fractal_brownian_motion(&self, x: f32, y: f32, octave_count: u32) -> f32 {
+        let mut result = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 0.005;
+        for _ in 0..octave_count {
+            result += amplitude * self.noise2d(x * frequency, y * frequency);
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        result
+    }
+}
+
+///a port of the generator of the Java project, it doesn't need a JVM
+///the computations are done in f32 in the same order, so a seed gives the same world as the Java generator
+pub struct NoiseGenerator {
+    noise: PerlinNoise,
+}
+
+impl NoiseGenerator {
+    const INPUT_FACTOR: f32 = 1.018_126_9; //1.0181268882175227 in the Java project, rounded to the same f32
+    const STONE_LEVEL: i32 = -39;
+    const SEA_LEVEL: i32 = -14;
+    const SNOW_LEVEL: i32 = 22;
+
+    pub fn new(seed: i64) -> Self {
+        Self {
+            noise: PerlinNoise::new(&mut JavaRandom::new(seed)),
+        }
+    }
+
+    ///the height of the surface of the column
+    pub fn surface_level(&self, x: i32, z: i32) -> i32 {
+        let noise = self.noise.fractal_brownian_motion(
+            x as f32 * Self::INPUT_FACTOR,
+            z as f32 * Self::INPUT_FACTOR,
+            8,
+        );
+        //Math.round of java, the halves are rounded up
+        ((noise * 35.0) as f64 + 0.5).floor() as i32
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn get_block(&mut self, x: i32, y: i32, z: i32) -> i32 {
+        let surface_level = self.surface_level(x, z);
+        if y >= Self::SNOW_LEVEL && y <= surface_level {
+            11
+        } else if y == surface_level {
+            3
+        } else if y < surface_level {
+            if y < Self::STONE_LEVEL {
+                1
+            } else {
+                5
+            }
+        } else if y < Self::SEA_LEVEL {
+            4
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::noise::JavaRandom;
+    use crate::{NoiseGenerator, WorldGenerator};
+
+    #[test]
+    pub fn java_random_gives_the_java_values() {
+        //new Random(42).nextInt() in java
+        let mut random = JavaRandom::new(42);
+        assert_eq!(random.next(32), -1170105035);
+        let mut random = JavaRandom::new(42);
+        for bound in [1, 7, 16, 256, 1000] {
+            let value = random.next_int(bound);
+            assert!((0..bound).contains(&value));
+        }
+    }
+
+    #[test]
+    pub fn the_columns_are_layered() {
+        let mut generator = NoiseGenerator::new(1234);
+        let other = NoiseGenerator::new(1234);
+        for (x, z) in [(0, 0), (-300, 17), (5000, -4000)] {
+            let surface_level = generator.surface_level(x, z);
+            assert_eq!(surface_level, other.surface_level(x, z));
+            assert_eq!(generator.get_block(x, -1000, z), 1);
+            assert_eq!(generator.get_block(x, 1000, z), 0);
+            assert_ne!(generator.get_block(x, surface_level, z), 0);
+            assert_eq!(generator.get_block(x, surface_level.max(-14) + 1, z), 0);
+        }
+    }
+}
@@ -1,11 +1,56 @@
-use glam::IVec3;
+use glam::{DVec3, IVec3};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+///serialized as its two corners, each one as an array of 3 coordinates
+///the deserialization fails if the min corner isn't strictly below the max corner on every axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "AABBCorners")]
 pub struct AABB {
     pub(crate) min: IVec3,
     pub(crate) max: IVec3,
 }
 
+///the serialized form of an [`AABB`], checked before becoming one
+#[derive(Deserialize)]
+struct AABBCorners {
+    min: IVec3,
+    max: IVec3,
+}
+
+///the corners of a deserialized [`AABB`] aren't strictly ordered on some axis
+#[derive(Debug)]
+pub struct UnorderedCornersError {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl Display for UnorderedCornersError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the min corner {} of the box isn't strictly below its max corner {}",
+            self.min, self.max
+        )
+    }
+}
+
+impl Error for UnorderedCornersError {}
+
+impl TryFrom<AABBCorners> for AABB {
+    type Error = UnorderedCornersError;
+
+    fn try_from(corners: AABBCorners) -> Result<Self, Self::Error> {
+        let AABBCorners { min, max } = corners;
+        if min.cmplt(max).all() {
+            Ok(Self { min, max })
+        } else {
+            Err(UnorderedCornersError { min, max })
+        }
+    }
+}
+
 impl AABB {
     pub fn new(min: IVec3, max: IVec3) -> Self {
         debug_assert!(min.x < max.x);
@@ -20,6 +65,25 @@ impl AABB {
         Self { min, max }
     }
 
+    ///the box of the given size around the center, with an odd size the extra block is on the max side
+    pub fn from_center_size(center: IVec3, size: IVec3) -> Self {
+        let min = center - size / 2;
+        Self::new(min, min + size)
+    }
+
+    ///the box going from center - half to center + half
+    pub fn from_center_half(center: IVec3, half: IVec3) -> Self {
+        Self::new(center - half, center + half)
+    }
+
+    pub fn min(&self) -> IVec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> IVec3 {
+        self.max
+    }
+
     pub fn contains(&self, pos: IVec3) -> bool {
         pos.x >= self.min.x
             && pos.x <= self.max.x
@@ -87,4 +151,125 @@ impl AABB {
     pub fn clamp(&self, pos: IVec3) -> IVec3 {
         pos.clamp(self.min, self.max)
     }
+
+    ///the cells of a grid of cubes of side `cell_size` overlapped by the AABB, with the part of the AABB in each cell
+    ///the cell (x, y, z) goes from (x, y, z) * cell_size to (x + 1, y + 1, z + 1) * cell_size, the max being excluded like for the AABB
+    ///the cells are given by x, then y, then z, like positions sorted by their array
+    pub fn subdivide_into_grid(&self, cell_size: i32) -> impl Iterator<Item = (IVec3, AABB)> {
+        debug_assert!(cell_size > 0, "the cells must have a positive size");
+        let aabb = *self;
+        let first = aabb.min.div_euclid(IVec3::splat(cell_size));
+        let last = (aabb.max - IVec3::ONE).div_euclid(IVec3::splat(cell_size));
+        (first.x..=last.x).flat_map(move |x| {
+            (first.y..=last.y).flat_map(move |y| {
+                (first.z..=last.z).filter_map(move |z| {
+                    let cell = IVec3::new(x, y, z);
+                    let cell_aabb = AABB::new(cell * cell_size, (cell + IVec3::ONE) * cell_size);
+                    Some((cell, aabb.get_intersection(&cell_aabb)?))
+                })
+            })
+        })
+    }
+}
+
+///an axis aligned box with float coordinates in blocks, like the hitbox of an entity
+///the coordinates are in f64 to stay precise far from the origin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatAABB {
+    pub min: DVec3,
+    pub max: DVec3,
+}
+
+impl FloatAABB {
+    pub fn new(min: DVec3, max: DVec3) -> Self {
+        debug_assert!(min.x <= max.x);
+        debug_assert!(min.y <= max.y);
+        debug_assert!(min.z <= max.z);
+        Self { min, max }
+    }
+
+    pub fn size(&self) -> DVec3 {
+        self.max - self.min
+    }
+
+    pub fn translated(&self, offset: DVec3) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    ///touching boxes don't intersect
+    pub fn intersects(&self, other: &FloatAABB) -> bool {
+        self.min.x < other.max.x
+            && self.max.x > other.min.x
+            && self.min.y < other.max.y
+            && self.max.y > other.min.y
+            && self.min.z < other.max.z
+            && self.max.z > other.min.z
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aabb::AABB;
+    use glam::IVec3;
+
+    #[test]
+    pub fn from_center() {
+        let aabb = AABB::from_center_size(IVec3::new(0, 10, -4), IVec3::new(4, 3, 2));
+        assert_eq!(aabb.min(), IVec3::new(-2, 9, -5));
+        assert_eq!(aabb.max(), IVec3::new(2, 12, -3));
+        assert_eq!(aabb.size(), IVec3::new(4, 3, 2));
+
+        let aabb = AABB::from_center_half(IVec3::new(1, 1, 1), IVec3::splat(2));
+        assert_eq!(aabb, AABB::new(IVec3::splat(-1), IVec3::splat(3)));
+    }
+
+    #[test]
+    pub fn grid_cells_cover_the_aabb() {
+        let aabb = AABB::new(IVec3::new(-3, 0, 15), IVec3::new(5, 16, 17));
+        let cells = aabb.subdivide_into_grid(16).collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            vec![
+                (
+                    IVec3::new(-1, 0, 0),
+                    AABB::new(IVec3::new(-3, 0, 15), IVec3::new(0, 16, 16))
+                ),
+                (
+                    IVec3::new(-1, 0, 1),
+                    AABB::new(IVec3::new(-3, 0, 16), IVec3::new(0, 16, 17))
+                ),
+                (
+                    IVec3::new(0, 0, 0),
+                    AABB::new(IVec3::new(0, 0, 15), IVec3::new(5, 16, 16))
+                ),
+                (
+                    IVec3::new(0, 0, 1),
+                    AABB::new(IVec3::new(0, 0, 16), IVec3::new(5, 16, 17))
+                ),
+            ]
+        );
+
+        let volume = cells.iter().map(|(_, part)| part.get_volume()).sum::<i32>();
+        assert_eq!(volume, aabb.get_volume());
+    }
+
+    #[test]
+    pub fn serde_round_trip() {
+        let aabb = AABB::new(IVec3::new(-5, 0, 3), IVec3::new(2, 16, 4));
+        let json = serde_json::to_string(&aabb).unwrap();
+        assert_eq!(json, r#"{"min":[-5,0,3],"max":[2,16,4]}"#);
+        assert_eq!(serde_json::from_str::<AABB>(&json).unwrap(), aabb);
+    }
+
+    #[test]
+    pub fn deserialize_rejects_unordered_corners() {
+        //flat on the x axis
+        let json = r#"{"min":[0,0,0],"max":[0,16,4]}"#;
+        assert!(serde_json::from_str::<AABB>(json).is_err());
+        let json = r#"{"min":[2,0,0],"max":[-2,16,4]}"#;
+        assert!(serde_json::from_str::<AABB>(json).is_err());
+    }
 }
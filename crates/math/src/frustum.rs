@@ -0,0 +1,262 @@
+use crate::aabb::AABB;
+use crate::consts::CHUNK_SIZE_F;
+use crate::positions::EntityPos;
+use glam::{EulerRot, IVec3, Quat, Vec3};
+use std::f32::consts::FRAC_PI_2;
+use std::ops::Mul;
+
+///the vertical and the horizontal field of view, in radians
+pub fn get_fovs(fov: f32, ratio: f32) -> (f32, f32) {
+    let h_fov = fov;
+    let v_fov = 2.0 * f32::atan(f32::tan(h_fov * 0.5) * ratio);
+    (v_fov, h_fov)
+}
+
+///the frustum of a camera, it doesn't need the GPU so it can be computed anywhere, like on the server or in the tests
+///render_distance is in chunks
+pub fn compute_frustum(
+    pitch: f32,
+    yaw: f32,
+    position: EntityPos,
+    fov: f32,
+    ratio: f32,
+    render_distance: i32,
+) -> CameraFrustum {
+    // yaw == 0 <==> looking at z+
+    // yaw == -PI/2 <==> looking at x+
+    // pitch == PI/2 <==> looking at y-
+    // pitch == -PI/2 <==> looking at y
+    //todo: the math is weird, but it works
+
+    let rotation = Quat::from_euler(EulerRot::XYZ, -pitch, yaw, 0.0).inverse();
+
+    let (v_fov, h_fov) = get_fovs(fov, ratio);
+
+    let height_normal_angle = v_fov * 0.5 + FRAC_PI_2;
+    let width_normal_angle = h_fov * 0.5 + FRAC_PI_2;
+    let right = Quat::from_rotation_y(-height_normal_angle) * Vec3::Z; //because Z is forward
+    let left = Quat::from_rotation_y(height_normal_angle) * Vec3::Z;
+    let up = Quat::from_rotation_x(width_normal_angle) * Vec3::Z;
+    let down = Quat::from_rotation_x(-width_normal_angle) * Vec3::Z;
+
+    let origin = position.chunk_pos + (position.relative_pos / CHUNK_SIZE_F).as_ivec3();
+
+    let get_rotation = |v_fov: f32, h_fov: f32| Quat::from_euler(EulerRot::XYZ, v_fov, h_fov, 0.0);
+
+    let get_corner = |local_rotation: Quat, dist: i32| {
+        let vec = rotation * local_rotation * Vec3::Z.mul(dist as f32);
+        (vec + position.relative_pos / CHUNK_SIZE_F).as_ivec3() + origin
+    };
+
+    let v_fov_2 = v_fov * 0.5;
+    let h_fov_2 = h_fov * 0.5;
+
+    let top_left = get_corner(get_rotation(v_fov_2, h_fov_2), render_distance);
+    let top_right = get_corner(get_rotation(-v_fov_2, h_fov_2), render_distance);
+    let bottom_left = get_corner(get_rotation(v_fov_2, -h_fov_2), render_distance);
+    let bottom_right = get_corner(get_rotation(-v_fov_2, -h_fov_2), render_distance);
+
+    //compute the intersection of the for plane tangents to the sides vectors of the frustum
+    let cosine = get_rotation(v_fov_2, h_fov_2).dot(Quat::IDENTITY);
+    let length = (render_distance as f32 / cosine) as i32;
+    let furthest = get_corner(Quat::IDENTITY, length);
+
+    let corners = FrustumCorners {
+        origin,
+        top_left,
+        top_right,
+        bottom_left,
+        bottom_right,
+        furthest,
+    };
+    let points = corners.points();
+    let min = points.into_iter().reduce(IVec3::min).unwrap();
+    let max = points.into_iter().reduce(IVec3::max).unwrap();
+
+    let aabb = AABB::new(min - IVec3::splat(1), max + IVec3::splat(1));
+
+    CameraFrustum {
+        planes: [
+            //todo: get the correct planes and positions
+            rotation * right,
+            rotation * left,
+            rotation * up,
+            rotation * down,
+        ],
+        origin: position,
+        aabb,
+        corners,
+        render_distance,
+    }
+}
+
+///the points computed for the frustum, in chunk coordinates, to draw it while debugging the culling, the frustum is a pyramid from the origin to the far corners
+///furthest is where the planes tangent to the far corners meet, it is only used to bound the frustum
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrustumCorners {
+    pub origin: IVec3,
+    pub top_left: IVec3,
+    pub top_right: IVec3,
+    pub bottom_left: IVec3,
+    pub bottom_right: IVec3,
+    pub furthest: IVec3,
+}
+
+impl FrustumCorners {
+    pub fn points(&self) -> [IVec3; 6] {
+        [
+            self.origin,
+            self.top_left,
+            self.top_right,
+            self.bottom_left,
+            self.bottom_right,
+            self.furthest,
+        ]
+    }
+
+    ///the lines of the pyramid, the 4 sides from the origin then the 4 edges of the far side
+    pub fn edges(&self) -> [(IVec3, IVec3); 8] {
+        [
+            (self.origin, self.top_left),
+            (self.origin, self.top_right),
+            (self.origin, self.bottom_left),
+            (self.origin, self.bottom_right),
+            (self.top_left, self.top_right),
+            (self.top_right, self.bottom_right),
+            (self.bottom_right, self.bottom_left),
+            (self.bottom_left, self.top_left),
+        ]
+    }
+}
+
+pub struct CameraFrustum {
+    planes: [Vec3; 4],
+    origin: EntityPos,
+    aabb: AABB,
+    corners: FrustumCorners,
+    render_distance: i32,
+}
+
+impl CameraFrustum {
+    pub fn contains(&self, aabb: &AABB) -> bool {
+        let corners = aabb.corners();
+
+        let is_behind = |normal_plane: Vec3| {
+            for corner in corners {
+                let mut vec = (corner - self.origin.chunk_pos).as_vec3();
+                vec *= CHUNK_SIZE_F;
+                if normal_plane.dot(vec - self.origin.relative_pos) <= 0.0 {
+                    return true;
+                }
+            }
+            false
+        };
+
+        for plane in self.planes {
+            if !is_behind(plane) {
+                return false;
+            }
+        }
+        self.in_render_distance(aabb)
+    }
+
+    ///whether the closest point of the aabb is within the render distance of the camera
+    ///the distance is computed in blocks, from the exact position of the camera in its chunk
+    fn in_render_distance(&self, aabb: &AABB) -> bool {
+        let min = (aabb.min() - self.origin.chunk_pos).as_vec3() * CHUNK_SIZE_F;
+        let max = (aabb.max() - self.origin.chunk_pos).as_vec3() * CHUNK_SIZE_F;
+        let closest = self.origin.relative_pos.clamp(min, max);
+        let render_distance = self.render_distance as f32 * CHUNK_SIZE_F;
+        (closest - self.origin.relative_pos).length_squared() <= render_distance * render_distance
+    }
+
+    pub fn get_aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    pub fn corners(&self) -> FrustumCorners {
+        self.corners
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aabb::AABB;
+    use crate::frustum::{compute_frustum, CameraFrustum, FrustumCorners};
+    use crate::positions::EntityPos;
+    use glam::{IVec3, Vec3};
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    fn frustum_at(relative_pos: Vec3, render_distance: i32) -> CameraFrustum {
+        CameraFrustum {
+            planes: [Vec3::ZERO; 4],
+            origin: EntityPos::new(IVec3::ZERO, relative_pos),
+            aabb: AABB::from_center_half(IVec3::ZERO, IVec3::splat(render_distance)),
+            corners: FrustumCorners {
+                origin: IVec3::ZERO,
+                top_left: IVec3::ZERO,
+                top_right: IVec3::ZERO,
+                bottom_left: IVec3::ZERO,
+                bottom_right: IVec3::ZERO,
+                furthest: IVec3::ZERO,
+            },
+            render_distance,
+        }
+    }
+
+    #[test]
+    pub fn the_render_distance_depends_on_the_position_in_the_chunk() {
+        //the closest block of the chunk (2, 2, 0) is at (32, 32, z), the camera is at y = 8
+        let chunk = AABB::new(IVec3::new(2, 2, 0), IVec3::new(3, 3, 1));
+        let render_distance = 2; //32 blocks
+
+        let near_the_border = frustum_at(Vec3::new(15.5, 8.0, 8.0), render_distance);
+        assert!(near_the_border.in_render_distance(&chunk));
+
+        let near_the_origin = frustum_at(Vec3::new(0.5, 8.0, 8.0), render_distance);
+        assert!(!near_the_origin.in_render_distance(&chunk));
+
+        //both are within the render distance of the camera chunk
+        let own_chunk = AABB::new(IVec3::ZERO, IVec3::ONE);
+        assert!(near_the_border.in_render_distance(&own_chunk));
+        assert!(near_the_origin.in_render_distance(&own_chunk));
+    }
+
+    #[test]
+    pub fn the_frustum_contains_the_chunks_ahead_only() {
+        let position = EntityPos::new(IVec3::ZERO, Vec3::splat(8.0));
+        let chunk_at = |x, y, z| AABB::new(IVec3::new(x, y, z), IVec3::new(x + 1, y + 1, z + 1));
+
+        //yaw == 0 looks at z+
+        let frustum = compute_frustum(0.0, 0.0, position, FRAC_PI_2, 16.0 / 9.0, 8);
+        assert!(frustum.contains(&chunk_at(0, 0, 3)));
+        assert!(!frustum.contains(&chunk_at(0, 0, -4)));
+
+        //half a turn, it looks at z-
+        let frustum = compute_frustum(0.0, PI, position, FRAC_PI_2, 16.0 / 9.0, 8);
+        assert!(frustum.contains(&chunk_at(0, 0, -4)));
+        assert!(!frustum.contains(&chunk_at(0, 0, 3)));
+
+        //ahead, but beyond the render distance
+        let frustum = compute_frustum(0.0, 0.0, position, FRAC_PI_2, 16.0 / 9.0, 2);
+        assert!(!frustum.contains(&chunk_at(0, 0, 3)));
+    }
+
+    #[test]
+    pub fn the_corners_are_in_the_frustum_aabb() {
+        let position = EntityPos::new(IVec3::new(4, -2, 7), Vec3::splat(8.0));
+        let frustum = compute_frustum(0.3, -1.2, position, FRAC_PI_2, 16.0 / 9.0, 8);
+        let corners = frustum.corners();
+        assert_eq!(corners.origin, IVec3::new(4, -2, 7));
+
+        let aabb = frustum.get_aabb();
+        for point in corners.points() {
+            assert!(point.cmpge(aabb.min()).all() && point.cmplt(aabb.max()).all());
+        }
+        //every edge starts or ends on a far corner
+        for (start, end) in corners.edges() {
+            assert_ne!(start, end);
+            assert_ne!(end, corners.origin);
+        }
+    }
+}
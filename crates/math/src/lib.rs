@@ -2,6 +2,8 @@
 
 pub mod aabb;
 pub mod consts;
+pub mod frustum;
+pub mod morton;
 pub mod positions;
 
 pub use glam::*;
@@ -0,0 +1,105 @@
+use glam::IVec3;
+
+///the maximum number of bits per coordinate, 3 * 21 bits fit in an u64
+pub const MAX_MORTON_BITS: u32 = 21;
+
+///spread the 21 lowest bits of the value, so that there are two 0 bits between each of them
+fn spread_bits(value: u64) -> u64 {
+    let mut x = value & 0x1f_ffff;
+    x = (x | x << 32) & 0x001f_0000_0000_ffff;
+    x = (x | x << 16) & 0x001f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
+
+///the inverse of spread_bits, keep one bit every three bits
+fn compact_bits(value: u64) -> u64 {
+    let mut x = value & 0x1249_2492_4924_9249;
+    x = (x ^ (x >> 2)) & 0x10c3_0c30_c30c_30c3;
+    x = (x ^ (x >> 4)) & 0x100f_00f0_0f00_f00f;
+    x = (x ^ (x >> 8)) & 0x001f_0000_ff00_00ff;
+    x = (x ^ (x >> 16)) & 0x001f_0000_0000_ffff;
+    x = (x ^ (x >> 32)) & 0x1f_ffff;
+    x
+}
+
+///interleave the `bits` lowest bits of each coordinate (Z-order curve), x being the lowest bit
+///the coordinates should be in the range [0, 2^bits[, positions close in space get close codes
+pub fn morton_encode_3d(pos: IVec3, bits: u32) -> u64 {
+    debug_assert!(bits <= MAX_MORTON_BITS, "too many bits");
+    debug_assert!(pos.cmpge(IVec3::ZERO).all(), "negative coordinate");
+    debug_assert!(
+        pos.cmplt(IVec3::splat(1 << bits)).all(),
+        "coordinate too big"
+    );
+    let mask = (1u64 << bits) - 1;
+    spread_bits(pos.x as u64 & mask)
+        | spread_bits(pos.y as u64 & mask) << 1
+        | spread_bits(pos.z as u64 & mask) << 2
+}
+
+///the inverse of [`morton_encode_3d`]
+pub fn morton_decode_3d(code: u64, bits: u32) -> IVec3 {
+    debug_assert!(bits <= MAX_MORTON_BITS, "too many bits");
+    let mask = (1u64 << bits) - 1;
+    IVec3::new(
+        (compact_bits(code) & mask) as i32,
+        (compact_bits(code >> 1) & mask) as i32,
+        (compact_bits(code >> 2) & mask) as i32,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::morton::{morton_decode_3d, morton_encode_3d, MAX_MORTON_BITS};
+    use glam::IVec3;
+
+    ///interleave the bits one by one
+    fn reference_encode(pos: IVec3, bits: u32) -> u64 {
+        let mut code = 0;
+        for i in 0..bits {
+            code |= ((pos.x as u64 >> i) & 1) << (3 * i);
+            code |= ((pos.y as u64 >> i) & 1) << (3 * i + 1);
+            code |= ((pos.z as u64 >> i) & 1) << (3 * i + 2);
+        }
+        code
+    }
+
+    #[test]
+    pub fn encode_matches_reference() {
+        //every position of a 16^3 cube
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(morton_encode_3d(pos, 4), reference_encode(pos, 4));
+                }
+            }
+        }
+
+        //some big positions
+        let max = (1 << MAX_MORTON_BITS) - 1;
+        for pos in [
+            IVec3::new(max, 0, 0),
+            IVec3::new(0, max, 0),
+            IVec3::new(0, 0, max),
+            IVec3::new(max, max, max),
+            IVec3::new(123456, 654321, 1048576),
+        ] {
+            let code = morton_encode_3d(pos, MAX_MORTON_BITS);
+            assert_eq!(code, reference_encode(pos, MAX_MORTON_BITS));
+        }
+    }
+
+    #[test]
+    pub fn decode_is_the_inverse_of_encode() {
+        for code in 0..4096 {
+            assert_eq!(morton_encode_3d(morton_decode_3d(code, 4), 4), code);
+        }
+        let pos = IVec3::new(123456, 654321, 1048576);
+        let code = morton_encode_3d(pos, MAX_MORTON_BITS);
+        assert_eq!(morton_decode_3d(code, MAX_MORTON_BITS), pos);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::consts::{CHUNK_SIZE, CHUNK_SIZE_D, CHUNK_SIZE_F};
 use glam::{DVec3, IVec3, Vec3};
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, AddAssign};
 
 /// A chunk position in the world, measured in chunks, valid from -2^27 to 2^27 - 1
@@ -10,7 +11,7 @@ pub type BlockPos = IVec3;
 
 /// A world BlockPos for Entities or other things that need to be more precise than a block, it is a combination of a chunk position and a floating point block position
 /// useful for rendering
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct EntityPos {
     pub chunk_pos: ChunkPos,
     pub relative_pos: Vec3,
@@ -146,3 +147,20 @@ impl AddAssign<Vec3> for EntityPos {
         *self = new;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::positions::EntityPos;
+    use glam::{IVec3, Vec3};
+
+    #[test]
+    pub fn serde_round_trip() {
+        let pos = EntityPos::new(IVec3::new(-3, 7, 120), Vec3::new(0.5, 15.25, 3.0));
+        let json = serde_json::to_string(&pos).unwrap();
+        assert_eq!(
+            json,
+            r#"{"chunk_pos":[-3,7,120],"relative_pos":[0.5,15.25,3.0]}"#
+        );
+        assert_eq!(serde_json::from_str::<EntityPos>(&json).unwrap(), pos);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::DeserializationError;
 use crate::packets::{Packet, PacketId, ReadingByteBuf, WritingByteBuf};
+use math::positions::EntityPos;
 use std::mem;
 
 pub struct ChatPacket {
@@ -9,20 +10,103 @@ pub struct ChatPacket {
 impl Packet for ChatPacket {
     const ID: PacketId = 0;
     fn serialize(self) -> WritingByteBuf {
-        let bytes = self.message.as_bytes();
-        let len = bytes.len();
-        let mut buf = Self::get_writing_byte_buff(len + mem::size_of::<usize>());
-        buf.write(len);
-        buf.write_bytes(bytes);
+        //the length is always sent on 32 bits, whatever the platform is
+        let mut buf = Self::get_writing_byte_buff(self.message.len() + mem::size_of::<u32>());
+        buf.write_string(&self.message);
         buf
     }
 
     fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
-        let len = buf.read::<usize>()?;
-        let message_bytes = buf.read_bytes(len)?;
-        let message = std::str::from_utf8(message_bytes)
-            .map_err(|_| DeserializationError::InvalidPacketContent)?;
-        let message = message.to_string();
-        Ok(Self { message })
+        Ok(Self {
+            message: buf.read_string()?,
+        })
+    }
+}
+
+///sent every tick by the client with the position of the player, on the unreliable channel
+///the server acknowledges it with a [`crate::s2c::PlayerPositionPacket`]
+pub struct PlayerMovePacket {
+    ///increase by one for each move of the player, the server ignores the moves older than the last one it received
+    pub sequence: u32,
+    ///the position once all the moves up to the sequence are applied
+    pub pos: EntityPos,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Packet for PlayerMovePacket {
+    const ID: PacketId = 1;
+    fn serialize(self) -> WritingByteBuf {
+        let mut buf = Self::get_writing_byte_buff(
+            mem::size_of::<u32>() + 3 * mem::size_of::<i32>() + 5 * mem::size_of::<f32>(),
+        );
+        buf.write(self.sequence);
+        buf.write_entity_pos(self.pos);
+        buf.write(self.yaw);
+        buf.write(self.pitch);
+        buf
+    }
+
+    fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
+        Ok(Self {
+            sequence: buf.read()?,
+            pos: buf.read_entity_pos()?,
+            yaw: buf.read()?,
+            pitch: buf.read()?,
+        })
+    }
+}
+
+///sent periodically by the client to measure the latency, the server answers with a [`crate::s2c::PongPacket`]
+pub struct PingPacket {
+    ///the time when the ping was sent, in microseconds since an arbitrary point chosen by the client
+    pub timestamp: u64,
+}
+
+impl Packet for PingPacket {
+    const ID: PacketId = 2;
+    fn serialize(self) -> WritingByteBuf {
+        let mut buf = Self::get_writing_byte_buff(mem::size_of::<u64>());
+        buf.write(self.timestamp);
+        buf
+    }
+
+    fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
+        Ok(Self {
+            timestamp: buf.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::c2s::ChatPacket;
+    use crate::packets::{ByteBuf, Packet, PacketId, ReadingByteBuf};
+    use std::mem;
+
+    #[test]
+    pub fn chat_packet_length_is_fixed_width() {
+        let message = "hello world".to_string();
+        let data: ByteBuf = ChatPacket {
+            message: message.clone(),
+        }
+        .serialize()
+        .into();
+
+        //packet id, then a little-endian u32 length, then the utf8 bytes
+        assert_eq!(
+            data.len(),
+            mem::size_of::<PacketId>() + mem::size_of::<u32>() + message.len()
+        );
+        assert_eq!(
+            data[1..5],
+            (message.len() as u32).to_le_bytes(),
+            "the length must be encoded on 4 little-endian bytes"
+        );
+
+        let buf = ReadingByteBuf::new(data);
+        assert_eq!(buf.get_packet_id(), ChatPacket::ID);
+        let packet = ChatPacket::deserialize(buf).unwrap();
+        assert_eq!(packet.message, message);
     }
 }
@@ -3,3 +3,4 @@ pub mod c2s;
 pub mod errors;
 pub mod packets;
 pub mod s2c;
+pub mod socket;
@@ -1,10 +1,14 @@
 use crate::errors::DeserializationError;
-use bytemuck::{bytes_of, from_bytes, pod_read_unaligned, Pod};
+use bytemuck::{bytes_of, from_bytes};
 use log::error;
+use math::positions::{ChunkPos, EntityPos};
+use math::Vec3;
 use std::collections::HashMap;
 use std::mem;
 
 pub type PacketId = u8;
+///the netcode protocol id used by default, the client and the server must use the same one to be able to connect
+pub const PROTOCOL_ID: u64 = 0;
 pub type ByteBuf = Box<[u8]>;
 pub trait Packet: Sized {
     const ID: PacketId;
@@ -20,47 +24,70 @@ pub trait Packet: Sized {
     fn deserialize(data: ReadingByteBuf) -> Result<Self, DeserializationError>;
 }
 
-trait PacketHandler {
-    fn handle_packet(&self, data: ReadingByteBuf);
+trait PacketHandler<Context> {
+    fn handle_packet(
+        &self,
+        data: ReadingByteBuf,
+        context: &mut Context,
+    ) -> Result<(), DeserializationError>;
 }
 
-struct PacketHandlerImpl<PacketType, CallBack>
+struct PacketHandlerImpl<PacketType, CallBack, Context>
 where
     PacketType: Packet,
-    CallBack: Fn(PacketType) -> (),
+    CallBack: Fn(PacketType, &mut Context),
 {
     callback: CallBack,
-    phantom: std::marker::PhantomData<PacketType>,
+    phantom: std::marker::PhantomData<(PacketType, Context)>,
 }
 
-impl<PacketType, CallBack> PacketHandler for PacketHandlerImpl<PacketType, CallBack>
+impl<PacketType, CallBack, Context> PacketHandler<Context>
+    for PacketHandlerImpl<PacketType, CallBack, Context>
 where
     PacketType: Packet,
-    CallBack: Fn(PacketType) -> (),
+    CallBack: Fn(PacketType, &mut Context),
 {
-    fn handle_packet(&self, data: ReadingByteBuf) {
-        let packet = PacketType::deserialize(data).unwrap();
-        (self.callback)(packet);
+    fn handle_packet(
+        &self,
+        data: ReadingByteBuf,
+        context: &mut Context,
+    ) -> Result<(), DeserializationError> {
+        let packet = PacketType::deserialize(data)?;
+        (self.callback)(packet, context);
+        Ok(())
     }
 }
 
-pub struct Dispatcher {
-    handlers: HashMap<PacketId, Box<dyn PacketHandler>>,
+///route the received packets to the handler registered for their id
+///Context is the data given to every handler, like the sender of the packet or the state the handler should modify
+pub struct Dispatcher<Context = ()> {
+    handlers: HashMap<PacketId, Box<dyn PacketHandler<Context>>>,
 }
 
-impl Dispatcher {
+impl<Context: 'static> Dispatcher<Context> {
     pub fn new() -> Self {
         Self {
             handlers: HashMap::new(),
         }
     }
 
+    ///register the handler of a packet type, panic if this packet type already has a handler
     pub fn register_handler<PacketType, CallBack>(&mut self, callback: CallBack)
     where
         PacketType: Packet + 'static,
-        CallBack: Fn(PacketType) -> () + 'static,
+        CallBack: Fn(PacketType, &mut Context) + 'static,
     {
         assert!(self.handlers.get(&PacketType::ID).is_none());
+        self.replace_handler(callback);
+    }
+
+    ///register the handler of a packet type, overwriting the previous one if there is any
+    ///useful to switch the handlers when the state of the connection changes
+    pub fn replace_handler<PacketType, CallBack>(&mut self, callback: CallBack)
+    where
+        PacketType: Packet + 'static,
+        CallBack: Fn(PacketType, &mut Context) + 'static,
+    {
         let handler = PacketHandlerImpl {
             callback,
             phantom: std::marker::PhantomData,
@@ -68,33 +95,98 @@ impl Dispatcher {
         self.handlers.insert(PacketType::ID, Box::new(handler));
     }
 
-    pub fn dispatch_packet(&self, data: ByteBuf) {
+    ///remove the handler of the given packet id, return false if there was no handler
+    pub fn unregister(&mut self, id: PacketId) -> bool {
+        self.handlers.remove(&id).is_some()
+    }
+
+    ///return true if a handler is registered for the given packet id
+    pub fn has_handler(&self, id: PacketId) -> bool {
+        self.handlers.contains_key(&id)
+    }
+
+    ///deserialize the packet and give it to its handler, malformed or unknown packets are logged and dropped
+    pub fn dispatch_packet(&self, data: ByteBuf, context: &mut Context) {
+        if data.len() < mem::size_of::<PacketId>() {
+            error!("empty packet received");
+            return;
+        }
         let data = ReadingByteBuf::new(data);
         let id = data.get_packet_id();
         let handler = self.handlers.get(&id);
         if let Some(handler) = handler {
-            handler.handle_packet(data);
+            if let Err(e) = handler.handle_packet(data, context) {
+                error!("malformed packet {} received: {}", id, e);
+            }
         } else {
             error!("unknown packet received {}", id);
         }
     }
 }
 
+///a fixed-width value, written in little endian whatever the platform is, so the packets are the same on every architecture
+///usize and isize aren't values because their size depends on the platform
+pub trait WireValue: Sized {
+    const SIZE: usize;
+
+    fn write_le(self, data: &mut Vec<u8>);
+    ///bytes is exactly SIZE bytes long
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_wire_value {
+    ($($primitive:ty),*) => {
+        $(
+            impl WireValue for $primitive {
+                const SIZE: usize = mem::size_of::<$primitive>();
+
+                fn write_le(self, data: &mut Vec<u8>) {
+                    data.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    <$primitive>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_wire_value!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
 pub struct WritingByteBuf {
     data: Vec<u8>,
 }
 
 impl WritingByteBuf {
+    ///write a fixed-width value in little endian, see [`WireValue`]
     pub fn write<T>(&mut self, value: T)
     where
-        T: Pod,
+        T: WireValue,
     {
-        self.data.extend_from_slice(bytes_of(&value));
+        value.write_le(&mut self.data);
     }
 
     pub fn write_bytes(&mut self, value: &[u8]) {
         self.data.extend_from_slice(value);
     }
+
+    ///write the length of the string on 32 bits, then its utf8 bytes
+    pub fn write_string(&mut self, value: &str) {
+        let bytes = value.as_bytes();
+        self.write(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    ///write the chunk position on i32, then the position relative to the chunk on f32
+    pub fn write_entity_pos(&mut self, pos: EntityPos) {
+        self.write(pos.chunk_pos.x);
+        self.write(pos.chunk_pos.y);
+        self.write(pos.chunk_pos.z);
+        self.write(pos.relative_pos.x);
+        self.write(pos.relative_pos.y);
+        self.write(pos.relative_pos.z);
+    }
 }
 
 impl From<WritingByteBuf> for ByteBuf {
@@ -109,31 +201,25 @@ pub struct ReadingByteBuf {
 }
 
 impl ReadingByteBuf {
-    fn new(data: Box<[u8]>) -> Self {
+    pub(crate) fn new(data: Box<[u8]>) -> Self {
         Self {
             data,
             offset: mem::size_of::<PacketId>(),
         }
     }
 
-    fn get_packet_id(&self) -> PacketId {
+    pub(crate) fn get_packet_id(&self) -> PacketId {
         let id = &self.data[0..mem::size_of::<PacketId>()];
         *from_bytes::<PacketId>(id)
     }
 
+    ///read a fixed-width value, see [`WritingByteBuf::write`]
     pub fn read<T>(&mut self) -> Result<T, DeserializationError>
     where
-        T: Pod,
+        T: WireValue,
     {
-        let type_size = mem::size_of::<T>();
-        if self.offset + type_size > self.data.len() {
-            return Err(DeserializationError::NotEnoughBytes);
-        }
-
-        let slice = &self.data[self.offset..self.offset + type_size];
-        assert_eq!(slice.len(), type_size);
-        self.offset += type_size;
-        Ok(pod_read_unaligned(slice))
+        let slice = self.read_bytes(T::SIZE)?;
+        Ok(T::read_le(slice))
     }
 
     pub fn read_bytes(&mut self, size: usize) -> Result<&[u8], DeserializationError> {
@@ -144,4 +230,152 @@ impl ReadingByteBuf {
         self.offset += size;
         Ok(slice)
     }
+
+    ///read a string written by [`WritingByteBuf::write_string`]
+    pub fn read_string(&mut self) -> Result<String, DeserializationError> {
+        let len = self.read::<u32>()? as usize;
+        let bytes = self.read_bytes(len)?;
+        let string =
+            std::str::from_utf8(bytes).map_err(|_| DeserializationError::InvalidPacketContent)?;
+        Ok(string.to_string())
+    }
+
+    ///read a position written by [`WritingByteBuf::write_entity_pos`], a non finite relative position is invalid
+    pub fn read_entity_pos(&mut self) -> Result<EntityPos, DeserializationError> {
+        let chunk_pos = ChunkPos::new(self.read()?, self.read()?, self.read()?);
+        let relative_pos = Vec3::new(self.read()?, self.read()?, self.read()?);
+        if !relative_pos.is_finite() {
+            return Err(DeserializationError::InvalidPacketContent);
+        }
+        Ok(EntityPos::new(chunk_pos, relative_pos))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::c2s::{ChatPacket, PingPacket};
+    use crate::errors::DeserializationError;
+    use crate::packets::{ByteBuf, Dispatcher, Packet, ReadingByteBuf, WritingByteBuf};
+    use crate::s2c::PongPacket;
+
+    fn to_reading_buf(buf: WritingByteBuf) -> ReadingByteBuf {
+        ReadingByteBuf::new(buf.into())
+    }
+
+    #[test]
+    pub fn strings_are_read_back() {
+        for string in ["", "hello", "héllo wörld ✓ 🦀"] {
+            let mut buf = ChatPacket::get_writing_byte_buff(0);
+            buf.write_string(string);
+            buf.write(7u8);
+            let mut buf = to_reading_buf(buf);
+            assert_eq!(buf.read_string().unwrap(), string);
+            assert_eq!(buf.read::<u8>().unwrap(), 7);
+        }
+    }
+
+    #[test]
+    pub fn values_are_written_in_little_endian() {
+        let mut buf = ChatPacket::get_writing_byte_buff(0);
+        buf.write(0x01020304u32);
+        buf.write(-2i16);
+        buf.write(1.0f32);
+        buf.write(0x0102030405060708u64);
+        let data: ByteBuf = buf.into();
+        let expected: &[u8] = &[
+            4, 3, 2, 1, //u32
+            0xfe, 0xff, //i16
+            0, 0, 0x80, 0x3f, //f32
+            8, 7, 6, 5, 4, 3, 2, 1, //u64
+        ];
+        assert_eq!(&data[1..], expected);
+
+        //the bytes of a big endian platform would be read the same
+        let mut buf = ReadingByteBuf::new(data);
+        assert_eq!(buf.read::<u32>().unwrap(), 0x01020304);
+        assert_eq!(buf.read::<i16>().unwrap(), -2);
+        assert_eq!(buf.read::<f32>().unwrap(), 1.0);
+        assert_eq!(buf.read::<u64>().unwrap(), 0x0102030405060708);
+        assert!(matches!(
+            buf.read::<u8>(),
+            Err(DeserializationError::NotEnoughBytes)
+        ));
+    }
+
+    #[test]
+    pub fn invalid_strings_are_rejected() {
+        //the length says 6 bytes but only 5 are sent
+        let mut buf = ChatPacket::get_writing_byte_buff(0);
+        buf.write(6u32);
+        buf.write_bytes(b"hello");
+        let result = to_reading_buf(buf).read_string();
+        assert!(matches!(result, Err(DeserializationError::NotEnoughBytes)));
+
+        //the length itself is truncated
+        let mut buf = ChatPacket::get_writing_byte_buff(0);
+        buf.write_bytes(&[0, 0]);
+        let result = to_reading_buf(buf).read_string();
+        assert!(matches!(result, Err(DeserializationError::NotEnoughBytes)));
+
+        //a multibyte character cut in the middle
+        let mut buf = ChatPacket::get_writing_byte_buff(0);
+        buf.write_string("é");
+        let mut data: ByteBuf = buf.into();
+        let len = data.len();
+        data[len - 1] = b'a';
+        let result = ReadingByteBuf::new(data).read_string();
+        assert!(matches!(
+            result,
+            Err(DeserializationError::InvalidPacketContent)
+        ));
+    }
+
+    #[test]
+    pub fn chat_packet_reaches_its_handler() {
+        let mut dispatcher = Dispatcher::<Vec<String>>::new();
+        dispatcher.register_handler(|packet: ChatPacket, received: &mut Vec<String>| {
+            received.push(packet.message)
+        });
+        assert!(dispatcher.has_handler(ChatPacket::ID));
+
+        let mut received = Vec::new();
+        let packet = ChatPacket {
+            message: "hello".to_string(),
+        };
+        dispatcher.dispatch_packet(packet.serialize().into(), &mut received);
+        assert_eq!(received, vec!["hello".to_string()]);
+
+        //a packet without handler is dropped
+        assert!(dispatcher.unregister(ChatPacket::ID));
+        let packet = ChatPacket {
+            message: "world".to_string(),
+        };
+        dispatcher.dispatch_packet(packet.serialize().into(), &mut received);
+        assert_eq!(received.len(), 1);
+    }
+
+    #[test]
+    pub fn ping_is_echoed_as_pong() {
+        //the server answers to the ping with a pong holding the same timestamp
+        let mut server = Dispatcher::<Vec<ByteBuf>>::new();
+        server.register_handler(|packet: PingPacket, responses: &mut Vec<ByteBuf>| {
+            let pong = PongPacket {
+                timestamp: packet.timestamp,
+            };
+            responses.push(pong.serialize().into());
+        });
+        let mut client = Dispatcher::<Option<u64>>::new();
+        client.register_handler(|packet: PongPacket, received: &mut Option<u64>| {
+            *received = Some(packet.timestamp)
+        });
+
+        let mut responses = Vec::new();
+        let ping = PingPacket { timestamp: 123456 };
+        server.dispatch_packet(ping.serialize().into(), &mut responses);
+        assert_eq!(responses.len(), 1);
+
+        let mut received = None;
+        client.dispatch_packet(responses.pop().unwrap(), &mut received);
+        assert_eq!(received, Some(123456));
+    }
 }
@@ -1 +1,213 @@
+use crate::errors::DeserializationError;
+use crate::packets::{Packet, PacketId, ReadingByteBuf, WritingByteBuf};
+use math::consts::CHUNK_SIZE;
+use math::positions::{ChunkPos, EntityPos};
+use std::mem;
 
+///carry a serialized chunk, sent on the reliable ordered channel when a chunk enters the view distance of a player
+pub struct ChunkDataPacket {
+    pub pos: ChunkPos,
+    ///the chunk serialized with `Chunk::serialize`
+    pub data: Vec<u8>,
+}
+
+impl Packet for ChunkDataPacket {
+    const ID: PacketId = 0;
+    fn serialize(self) -> WritingByteBuf {
+        let len = self.data.len() as u32;
+        let mut buf = Self::get_writing_byte_buff(
+            3 * mem::size_of::<i32>() + mem::size_of::<u32>() + self.data.len(),
+        );
+        buf.write(self.pos.x);
+        buf.write(self.pos.y);
+        buf.write(self.pos.z);
+        buf.write(len);
+        buf.write_bytes(&self.data);
+        buf
+    }
+
+    fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
+        let x = buf.read::<i32>()?;
+        let y = buf.read::<i32>()?;
+        let z = buf.read::<i32>()?;
+        let len = buf.read::<u32>()? as usize;
+        let data = buf.read_bytes(len)?.to_vec();
+        Ok(Self {
+            pos: ChunkPos::new(x, y, z),
+            data,
+        })
+    }
+}
+
+///the position of the player known by the server, the answer to a [`crate::c2s::PlayerMovePacket`]
+///the client replays its moves newer than the sequence on top of it, sent on the unreliable channel
+pub struct PlayerPositionPacket {
+    ///the sequence of the last move the server applied
+    pub sequence: u32,
+    pub pos: EntityPos,
+}
+
+impl Packet for PlayerPositionPacket {
+    const ID: PacketId = 2;
+    fn serialize(self) -> WritingByteBuf {
+        let mut buf = Self::get_writing_byte_buff(
+            mem::size_of::<u32>() + 3 * mem::size_of::<i32>() + 3 * mem::size_of::<f32>(),
+        );
+        buf.write(self.sequence);
+        buf.write_entity_pos(self.pos);
+        buf
+    }
+
+    fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
+        Ok(Self {
+            sequence: buf.read()?,
+            pos: buf.read_entity_pos()?,
+        })
+    }
+}
+
+///the blocks changed in a chunk during a tick, sent on the reliable ordered channel to the clients that have the chunk
+///many edits of a chunk cost one packet, so a big edit like a flood fill doesn't flood the channel
+pub struct BlockBatchPacket {
+    pub chunk: ChunkPos,
+    ///the index of each block in the chunk (x + y * 16 + z * 256) with the id of its new state
+    pub changes: Vec<(u16, u16)>,
+}
+
+impl BlockBatchPacket {
+    ///the number of blocks in a chunk, no index can reach it and a chunk can't have more changes
+    const BLOCK_COUNT: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+}
+
+impl Packet for BlockBatchPacket {
+    const ID: PacketId = 3;
+    fn serialize(self) -> WritingByteBuf {
+        let mut buf = Self::get_writing_byte_buff(
+            3 * mem::size_of::<i32>()
+                + mem::size_of::<u32>()
+                + self.changes.len() * 2 * mem::size_of::<u16>(),
+        );
+        buf.write(self.chunk.x);
+        buf.write(self.chunk.y);
+        buf.write(self.chunk.z);
+        buf.write(self.changes.len() as u32);
+        for (index, state) in self.changes {
+            buf.write(index);
+            buf.write(state);
+        }
+        buf
+    }
+
+    fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
+        let chunk = ChunkPos::new(buf.read()?, buf.read()?, buf.read()?);
+        let len = buf.read::<u32>()? as usize;
+        if len > Self::BLOCK_COUNT {
+            return Err(DeserializationError::InvalidPacketContent);
+        }
+        let mut changes = Vec::with_capacity(len);
+        for _ in 0..len {
+            let index = buf.read::<u16>()?;
+            if index as usize >= Self::BLOCK_COUNT {
+                return Err(DeserializationError::InvalidPacketContent);
+            }
+            changes.push((index, buf.read()?));
+        }
+        Ok(Self { chunk, changes })
+    }
+}
+
+///the answer to a [`crate::c2s::PingPacket`], the timestamp is sent back unchanged
+pub struct PongPacket {
+    pub timestamp: u64,
+}
+
+impl Packet for PongPacket {
+    const ID: PacketId = 1;
+    fn serialize(self) -> WritingByteBuf {
+        let mut buf = Self::get_writing_byte_buff(mem::size_of::<u64>());
+        buf.write(self.timestamp);
+        buf
+    }
+
+    fn deserialize(mut buf: ReadingByteBuf) -> Result<Self, DeserializationError> {
+        Ok(Self {
+            timestamp: buf.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::errors::DeserializationError;
+    use crate::packets::{ByteBuf, Packet, ReadingByteBuf};
+    use crate::s2c::{BlockBatchPacket, ChunkDataPacket, PlayerPositionPacket};
+    use math::positions::{ChunkPos, EntityPos};
+    use math::Vec3;
+
+    #[test]
+    pub fn chunk_data_packet_round_trip() {
+        let pos = ChunkPos::new(-3, 7, 1 << 20);
+        let data: ByteBuf = ChunkDataPacket {
+            pos,
+            data: vec![1, 2, 3, 4],
+        }
+        .serialize()
+        .into();
+
+        let buf = ReadingByteBuf::new(data);
+        assert_eq!(buf.get_packet_id(), ChunkDataPacket::ID);
+        let packet = ChunkDataPacket::deserialize(buf).unwrap();
+        assert_eq!(packet.pos, pos);
+        assert_eq!(packet.data, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    pub fn block_batch_packet_round_trip() {
+        let changes = vec![(0, 7), (4095, 0), (273, u16::MAX)];
+        let data: ByteBuf = BlockBatchPacket {
+            chunk: ChunkPos::new(5, -6, 7),
+            changes: changes.clone(),
+        }
+        .serialize()
+        .into();
+
+        let buf = ReadingByteBuf::new(data);
+        assert_eq!(buf.get_packet_id(), BlockBatchPacket::ID);
+        let packet = BlockBatchPacket::deserialize(buf).unwrap();
+        assert_eq!(packet.chunk, ChunkPos::new(5, -6, 7));
+        assert_eq!(packet.changes, changes);
+    }
+
+    #[test]
+    pub fn block_batch_indices_out_of_the_chunk_are_rejected() {
+        let mut buf = BlockBatchPacket::get_writing_byte_buff(16);
+        for coordinate in [0i32, 0, 0] {
+            buf.write(coordinate);
+        }
+        buf.write(1u32);
+        buf.write(4096u16);
+        buf.write(1u16);
+        let result = BlockBatchPacket::deserialize(ReadingByteBuf::new(buf.into()));
+        assert!(matches!(
+            result,
+            Err(DeserializationError::InvalidPacketContent)
+        ));
+    }
+
+    #[test]
+    pub fn player_position_packet_round_trip() {
+        let pos = EntityPos::new(ChunkPos::new(-1, 2, -300), Vec3::new(0.5, 15.75, 3.0));
+        let data: ByteBuf = PlayerPositionPacket {
+            sequence: 4_000_000_000,
+            pos,
+        }
+        .serialize()
+        .into();
+
+        let buf = ReadingByteBuf::new(data);
+        assert_eq!(buf.get_packet_id(), PlayerPositionPacket::ID);
+        let packet = PlayerPositionPacket::deserialize(buf).unwrap();
+        assert_eq!(packet.sequence, 4_000_000_000);
+        assert_eq!(packet.pos, pos);
+    }
+}
@@ -0,0 +1,42 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+///the size of the buffers of the socket by default, the default of the OS is often too small and drops packets under load
+pub const DEFAULT_SOCKET_BUFFER_SIZE: usize = 1 << 20;
+
+///bind an UDP socket with the given receive and send buffer sizes, in bytes, the socket is non-blocking like renet expects
+///the OS may cap the buffer sizes (net.core.rmem_max and wmem_max on Linux), the socket is still created in this case
+pub fn bind_udp_socket(
+    address: SocketAddr,
+    recv_buffer_size: usize,
+    send_buffer_size: usize,
+) -> io::Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_recv_buffer_size(recv_buffer_size)?;
+    socket.set_send_buffer_size(send_buffer_size)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::socket::bind_udp_socket;
+    use std::io::ErrorKind;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    #[test]
+    pub fn the_socket_is_non_blocking() {
+        let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0);
+        let socket = bind_udp_socket(address, 1 << 16, 1 << 16).unwrap();
+
+        let mut buf = [0; 16];
+        let error = socket.recv_from(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    }
+}
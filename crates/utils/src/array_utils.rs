@@ -1,5 +1,34 @@
-/// Utils to get many references to the elements of an array
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayIndexError {
+    ///the index is not smaller than the length of the array
+    OutOfBounds { index: usize, len: usize },
+    ///the element at this index has already been given by the iterator
+    AlreadyBorrowed(usize),
+}
 
+impl Error for ArrayIndexError {}
+
+impl Display for ArrayIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrayIndexError::OutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "the index {} is out of bounds, the length is {}",
+                    index, len
+                )
+            }
+            ArrayIndexError::AlreadyBorrowed(index) => {
+                write!(f, "the element at index {} is already borrowed", index)
+            }
+        }
+    }
+}
+
+/// Utils to get many references to the elements of an array
 pub trait ArrayUtils<T> {
     fn create_ref_iter<'a>(
         &'a self,
@@ -8,8 +37,19 @@ pub trait ArrayUtils<T> {
     where
         T: 'a;
 
-    ///get an iterator of mutable reference to the elements at the given indexes, panic if the indexes are out of bounds or the same element is borrowed many times
-    fn create_mut_iter<'a>(
+    ///get an iterator of mutable reference to the elements at the given indexes
+    ///an index out of bounds or an element borrowed many times gives an error instead of the reference, in all builds
+    fn try_create_mut_iter<'a>(
+        &'a mut self,
+        iter: impl Iterator<Item = usize>,
+    ) -> impl Iterator<Item = Result<&'a mut T, ArrayIndexError>>
+    where
+        T: 'a;
+
+    ///same as try_create_mut_iter without any check
+    ///# Safety
+    ///the indexes must be in bounds and distinct, else the behavior is undefined
+    unsafe fn create_mut_iter_unchecked<'a>(
         &'a mut self,
         iter: impl Iterator<Item = usize>,
     ) -> impl Iterator<Item = &'a mut T>
@@ -28,36 +68,63 @@ impl<T, const N: usize> ArrayUtils<T> for [T; N] {
         iter.map(move |i| &self[i])
     }
 
-    #[cfg(not(debug_assertions))]
-    fn create_mut_iter<'a>(
+    fn try_create_mut_iter<'a>(
         &'a mut self,
         iter: impl Iterator<Item = usize>,
-    ) -> impl Iterator<Item = &'a mut T>
+    ) -> impl Iterator<Item = Result<&'a mut T, ArrayIndexError>>
     where
         T: 'a,
     {
-        unsafe { iter.map(move |i| &mut *(&mut self[i] as *mut T)) }
+        let mut borrowed = [false; N];
+        let elements = self.as_mut_ptr();
+        iter.map(move |i| {
+            if i >= N {
+                return Err(ArrayIndexError::OutOfBounds { index: i, len: N });
+            }
+            if borrowed[i] {
+                return Err(ArrayIndexError::AlreadyBorrowed(i));
+            }
+            borrowed[i] = true;
+            //the index is in bounds and each element is given only once, so the references don't alias
+            Ok(unsafe { &mut *elements.add(i) })
+        })
     }
 
-    #[cfg(debug_assertions)]
-    fn create_mut_iter<'a>(
+    unsafe fn create_mut_iter_unchecked<'a>(
         &'a mut self,
         iter: impl Iterator<Item = usize>,
     ) -> impl Iterator<Item = &'a mut T>
     where
         T: 'a,
     {
-        let mut borrowed = [false; N];
-        unsafe {
-            iter.map(move |i| {
-                assert_eq!(
-                    borrowed[i], false,
-                    "the element at index {} is already borrowed",
-                    i
-                );
-                borrowed[i] = true;
-                &mut *(&mut self[i] as *mut T)
-            })
+        let elements = self.as_mut_ptr();
+        iter.map(move |i| unsafe { &mut *elements.add(i) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::array_utils::{ArrayIndexError, ArrayUtils};
+
+    #[test]
+    pub fn try_create_mut_iter_checks_the_indexes() {
+        let mut array = [0, 1, 2, 3];
+        for element in array.try_create_mut_iter([3, 1].into_iter()) {
+            *element.unwrap() *= 10;
         }
+        assert_eq!(array, [0, 10, 2, 30]);
+
+        let results: Vec<_> = array
+            .try_create_mut_iter([2, 4, 2].into_iter())
+            .map(|element| element.map(|element| *element))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(2),
+                Err(ArrayIndexError::OutOfBounds { index: 4, len: 4 }),
+                Err(ArrayIndexError::AlreadyBorrowed(2)),
+            ]
+        );
     }
 }
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    ///the line is not a `key = value` pair
+    InvalidLine(usize),
+    ///the value of the key can't be parsed
+    InvalidValue(String),
+}
+
+impl Error for ConfigError {}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "can't read the config file: {}", e),
+            ConfigError::InvalidLine(line) => write!(f, "invalid line {} in the config file", line),
+            ConfigError::InvalidValue(key) => write!(f, "invalid value for the key {}", key),
+        }
+    }
+}
+
+/// A minimal config file made of `key = value` lines, empty lines and lines starting with `#` are ignored
+pub struct ConfigFile {
+    entries: HashMap<String, String>,
+}
+
+impl ConfigFile {
+    ///read the config file at the given path, an absent file is considered empty
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self {
+                entries: HashMap::new(),
+            }),
+            Err(e) => Err(ConfigError::Io(e)),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        let mut entries = HashMap::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(ConfigError::InvalidLine(i + 1))?;
+            entries.insert(key.trim().to_string(), value.trim().to_string());
+        }
+        Ok(Self { entries })
+    }
+
+    ///get the value of a key, return None if the key is absent
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        match self.entries.get(key) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| ConfigError::InvalidValue(key.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    ///get the value of a key, or the given default value if the key is absent
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> Result<T, ConfigError> {
+        Ok(self.get(key)?.unwrap_or(default))
+    }
+}
@@ -1,4 +1,5 @@
 #![doc = include_str!("../README.md")]
 pub mod array_utils;
+pub mod config_file;
 pub mod memory_utils;
 pub mod spare_set;
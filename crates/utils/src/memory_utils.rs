@@ -1,24 +1,27 @@
 use std::fmt::Display;
+use std::ops::{Add, AddAssign};
 
-/// Represents the size of memory in bytes, kilobytes, megabytes, or gigabytes. This is useful for displaying memory usage.
-pub enum MemorySize {
-    Bytes(usize),
-    KiloBytes(usize),
-    MegaBytes(usize),
-    GigaBytes(usize),
+/// Represents a size of memory. The exact byte count is kept, the unit (bytes, kilobytes, megabytes or gigabytes) is only chosen when displayed. This is useful for displaying memory usage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemorySize {
+    bytes: usize,
 }
 
 impl MemorySize {
-    fn new(bytes: usize) -> Self {
-        if bytes < 1024 {
-            Self::Bytes(bytes)
-        } else if bytes < 1024 * 1024 {
-            Self::KiloBytes(bytes / 1024)
-        } else if bytes < 1024 * 1024 * 1024 {
-            Self::MegaBytes(bytes / (1024 * 1024))
-        } else {
-            Self::GigaBytes(bytes / (1024 * 1024 * 1024))
+    pub fn new(bytes: usize) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    ///the part of the total this size represents, 0 if the total is empty
+    pub fn ratio(&self, total: &MemorySize) -> f32 {
+        if total.bytes == 0 {
+            return 0.0;
         }
+        (self.bytes as f64 / total.bytes as f64) as f32
     }
 }
 
@@ -28,13 +31,73 @@ impl From<usize> for MemorySize {
     }
 }
 
+impl Add for MemorySize {
+    type Output = MemorySize;
+
+    fn add(self, other: MemorySize) -> MemorySize {
+        MemorySize::new(self.bytes + other.bytes)
+    }
+}
+
+impl AddAssign for MemorySize {
+    fn add_assign(&mut self, other: MemorySize) {
+        self.bytes += other.bytes;
+    }
+}
+
+impl std::iter::Sum for MemorySize {
+    fn sum<I: Iterator<Item = MemorySize>>(iter: I) -> Self {
+        iter.fold(MemorySize::default(), Add::add)
+    }
+}
+
 impl Display for MemorySize {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            MemorySize::Bytes(bytes) => write!(f, "{} bytes", bytes),
-            MemorySize::KiloBytes(kb) => write!(f, "{} KB", kb),
-            MemorySize::MegaBytes(mb) => write!(f, "{} MB", mb),
-            MemorySize::GigaBytes(gb) => write!(f, "{} GB", gb),
+        let bytes = self.bytes;
+        if bytes < 1024 {
+            write!(f, "{} bytes", bytes)
+        } else if bytes < 1024 * 1024 {
+            write!(f, "{} KB", bytes / 1024)
+        } else if bytes < 1024 * 1024 * 1024 {
+            write!(f, "{} MB", bytes / (1024 * 1024))
+        } else {
+            write!(f, "{} GB", bytes / (1024 * 1024 * 1024))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::memory_utils::MemorySize;
+
+    #[test]
+    pub fn addition_keeps_every_byte() {
+        //displayed as 1 KB each, but the sum is 3 KB and not 2 KB
+        let size = MemorySize::from(1536);
+        assert_eq!(size.to_string(), "1 KB");
+        assert_eq!((size + size).to_string(), "3 KB");
+        assert_eq!((size + size).bytes(), 3072);
+
+        let mut total = MemorySize::default();
+        total += MemorySize::from(10);
+        assert_eq!(total.bytes(), 10);
+        let sum: MemorySize = [1, 2, 3].into_iter().map(MemorySize::from).sum();
+        assert_eq!(sum.bytes(), 6);
+    }
+
+    #[test]
+    pub fn ratio_of_the_total() {
+        let used = MemorySize::from(256);
+        let total = MemorySize::from(1024);
+        assert_eq!(used.ratio(&total), 0.25);
+        assert_eq!(total.ratio(&total), 1.0);
+        assert_eq!(used.ratio(&MemorySize::default()), 0.0);
+    }
+
+    #[test]
+    pub fn display_chooses_the_unit() {
+        assert_eq!(MemorySize::from(1023).to_string(), "1023 bytes");
+        assert_eq!(MemorySize::from(5 * 1024 * 1024).to_string(), "5 MB");
+        assert_eq!(MemorySize::from(2 * 1024 * 1024 * 1024).to_string(), "2 GB");
+    }
+}
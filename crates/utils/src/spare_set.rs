@@ -150,6 +150,25 @@ impl<T> SparseSet<T> {
         }
     }
 
+    ///whether an element is stored at the given ID
+    pub fn contains(&self, id: Id) -> bool {
+        self.sparse_get_dense_pos(id) != Self::EMPTY
+    }
+
+    ///get the element at the given ID, it is inserted with the value returned by `f` if it doesn't exist
+    pub fn get_or_insert_with(&mut self, id: Id, f: impl FnOnce() -> T) -> &mut T {
+        let mut dense_pos = self.sparse_get_dense_pos(id);
+        if dense_pos == Self::EMPTY {
+            dense_pos = self.dense.len() as Uint;
+            self.set_sparse_id(id, dense_pos);
+            self.dense.push(DenseNode {
+                sparse_pos: id,
+                value: f(),
+            });
+        }
+        &mut self.dense[dense_pos as usize].value
+    }
+
     ///Remove the element at the given ID if it exists, return it
     pub fn remove(&mut self, id: Id) -> Option<T> {
         if self.dense.is_empty() {
@@ -189,6 +208,24 @@ impl<T> SparseSet<T> {
         self.dense.capacity()
     }
 
+    ///reserve the dense array for at least `additional` more elements
+    pub fn reserve(&mut self, additional: usize) {
+        self.dense.reserve(additional);
+    }
+
+    ///free the unused memory, the dense array is shrunk to its length and the sparse array to the biggest stored ID
+    ///the trailing entries of the sparse array are all empty, so removing them doesn't change any stored ID
+    pub fn shrink_to_fit(&mut self) {
+        self.dense.shrink_to_fit();
+        let sparse_len = self
+            .sparse
+            .iter()
+            .rposition(|&dense_pos| dense_pos != Self::EMPTY)
+            .map_or(0, |last| last + 1);
+        self.sparse.truncate(sparse_len);
+        self.sparse.shrink_to_fit();
+    }
+
     ///iterate over the elements of the SparseSet, the order is not specified, but it is guaranteed that all the elements will be visited once
     ///iterating over the SparseSet take O(len) time
     pub fn iter(&self) -> impl Iterator<Item = (Id, &T)> {
@@ -238,4 +275,60 @@ mod test {
 
         sparse_set.assert_sparse_valid();
     }
+
+    #[test]
+    pub fn shrink_to_fit_keeps_the_elements() {
+        let mut sparse_set = SparseSet::with_capacity(10);
+        sparse_set.reserve(1000);
+        assert!(sparse_set.capacity() >= 1000);
+
+        for i in 0..500 {
+            sparse_set.insert(Id(i), i);
+        }
+        for i in 10..500 {
+            sparse_set.remove(Id(i));
+        }
+        sparse_set.remove(Id(3));
+        sparse_set.shrink_to_fit();
+        sparse_set.assert_sparse_valid();
+
+        assert!(sparse_set.capacity() < 1000);
+        assert_eq!(sparse_set.sparse.len(), 10);
+        assert_eq!(sparse_set.len(), 9);
+        assert_eq!(sparse_set.get(Id(9)), Some(&9));
+        assert_eq!(sparse_set.get(Id(3)), None);
+        assert_eq!(sparse_set.get(Id(400)), None);
+
+        //the removed IDs can be used again
+        sparse_set.insert(Id(400), 400);
+        assert_eq!(sparse_set.get(Id(400)), Some(&400));
+        sparse_set.assert_sparse_valid();
+    }
+
+    #[test]
+    pub fn get_or_insert_with_inserts_once() {
+        let mut sparse_set = SparseSet::new();
+        sparse_set.insert(Id(5), 5);
+        assert!(sparse_set.contains(Id(5)));
+        assert!(!sparse_set.contains(Id(4)));
+        assert!(!sparse_set.contains(Id(1000)));
+
+        *sparse_set.get_or_insert_with(Id(5), || unreachable!()) += 1;
+        assert_eq!(sparse_set.get(Id(5)), Some(&6));
+
+        for i in (0..20).rev() {
+            assert_eq!(*sparse_set.get_or_insert_with(Id(i * 3), || i * 3), i * 3);
+            sparse_set.assert_sparse_valid();
+        }
+        sparse_set.insert(Id(7), 7);
+        sparse_set.remove(Id(9));
+        assert_eq!(*sparse_set.get_or_insert_with(Id(9), || 90), 90);
+        sparse_set.assert_sparse_valid();
+
+        assert_eq!(sparse_set.len(), 22);
+        assert!(sparse_set.contains(Id(7)));
+        assert!(sparse_set.contains(Id(57)));
+        assert!(!sparse_set.contains(Id(58)));
+        assert_eq!(sparse_set.get(Id(15)), Some(&15));
+    }
 }
@@ -0,0 +1,246 @@
+use math::aabb::AABB;
+use math::positions::{BlockPos, ChunkPos};
+use math::IVec3;
+use std::collections::{HashMap, HashSet};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use world_core::block_state::BlockState;
+use world_core::{Chunk, ChunkManager, ChunkSnapshot};
+
+///the loaded chunks form a cube of this side, 4096 chunks
+const SIDE: i32 = 16;
+
+///run the function the given number of times and print the mean duration of a run
+fn bench(name: &str, runs: u32, mut func: impl FnMut()) {
+    func(); //warm up
+    let start = Instant::now();
+    for _ in 0..runs {
+        func();
+    }
+    println!("{name}: {:?} per run", start.elapsed() / runs);
+}
+
+///like [`bench`], but the setup and the drop of the result aren't timed
+fn bench_with_setup<T, R>(
+    name: &str,
+    runs: u32,
+    mut setup: impl FnMut() -> T,
+    mut func: impl FnMut(T) -> R,
+) {
+    drop(func(setup())); //warm up
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..runs {
+        let input = setup();
+        let start = Instant::now();
+        let output = func(input);
+        elapsed += start.elapsed();
+        drop(output);
+    }
+    println!("{name}: {:?} per run", elapsed / runs);
+}
+
+fn world_aabb() -> AABB {
+    AABB::new(IVec3::ZERO, IVec3::splat(SIDE))
+}
+
+///a cube of chunks with a few blocks each, so they are stored on 4 bits like most of the terrain
+fn world() -> ChunkManager {
+    let mut manager = ChunkManager::new();
+    let chunks = (0..SIDE * SIDE * SIDE).map(|i| {
+        let mut chunk = Chunk::new(chunk_pos(i));
+        for x in 0..4 {
+            chunk.set_block_at(x, 0, 0, BlockState::new(1 + x as u16));
+        }
+        chunk
+    });
+    manager.insert_chunks(chunks).unwrap();
+    manager
+}
+
+fn chunk_pos(index: i32) -> ChunkPos {
+    ChunkPos::new(index % SIDE, index / SIDE % SIDE, index / (SIDE * SIDE))
+}
+
+///set one block in each of `count` chunks, different chunks at each tick
+fn edit(manager: &mut ChunkManager, tick: i32, count: i32) {
+    for i in 0..count {
+        let chunk = chunk_pos((tick * count + i) % (SIDE * SIDE * SIDE));
+        let state = BlockState::new(1 + (tick % 2) as u16);
+        manager.set_block(chunk * Chunk::SIZE + BlockPos::new(5, 5, 5), state);
+    }
+}
+
+///the side of the sections locked one by one in the RwLock version
+const LOCKED_SECTION_SIDE: i32 = 8;
+
+///the main thread edits the world while another thread reads all of it, like a mesher or a save would
+///with the snapshots the reader never blocks the ticks, the chunks it holds are copied on the first write
+///the ChunkManager can't be shared between threads, so the locking alternative is a RwLock per section of 8³ chunks,
+///the reader locks the sections one at a time and a tick waits when it edits the section being read
+fn snapshot_against_rwlock() {
+    const TICKS: u32 = 100;
+    for edits in [16, 256] {
+        let mut manager = world();
+        manager.on_process_modified_chunks(|_| ());
+        let (sender, receiver) = mpsc::sync_channel::<ChunkSnapshot>(1);
+        let reader = thread::spawn(move || {
+            for snapshot in receiver {
+                black_box(snapshot.iter().map(Chunk::block_count).sum::<usize>());
+            }
+        });
+        let mut tick = 0;
+        bench(&format!("snapshot, {edits} edits per tick"), TICKS, || {
+            //the snapshot is dropped if the reader is still busy with the previous one
+            let _ = sender.try_send(manager.snapshot());
+            edit(&mut manager, tick, edits);
+            manager.on_process_modified_chunks(|_| ());
+            tick += 1;
+        });
+        drop(sender);
+        reader.join().unwrap();
+
+        let section_count = (SIDE / LOCKED_SECTION_SIDE).pow(3);
+        let section_index = |pos: ChunkPos| {
+            let pos = pos / LOCKED_SECTION_SIDE;
+            let side = SIDE / LOCKED_SECTION_SIDE;
+            (pos.x + pos.y * side + pos.z * side * side) as usize
+        };
+        let mut sections = (0..section_count)
+            .map(|_| HashMap::new())
+            .collect::<Vec<_>>();
+        world().foreach_chunk_in(world_aabb(), &mut |_, chunk| {
+            sections[section_index(chunk.position())].insert(chunk.position(), chunk.clone());
+        });
+        let sections: Arc<Vec<RwLock<HashMap<ChunkPos, Chunk>>>> =
+            Arc::new(sections.into_iter().map(RwLock::new).collect());
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (sections, stop) = (sections.clone(), stop.clone());
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let mut count = 0;
+                    for section in sections.iter() {
+                        let section = section.read().unwrap();
+                        count += section.values().map(Chunk::block_count).sum::<usize>();
+                    }
+                    black_box(count);
+                }
+            })
+        };
+        let mut tick = 0;
+        bench(&format!("RwLock, {edits} edits per tick"), TICKS, || {
+            for i in 0..edits {
+                let pos = chunk_pos((tick * edits + i) % (SIDE * SIDE * SIDE));
+                let mut section = sections[section_index(pos)].write().unwrap();
+                let chunk = section.get_mut(&pos).unwrap();
+                chunk.set_block_at(5, 5, 5, BlockState::new(1 + (tick % 2) as u16));
+            }
+            tick += 1;
+        });
+        stop.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+    }
+}
+
+///a tick of heavy edits, many blocks set in the same chunks, then the modified chunks processed
+///the lists of the previous versions, sorted at each tick or deduplicated by a hash set, are replayed on the same marks to compare
+fn dirty_list_under_heavy_edits() {
+    const TICKS: u32 = 20;
+    const EDITED_CHUNKS: i32 = 512;
+    for edits_per_chunk in [1, 64] {
+        let mut manager = world();
+        manager.on_process_modified_chunks(|_| ());
+        let mut tick = 0;
+        let name = format!("dirty list, {edits_per_chunk} edits in each of {EDITED_CHUNKS} chunks");
+        bench(&name, TICKS, || {
+            for block in 0..edits_per_chunk {
+                for chunk in 0..EDITED_CHUNKS {
+                    let pos = chunk_pos(chunk) * Chunk::SIZE + Chunk::block_pos(block as u16);
+                    manager.set_block(pos, BlockState::new(1 + (tick % 2) as u16));
+                }
+            }
+            manager.on_process_modified_chunks(|ids| {
+                black_box(ids);
+            });
+            tick += 1;
+        });
+
+        //the marks alone, in the order of the edits
+        let marks = (0..edits_per_chunk)
+            .flat_map(|_| 0..EDITED_CHUNKS as u32)
+            .collect::<Vec<_>>();
+        bench(&format!("{name}, marks sorted at each tick"), TICKS, || {
+            let mut list = Vec::new();
+            for id in &marks {
+                list.push(*id);
+            }
+            list.sort_unstable();
+            list.dedup();
+            black_box(list);
+        });
+        bench(
+            &format!("{name}, marks deduplicated by a set"),
+            TICKS,
+            || {
+                let (mut list, mut contained) = (Vec::new(), HashSet::new());
+                for id in &marks {
+                    if contained.insert(*id) {
+                        list.push(*id);
+                    }
+                }
+                black_box(list);
+            },
+        );
+        //the current list, a bit per ID
+        bench(
+            &format!("{name}, marks deduplicated by bits"),
+            TICKS,
+            || {
+                let (mut list, mut contained) = (Vec::new(), Vec::<u64>::new());
+                for id in &marks {
+                    let (word, bit) = (*id as usize / 64, 1 << (id % 64));
+                    if word >= contained.len() {
+                        contained.resize(word + 1, 0);
+                    }
+                    if contained[word] & bit == 0 {
+                        contained[word] |= bit;
+                        list.push(*id);
+                    }
+                }
+                black_box(list);
+            },
+        );
+    }
+}
+
+///the bulk insertion against a loop of insert_chunk, on the cube of chunks generated around the client at startup
+fn insert_chunks_against_insert_chunk() {
+    const RUNS: u32 = 20;
+    let cube = || {
+        (-20..20)
+            .flat_map(|x| (-20..20).flat_map(move |z| (-5..5).map(move |y| ChunkPos::new(x, y, z))))
+            .map(Chunk::new)
+            .collect::<Vec<_>>()
+    };
+    bench_with_setup("insert_chunk loop, 16000 chunks", RUNS, cube, |chunks| {
+        let mut manager = ChunkManager::new();
+        for chunk in chunks {
+            manager.insert_chunk(chunk).unwrap();
+        }
+        manager
+    });
+    bench_with_setup("insert_chunks, 16000 chunks", RUNS, cube, |chunks| {
+        let mut manager = ChunkManager::new();
+        manager.insert_chunks(chunks).unwrap();
+        manager
+    });
+}
+
+fn main() {
+    snapshot_against_rwlock();
+    dirty_list_under_heavy_edits();
+    insert_chunks_against_insert_chunk();
+}
@@ -0,0 +1,235 @@
+use crate::block_state::{BlockState, AIR};
+use math::IVec3;
+use std::collections::HashMap;
+
+///the six faces of a block, in the order of [`BlockInfo::texture_indices`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFace {
+    Top,
+    Bottom,
+    West,  //x-
+    East,  //x+
+    North, //z-
+    South, //z+
+}
+
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::Top,
+        BlockFace::Bottom,
+        BlockFace::West,
+        BlockFace::East,
+        BlockFace::North,
+        BlockFace::South,
+    ];
+
+    ///the unit vector pointing outside the block through this face
+    pub fn normal(self) -> IVec3 {
+        match self {
+            BlockFace::Top => IVec3::Y,
+            BlockFace::Bottom => IVec3::NEG_Y,
+            BlockFace::West => IVec3::NEG_X,
+            BlockFace::East => IVec3::X,
+            BlockFace::North => IVec3::NEG_Z,
+            BlockFace::South => IVec3::Z,
+        }
+    }
+}
+
+///the properties shared by all the blocks with the same state
+#[derive(Clone, Debug)]
+pub struct BlockInfo {
+    pub name: String,
+    ///the layer of each face in the texture atlas, indexed by [`BlockFace`]
+    pub texture_indices: [u32; 6],
+    ///the faces next to a transparent block are visible
+    pub transparent: bool,
+    ///the entities can't go through a solid block
+    pub solid: bool,
+}
+
+impl BlockInfo {
+    ///a solid and opaque block with the same texture on all its faces
+    pub fn cube(name: &str, texture_index: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            texture_indices: [texture_index; 6],
+            transparent: false,
+            solid: true,
+        }
+    }
+
+    pub fn texture_index(&self, face: BlockFace) -> u32 {
+        self.texture_indices[face as usize]
+    }
+}
+
+///map each BlockState to the properties of the block, the states are attributed in the registration order
+pub struct BlockRegistry {
+    blocks: Vec<BlockInfo>,
+    states_by_name: HashMap<String, BlockState>,
+}
+
+impl BlockRegistry {
+    ///create a registry that only contains air
+    pub fn new() -> Self {
+        let mut registry = Self {
+            blocks: Vec::new(),
+            states_by_name: HashMap::new(),
+        };
+        let air = registry.register(BlockInfo {
+            name: "air".to_string(),
+            texture_indices: [0; 6],
+            transparent: true,
+            solid: false,
+        });
+        debug_assert_eq!(air, AIR);
+        registry
+    }
+
+    ///register a block and return its state, panic if the name is already used or if there is no more state available
+    pub fn register(&mut self, info: BlockInfo) -> BlockState {
+        assert!(
+            !self.states_by_name.contains_key(&info.name),
+            "the block {} is already registered",
+            info.name
+        );
+        let id = u16::try_from(self.blocks.len()).expect("too many blocks registered");
+        let state = BlockState::new(id);
+        self.states_by_name.insert(info.name.clone(), state);
+        self.blocks.push(info);
+        state
+    }
+
+    pub fn get(&self, state: BlockState) -> Option<&BlockInfo> {
+        self.blocks.get(state.id() as usize)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<BlockState> {
+        self.states_by_name.get(name).copied()
+    }
+
+    ///unknown states are considered transparent, so the blocks next to them stay visible
+    pub fn is_transparent(&self, state: BlockState) -> bool {
+        match self.get(state) {
+            Some(info) => info.transparent,
+            None => true,
+        }
+    }
+
+    ///whether the face of the block `here` touching the block `there` must be drawn
+    ///a face is hidden by an opaque block, or by the same transparent block, like the faces between two water blocks
+    pub fn is_face_visible(&self, here: BlockState, there: BlockState) -> bool {
+        here != there && self.is_transparent(there)
+    }
+
+    ///unknown states are not solid
+    pub fn is_solid(&self, state: BlockState) -> bool {
+        self.get(state).is_some_and(|info| info.solid)
+    }
+
+    ///give each block the texture with the same name, the textures are given with their index in the atlas
+    ///the blocks without a texture of their name keep their texture indices
+    pub fn assign_textures(&mut self, texture_indices: &HashMap<String, u32>) {
+        for block in &mut self.blocks {
+            if let Some(texture_index) = texture_indices.get(&block.name) {
+                block.texture_indices = [*texture_index; 6];
+            }
+        }
+    }
+
+    ///the number of registered blocks, air included
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+}
+
+impl Default for BlockRegistry {
+    ///the blocks of the generator, in the order of their states
+    ///the texture indices are placeholders until [`BlockRegistry::assign_textures`] gives them the layers of the atlas
+    fn default() -> Self {
+        let mut registry = Self::new();
+        let blocks = [
+            "stone",
+            "diamond_block",
+            "emerald_block",
+            "lapis_block",
+            "gold_block",
+            "iron_block",
+            "coal_block",
+            "red_wool",
+            "hay_block_top",
+            "hay_block_side",
+            "grass_block",
+        ];
+        for (texture_index, name) in blocks.iter().enumerate() {
+            registry.register(BlockInfo::cube(name, texture_index as u32));
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block_registry::{BlockInfo, BlockRegistry};
+    use crate::block_state::{BlockState, AIR};
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn default_registry_has_the_generator_blocks() {
+        let registry = BlockRegistry::default();
+        assert_eq!(registry.block_count(), 12);
+        assert!(registry.is_transparent(AIR));
+        assert!(!registry.is_solid(AIR));
+
+        //the states are the ones of the generator, the texture indices are placeholders until assign_textures runs
+        assert_eq!(registry.get_by_name("stone"), Some(BlockState::new(1)));
+        assert_eq!(
+            registry.get_by_name("grass_block"),
+            Some(BlockState::new(11))
+        );
+
+        assert!(registry.is_transparent(BlockState::new(1000)));
+    }
+
+    #[test]
+    pub fn textures_are_assigned_by_name() {
+        let mut registry = BlockRegistry::default();
+        let textures = HashMap::from([("stone".to_string(), 7), ("unknown".to_string(), 3)]);
+        registry.assign_textures(&textures);
+
+        let stone = registry.get_by_name("stone").unwrap();
+        assert_eq!(registry.get(stone).unwrap().texture_indices, [7; 6]);
+        let grass = registry.get_by_name("grass_block").unwrap();
+        assert_eq!(registry.get(grass).unwrap().texture_indices, [10; 6]);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn names_are_unique() {
+        let mut registry = BlockRegistry::new();
+        registry.register(BlockInfo::cube("stone", 0));
+        registry.register(BlockInfo::cube("stone", 0));
+    }
+
+    #[test]
+    pub fn faces_are_hidden_by_opaque_blocks() {
+        let mut registry = BlockRegistry::default();
+        let stone = registry.get_by_name("stone").unwrap();
+        let grass = registry.get_by_name("grass_block").unwrap();
+        let glass = registry.register(BlockInfo {
+            transparent: true,
+            ..BlockInfo::cube("glass", 0)
+        });
+
+        assert!(registry.is_face_visible(stone, AIR));
+        assert!(!registry.is_face_visible(stone, grass));
+        assert!(!registry.is_face_visible(stone, stone));
+        //between an opaque and a transparent block, only the face of the opaque block is drawn
+        assert!(registry.is_face_visible(stone, glass));
+        assert!(!registry.is_face_visible(glass, stone));
+        assert!(!registry.is_face_visible(glass, glass));
+        assert!(registry.is_face_visible(glass, AIR));
+        assert!(registry.is_face_visible(stone, BlockState::new(1000)));
+    }
+}
@@ -1,4 +1,78 @@
-//TODO: make a proper block state system
+use std::fmt;
 
-pub type BlockState = u16;
-pub const AIR: BlockState = 0;
+///the state of a block, it is the index of the block in the [`crate::block_registry::BlockRegistry`]
+///the state 0 is always air, the chunks palettes rely on it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct BlockState(u16);
+
+pub const AIR: BlockState = BlockState::AIR;
+
+impl BlockState {
+    pub const AIR: BlockState = BlockState(0);
+
+    pub const fn new(id: u16) -> Self {
+        Self(id)
+    }
+
+    pub const fn id(self) -> u16 {
+        self.0
+    }
+
+    pub const fn is_air(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 2]) -> Self {
+        Self(u16::from_le_bytes(bytes))
+    }
+}
+
+impl From<u16> for BlockState {
+    fn from(id: u16) -> Self {
+        Self(id)
+    }
+}
+
+impl From<BlockState> for u16 {
+    fn from(state: BlockState) -> Self {
+        state.0
+    }
+}
+
+///the generator gives the states as i32
+impl TryFrom<i32> for BlockState {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(id: i32) -> Result<Self, Self::Error> {
+        u16::try_from(id).map(Self)
+    }
+}
+
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_air() {
+            write!(f, "air")
+        } else {
+            write!(f, "#{}", self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block_state::{BlockState, AIR};
+
+    #[test]
+    pub fn conversions_are_checked() {
+        assert!(AIR.is_air());
+        assert!(!BlockState::new(1).is_air());
+        assert_eq!(BlockState::try_from(-1).ok(), None);
+        assert_eq!(AIR.to_string(), "air");
+        assert_eq!(BlockState::new(12).to_string(), "#12");
+    }
+}
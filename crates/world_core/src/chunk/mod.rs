@@ -3,11 +3,99 @@ mod implementation;
 use crate::block_state::{BlockState, AIR};
 use ctor::ctor;
 use implementation::{Chunk4Bits, Chunk8Bits, ChunkNative, InMemoryChunk};
+use math::aabb::AABB;
 use math::positions::{BlockPos, ChunkPos};
 use math::{consts::CHUNK_SIZE, IVec3};
 use shared_arena::{ArenaBox, SharedArena};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
 use utils::memory_utils::MemorySize;
 
+///the in-memory formats of the chunks, from the biggest to the smallest, an empty chunk uses no memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChunkFormat {
+    ///a block state per block
+    Native,
+    ///a palette of up to 256 states
+    Bits8,
+    ///a palette of up to 16 states
+    Bits4,
+}
+
+impl ChunkFormat {
+    pub const ALL: [ChunkFormat; 3] = [ChunkFormat::Native, ChunkFormat::Bits8, ChunkFormat::Bits4];
+}
+
+impl Display for ChunkFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkFormat::Native => write!(f, "native"),
+            ChunkFormat::Bits8 => write!(f, "8 bits"),
+            ChunkFormat::Bits4 => write!(f, "4 bits"),
+        }
+    }
+}
+
+///why the bytes given to [`Chunk::deserialize`] are not a chunk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkDecodeError {
+    ///the first byte isn't a known format
+    UnknownFormat(u8),
+    ///the bytes are shorter or longer than the format and the palette length declare, a truncated file for example
+    InvalidLength { expected: usize, found: usize },
+    ///the palette has more entries than the format can hold
+    PaletteTooLarge(usize),
+    ///a block refers to a palette entry that doesn't exist or is free
+    InvalidPaletteIndex(u8),
+}
+
+impl Error for ChunkDecodeError {}
+
+impl Display for ChunkDecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkDecodeError::UnknownFormat(tag) => write!(f, "unknown chunk format {}", tag),
+            ChunkDecodeError::InvalidLength { expected, found } => write!(
+                f,
+                "the chunk data is {} bytes long, but {} bytes were expected",
+                found, expected
+            ),
+            ChunkDecodeError::PaletteTooLarge(len) => {
+                write!(
+                    f,
+                    "the palette has too many entries for its format: {}",
+                    len
+                )
+            }
+            ChunkDecodeError::InvalidPaletteIndex(index) => {
+                write!(f, "a block refers to the missing palette entry {}", index)
+            }
+        }
+    }
+}
+
+///the memory of the chunks of a format
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FormatStats {
+    pub chunk_count: usize,
+    pub used: MemorySize,
+    ///pre-allocated but not used
+    pub free: MemorySize,
+}
+
+impl FormatStats {
+    fn of_arena<T>(arena: &SharedArena<T>) -> Self {
+        let (used, free) = arena.stats();
+        Self {
+            chunk_count: used,
+            used: (used * std::mem::size_of::<T>()).into(),
+            free: (free * std::mem::size_of::<T>()).into(),
+        }
+    }
+}
+
 ///class where all memory used by the chunk is stored, should leave longer than all the world_core loaded in memory
 pub struct ChunkMemoryPool {
     chunks_native: SharedArena<ChunkNative>,
@@ -26,19 +114,28 @@ impl ChunkMemoryPool {
 
     ///return the memory used and the memory pre-allocated but not used
     pub fn stats(&self) -> (MemorySize, MemorySize) {
-        let (native_used, native_free) = self.chunks_native.stats();
-        let (bits8_used, bits8_free) = self.chunks8bits.stats();
-        let (bits4_used, bits4_free) = self.chunks4bits.stats();
-
-        let memory_used = |native_used, bits8_used, bits4_used| {
-            native_used * std::mem::size_of::<ChunkNative>()
-                + bits8_used * std::mem::size_of::<Chunk8Bits>()
-                + bits4_used * std::mem::size_of::<Chunk4Bits>()
-        };
+        self.stats_by_format().iter().fold(
+            (MemorySize::default(), MemorySize::default()),
+            |(used, free), (_, stats)| (used + stats.used, free + stats.free),
+        )
+    }
 
-        let total_used = memory_used(native_used, bits8_used, bits4_used);
-        let total_free = memory_used(native_free, bits8_free, bits4_free);
-        (total_used.into(), total_free.into())
+    ///the memory and the number of chunks of each format, in the order of [`ChunkFormat::ALL`]
+    pub fn stats_by_format(&self) -> [(ChunkFormat, FormatStats); 3] {
+        ChunkFormat::ALL.map(|format| (format, self.format_stats(format)))
+    }
+
+    ///the number of chunks stored in the given format
+    pub fn chunk_count(&self, format: ChunkFormat) -> usize {
+        self.format_stats(format).chunk_count
+    }
+
+    fn format_stats(&self, format: ChunkFormat) -> FormatStats {
+        match format {
+            ChunkFormat::Native => FormatStats::of_arena(&self.chunks_native),
+            ChunkFormat::Bits8 => FormatStats::of_arena(&self.chunks8bits),
+            ChunkFormat::Bits4 => FormatStats::of_arena(&self.chunks4bits),
+        }
     }
 }
 
@@ -53,7 +150,8 @@ enum ChunkHandle {
 pub struct Chunk {
     position: ChunkPos,
     handle: ChunkHandle,
-    //memory map and metadata can be safely added here
+    generation: u64, //bumped on each set block, see [`Chunk::generation`]
+                     //memory map and metadata can be safely added here
 }
 
 #[ctor]
@@ -61,38 +159,167 @@ pub static MEMORY_MANAGER: ChunkMemoryPool = ChunkMemoryPool::new();
 
 impl Chunk {
     pub const SIZE: i32 = CHUNK_SIZE;
+    pub const BLOCK_COUNT: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+    //the first byte of the serialized chunks, see [`Chunk::serialize`]
+    const EMPTY_TAG: u8 = 0;
+    const NATIVE_TAG: u8 = 1;
+    const BITS8_TAG: u8 = 2;
+    const BITS4_TAG: u8 = 3;
 
     pub fn new(position: ChunkPos) -> Self {
         Self {
             position,
             handle: ChunkHandle::ChunkEmpty,
+            generation: 0,
+        }
+    }
+
+    ///build a chunk from all its blocks, indexed by x + y * 16 + z * 256
+    ///the blocks are scanned once to choose the smallest format that can hold them, so the chunk is never promoted
+    pub fn from_block_array(position: ChunkPos, blocks: &[BlockState; Self::BLOCK_COUNT]) -> Self {
+        //build the palette and the palette index of each block at the same time
+        let mut palette = Vec::new();
+        let mut palette_indices = HashMap::new();
+        let mut indices = [0u8; Self::BLOCK_COUNT];
+        for (i, state) in blocks.iter().enumerate() {
+            if *state == AIR {
+                continue; //0 is the static palette_index of air
+            }
+            let index = *palette_indices.entry(*state).or_insert_with(|| {
+                palette.push(*state);
+                palette.len() //+1 because 0 is air
+            });
+            if index > u8::MAX as usize {
+                //too many states for a palette, no need to go further
+                let handle = MEMORY_MANAGER
+                    .chunks_native
+                    .alloc(ChunkNative::from_blocks(blocks));
+                return Self {
+                    position,
+                    handle: ChunkHandle::ChunkNative(handle),
+                    generation: 0,
+                };
+            }
+            indices[i] = index as u8;
+        }
+
+        let handle = match palette.len() {
+            0 => ChunkHandle::ChunkEmpty,
+            1..=15 => ChunkHandle::Chunk4bits(
+                MEMORY_MANAGER
+                    .chunks4bits
+                    .alloc(Chunk4Bits::from_palette(&palette, &indices)),
+            ),
+            _ => ChunkHandle::Chunk8bits(
+                MEMORY_MANAGER
+                    .chunks8bits
+                    .alloc(Chunk8Bits::from_palette(&palette, &indices)),
+            ),
+        };
+        Self {
+            position,
+            handle,
+            generation: 0,
         }
     }
 
     ///promote the chunk to a bigger format, if the chunk is already in the largest format, nothing happens
     ///this function take time and extend the chunk in way that make it use more memory, so it should be used carefully
     pub fn promote(&mut self) {
+        self.promote_in(&MEMORY_MANAGER);
+    }
+
+    ///promote the chunk with the memory of the given pool, the old handle is dropped when it is replaced, which gives its memory back
+    ///the old handle must come from the same pool, each handle goes back to the arena it was allocated from
+    fn promote_in(&mut self, pool: &ChunkMemoryPool) {
         match &self.handle {
             ChunkHandle::ChunkNative(_) => (),
             ChunkHandle::Chunk8bits(handle) => {
-                let mut new_handle = MEMORY_MANAGER.chunks_native.alloc(ChunkNative::new());
+                let mut new_handle = pool.chunks_native.alloc(ChunkNative::new());
                 handle.promote_to(&mut new_handle);
                 self.handle = ChunkHandle::ChunkNative(new_handle);
             }
             ChunkHandle::Chunk4bits(chunk) => {
-                let mut new_handle = MEMORY_MANAGER.chunks8bits.alloc(Chunk8Bits::new());
+                let mut new_handle = pool.chunks8bits.alloc(Chunk8Bits::new());
                 chunk.promote_to(&mut new_handle);
                 self.handle = ChunkHandle::Chunk8bits(new_handle)
             }
             ChunkHandle::ChunkEmpty => {
-                let new_handle = MEMORY_MANAGER.chunks4bits.alloc(Chunk4Bits::new()); //nothing to copy
+                let new_handle = pool.chunks4bits.alloc(Chunk4Bits::new()); //nothing to copy
                 self.handle = ChunkHandle::Chunk4bits(new_handle)
             }
         }
     }
 
-    ///get the blockstate at the given position
+    ///move the chunk to the smallest format that can hold its blocks, an empty chunk if it only contains air
+    ///a chunk is never moved to a smaller format when its blocks are set, only with this function, return true if it did
+    ///it scans the whole chunk, see [`Chunk::can_demote`] to check it without modifying the chunk
+    pub fn demote(&mut self) -> bool {
+        self.demote_in(&MEMORY_MANAGER)
+    }
+
+    ///whether [`Chunk::demote`] would move the chunk to a smaller format
+    pub fn can_demote(&self) -> bool {
+        let max_state_count = match self.handle {
+            ChunkHandle::ChunkEmpty => return false,
+            ChunkHandle::Chunk4bits(_) => 0,
+            ChunkHandle::Chunk8bits(_) => 15,
+            ChunkHandle::ChunkNative(_) => 255,
+        };
+        self.count_states(max_state_count + 1) <= max_state_count
+    }
+
+    ///demote the chunk with the memory of the given pool, like [`Chunk::promote_in`] the old handle is dropped when it is replaced
+    fn demote_in(&mut self, pool: &ChunkMemoryPool) -> bool {
+        let state_count = self.count_states(256);
+        let mut demoted = false;
+        loop {
+            match &self.handle {
+                ChunkHandle::ChunkEmpty => return demoted,
+                _ if state_count == 0 => self.handle = ChunkHandle::ChunkEmpty,
+                ChunkHandle::ChunkNative(chunk) if state_count <= 255 => {
+                    let mut new_handle = pool.chunks8bits.alloc(Chunk8Bits::new());
+                    let copied = chunk.demote_to(&mut new_handle);
+                    debug_assert!(copied, "the states were counted");
+                    self.handle = ChunkHandle::Chunk8bits(new_handle);
+                }
+                ChunkHandle::Chunk8bits(chunk) if state_count <= 15 => {
+                    let mut new_handle = pool.chunks4bits.alloc(Chunk4Bits::new());
+                    let copied = chunk.demote_to(&mut new_handle);
+                    debug_assert!(copied, "the states were counted");
+                    self.handle = ChunkHandle::Chunk4bits(new_handle);
+                }
+                _ => return demoted,
+            }
+            demoted = true;
+        }
+    }
+
+    ///the number of distinct non-air states in the chunk, the count stops at the limit
+    fn count_states(&self, limit: usize) -> usize {
+        let mut states = HashSet::new();
+        for (_, state) in self.iter_blocks() {
+            if states.insert(state) && states.len() >= limit {
+                break;
+            }
+        }
+        states.len()
+    }
+
+    ///the in-memory format of the chunk, None if it is empty
+    pub fn format(&self) -> Option<ChunkFormat> {
+        match self.handle {
+            ChunkHandle::ChunkEmpty => None,
+            ChunkHandle::ChunkNative(_) => Some(ChunkFormat::Native),
+            ChunkHandle::Chunk8bits(_) => Some(ChunkFormat::Bits8),
+            ChunkHandle::Chunk4bits(_) => Some(ChunkFormat::Bits4),
+        }
+    }
+
+    ///get the blockstate at the given position, relative to the chunk
     pub fn get_block(&self, pos: BlockPos) -> BlockState {
+        debug_assert!(Self::is_in_chunk(pos), "the position is out of the chunk");
         match self.handle {
             ChunkHandle::ChunkNative(ref chunk) => chunk.get_block(pos),
             ChunkHandle::Chunk8bits(ref chunk) => chunk.get_block(pos),
@@ -106,8 +333,9 @@ impl Chunk {
         self.get_block(BlockPos::new(x, y, z))
     }
 
-    ///set the blockstate at the given position
+    ///set the blockstate at the given position, relative to the chunk
     pub fn set_block(&mut self, pos: BlockPos, state: BlockState) {
+        debug_assert!(Self::is_in_chunk(pos), "the position is out of the chunk");
         //set the blockstate at the given position can fail if the chunk is not in the right format
         while !match self.handle {
             ChunkHandle::ChunkNative(ref mut chunk) => chunk.try_set_block(pos, state),
@@ -117,6 +345,34 @@ impl Chunk {
         } {
             self.promote();
         }
+        self.generation += 1;
+    }
+
+    ///a counter bumped each time a block is set, a new chunk starts at 0
+    ///a cache holding a chunk can keep the generation it was built from, and compare it to know if it is outdated
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    ///the index of a block in the chunk, x + y * 16 + z * 256 like in [`Chunk::from_block_array`], it fits in an u16
+    pub fn block_index(pos: BlockPos) -> u16 {
+        debug_assert!(Self::is_in_chunk(pos), "the position is out of the chunk");
+        (pos.x + pos.y * CHUNK_SIZE + pos.z * CHUNK_SIZE * CHUNK_SIZE) as u16
+    }
+
+    ///the position relative to the chunk of the block at the given index, see [`Chunk::block_index`]
+    pub fn block_pos(index: u16) -> BlockPos {
+        let index = index as i32;
+        BlockPos::new(
+            index % CHUNK_SIZE,
+            index / CHUNK_SIZE % CHUNK_SIZE,
+            index / (CHUNK_SIZE * CHUNK_SIZE),
+        )
+    }
+
+    ///whether the position relative to the chunk is inside it, each coordinate must be in [0, CHUNK_SIZE[
+    pub fn is_in_chunk(pos: BlockPos) -> bool {
+        pos.cmpge(BlockPos::ZERO).all() && pos.cmplt(BlockPos::splat(CHUNK_SIZE)).all()
     }
 
     ///get the position of the chunk in the world
@@ -135,10 +391,671 @@ impl Chunk {
         matches!(self.handle, ChunkHandle::ChunkEmpty)
     }
 
+    ///iterate over the non-air blocks of the chunk with their position relative to the chunk
+    ///the blocks are visited in the memory order (x first, then y, then z), an empty chunk yields nothing
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (BlockPos, BlockState)> + '_ {
+        //only one of the three iterators is Some, this avoids boxing the iterator
+        let (native, bits8, bits4) = match self.handle {
+            ChunkHandle::ChunkNative(ref chunk) => (Some(chunk.iter_non_air()), None, None),
+            ChunkHandle::Chunk8bits(ref chunk) => (None, Some(chunk.iter_non_air()), None),
+            ChunkHandle::Chunk4bits(ref chunk) => (None, None, Some(chunk.iter_non_air())),
+            ChunkHandle::ChunkEmpty => (None, None, None),
+        };
+        native
+            .into_iter()
+            .flatten()
+            .chain(bits8.into_iter().flatten())
+            .chain(bits4.into_iter().flatten())
+            .map(|(i, state)| (Self::block_pos(i as u16), state))
+    }
+
+    ///the number of non-air blocks in the chunk
+    pub fn block_count(&self) -> usize {
+        self.iter_blocks().count()
+    }
+
+    ///list the blocks that differ between the two chunks, with their state in the other chunk
+    ///the states are compared, not the in-memory format, so the same blocks in two formats don't differ
+    pub fn diff(&self, other: &Chunk) -> Vec<(BlockPos, BlockState)> {
+        let mut delta = Vec::new();
+        if self.is_empty() && other.is_empty() {
+            return delta;
+        }
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let state = other.get_block_at(x, y, z);
+                    if self.get_block_at(x, y, z) != state {
+                        delta.push((BlockPos::new(x, y, z), state));
+                    }
+                }
+            }
+        }
+        delta
+    }
+
+    ///whether the two chunks have the same blocks, whatever their in-memory format and position are
+    pub fn blocks_eq(&self, other: &Chunk) -> bool {
+        if self.is_empty() && other.is_empty() {
+            return true;
+        }
+        (0..CHUNK_SIZE).all(|z| {
+            (0..CHUNK_SIZE).all(|y| {
+                (0..CHUNK_SIZE).all(|x| self.get_block_at(x, y, z) == other.get_block_at(x, y, z))
+            })
+        })
+    }
+
+    ///set the blocks of a delta made by [`Chunk::diff`]
+    pub fn apply_delta(&mut self, delta: &[(BlockPos, BlockState)]) {
+        for (pos, state) in delta {
+            self.set_block(*pos, *state);
+        }
+    }
+
+    ///serialize the blocks of the chunk in its in-memory format, the first byte is the format, see [`ChunkDecodeError`] for the checks on load
+    ///0: an empty chunk, nothing follows
+    ///1: the native format, every blockstate in little endian
+    ///2 and 3: the 8 bits and 4 bits formats, the palette length (u8), the palette in little endian, then the palette index of each block,
+    ///on a byte for the 8 bits format, or packed by two for the 4 bits format
+    pub fn serialize(&self) -> Vec<u8> {
+        match self.handle {
+            ChunkHandle::ChunkEmpty => vec![Self::EMPTY_TAG],
+            ChunkHandle::ChunkNative(ref chunk) => {
+                let mut bytes = Vec::with_capacity(1 + Self::BLOCK_COUNT * size_of::<BlockState>());
+                bytes.push(Self::NATIVE_TAG);
+                for state in chunk.blocks() {
+                    bytes.extend_from_slice(&state.to_le_bytes());
+                }
+                bytes
+            }
+            ChunkHandle::Chunk8bits(ref chunk) => {
+                Self::serialize_palette(Self::BITS8_TAG, chunk.palette(), chunk.packed_blocks())
+            }
+            ChunkHandle::Chunk4bits(ref chunk) => {
+                Self::serialize_palette(Self::BITS4_TAG, chunk.palette(), chunk.packed_blocks())
+            }
+        }
+    }
+
+    fn serialize_palette(tag: u8, palette: &[BlockState], packed_blocks: &[u8]) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(2 + std::mem::size_of_val(palette) + packed_blocks.len());
+        bytes.push(tag);
+        bytes.push(palette.len() as u8);
+        for state in palette {
+            bytes.extend_from_slice(&state.to_le_bytes());
+        }
+        bytes.extend_from_slice(packed_blocks);
+        bytes
+    }
+
+    ///build a chunk from the bytes produced by [`Chunk::serialize`], in the same in-memory format
+    ///an error if the bytes are malformed, the length is checked against the format before anything is read
+    pub fn deserialize(position: ChunkPos, bytes: &[u8]) -> Result<Self, ChunkDecodeError> {
+        let check_length = |expected: usize| {
+            if bytes.len() != expected {
+                return Err(ChunkDecodeError::InvalidLength {
+                    expected,
+                    found: bytes.len(),
+                });
+            }
+            Ok(())
+        };
+        let read_state = |bytes: &[u8]| BlockState::from_le_bytes([bytes[0], bytes[1]]);
+
+        let Some((&tag, data)) = bytes.split_first() else {
+            return Err(ChunkDecodeError::InvalidLength {
+                expected: 1,
+                found: 0,
+            });
+        };
+        let handle = match tag {
+            Self::EMPTY_TAG => {
+                check_length(1)?;
+                ChunkHandle::ChunkEmpty
+            }
+            Self::NATIVE_TAG => {
+                check_length(1 + Self::BLOCK_COUNT * size_of::<BlockState>())?;
+                let mut blocks = [AIR; Self::BLOCK_COUNT];
+                for (block, bytes) in blocks.iter_mut().zip(data.chunks_exact(2)) {
+                    *block = read_state(bytes);
+                }
+                ChunkHandle::ChunkNative(
+                    MEMORY_MANAGER
+                        .chunks_native
+                        .alloc(ChunkNative::from_blocks(&blocks)),
+                )
+            }
+            Self::BITS8_TAG | Self::BITS4_TAG => {
+                let (max_palette_len, packed_len) = match tag {
+                    Self::BITS8_TAG => (255, Self::BLOCK_COUNT),
+                    _ => (15, Self::BLOCK_COUNT / 2),
+                };
+                let Some(&palette_len) = data.first() else {
+                    return Err(ChunkDecodeError::InvalidLength {
+                        expected: 2,
+                        found: 1,
+                    });
+                };
+                let palette_len = palette_len as usize;
+                if palette_len > max_palette_len {
+                    return Err(ChunkDecodeError::PaletteTooLarge(palette_len));
+                }
+                check_length(2 + palette_len * size_of::<BlockState>() + packed_len)?;
+
+                let (palette, packed_blocks) =
+                    data[1..].split_at(palette_len * size_of::<BlockState>());
+                let palette: Vec<BlockState> = palette.chunks_exact(2).map(read_state).collect();
+                let mut indices = [0u8; Self::BLOCK_COUNT];
+                if tag == Self::BITS8_TAG {
+                    indices.copy_from_slice(packed_blocks);
+                } else {
+                    for (pair, packed) in indices.chunks_exact_mut(2).zip(packed_blocks) {
+                        pair[0] = packed & 0b1111;
+                        pair[1] = packed >> 4;
+                    }
+                }
+                //a block can't refer to a free entry, it would be read as air but counted as a block
+                let is_valid = |index: &u8| match index {
+                    0 => true,
+                    _ => palette
+                        .get(*index as usize - 1)
+                        .is_some_and(|state| *state != AIR),
+                };
+                if let Some(index) = indices.iter().find(|index| !is_valid(index)) {
+                    return Err(ChunkDecodeError::InvalidPaletteIndex(*index));
+                }
+
+                if tag == Self::BITS8_TAG {
+                    ChunkHandle::Chunk8bits(
+                        MEMORY_MANAGER
+                            .chunks8bits
+                            .alloc(Chunk8Bits::from_palette(&palette, &indices)),
+                    )
+                } else {
+                    ChunkHandle::Chunk4bits(
+                        MEMORY_MANAGER
+                            .chunks4bits
+                            .alloc(Chunk4Bits::from_palette(&palette, &indices)),
+                    )
+                }
+            }
+            _ => return Err(ChunkDecodeError::UnknownFormat(tag)),
+        };
+        Ok(Self {
+            position,
+            handle,
+            generation: 0,
+        })
+    }
+
     ///get the AABB of the chunk in block coordinate
-    pub fn get_aabb_in_block(&self) -> (IVec3, IVec3) {
+    pub fn get_aabb_in_block(&self) -> AABB {
         let min = self.position * CHUNK_SIZE;
-        let max = min + IVec3::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
-        (min, max)
+        AABB::new(min, min + IVec3::splat(CHUNK_SIZE))
+    }
+
+    ///get the AABB of the chunk in chunk coordinate, the unit AABB at its position, like the AABBs of the chunk manager
+    pub fn get_aabb_in_chunks(&self) -> AABB {
+        AABB::new(self.position, self.position + IVec3::ONE)
+    }
+}
+
+///the blocks are copied in a new allocation of the memory pool, in the same format
+impl Clone for Chunk {
+    fn clone(&self) -> Self {
+        let handle = match self.handle {
+            ChunkHandle::ChunkNative(ref chunk) => {
+                ChunkHandle::ChunkNative(MEMORY_MANAGER.chunks_native.alloc((**chunk).clone()))
+            }
+            ChunkHandle::Chunk8bits(ref chunk) => {
+                ChunkHandle::Chunk8bits(MEMORY_MANAGER.chunks8bits.alloc((**chunk).clone()))
+            }
+            ChunkHandle::Chunk4bits(ref chunk) => {
+                ChunkHandle::Chunk4bits(MEMORY_MANAGER.chunks4bits.alloc((**chunk).clone()))
+            }
+            ChunkHandle::ChunkEmpty => ChunkHandle::ChunkEmpty,
+        };
+        Self {
+            position: self.position,
+            handle,
+            generation: self.generation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block_state::{BlockState, AIR};
+    use crate::chunk::implementation::{Chunk4Bits, ChunkNative};
+    use crate::chunk::{Chunk, ChunkDecodeError, ChunkFormat, ChunkHandle, ChunkMemoryPool};
+    use math::aabb::AABB;
+    use math::positions::{BlockPos, ChunkPos};
+    use math::IVec3;
+
+    ///the blocks with the state id given by the function for each index
+    fn block_array(id: impl Fn(usize) -> usize) -> [BlockState; Chunk::BLOCK_COUNT] {
+        let mut blocks = [AIR; Chunk::BLOCK_COUNT];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = BlockState::new(id(i) as u16);
+        }
+        blocks
+    }
+
+    #[test]
+    pub fn from_block_array_picks_the_smallest_format() {
+        let pos = ChunkPos::new(1, 2, 3);
+
+        let chunk = Chunk::from_block_array(pos, &block_array(|_| 0));
+        assert!(chunk.is_empty());
+
+        let chunk = Chunk::from_block_array(pos, &block_array(|_| 7));
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk4bits(_)));
+
+        let chunk = Chunk::from_block_array(pos, &block_array(|i| i % 200));
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk8bits(_)));
+
+        let chunk = Chunk::from_block_array(pos, &block_array(|i| i));
+        assert!(matches!(chunk.handle, ChunkHandle::ChunkNative(_)));
+    }
+
+    #[test]
+    pub fn from_block_array_keeps_the_blocks() {
+        let pos = ChunkPos::new(0, 0, 0);
+        for modulo in [2, 16, 200, 4096] {
+            let blocks = block_array(|i| i * 7 % modulo);
+            let chunk = Chunk::from_block_array(pos, &blocks);
+            for z in 0..Chunk::SIZE {
+                for y in 0..Chunk::SIZE {
+                    for x in 0..Chunk::SIZE {
+                        let i = (x + y * Chunk::SIZE + z * Chunk::SIZE * Chunk::SIZE) as usize;
+                        assert_eq!(chunk.get_block_at(x, y, z), blocks[i]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn a_full_palette_promotes_and_keeps_the_blocks() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        //each state on both halves of a byte of the 4 bits format
+        let set_state = |chunk: &mut Chunk, id: u16| {
+            for index in [id * 2, id * 2 + 1] {
+                chunk.set_block(Chunk::block_pos(index), BlockState::new(id));
+            }
+        };
+        let check_states = |chunk: &Chunk, last_id: u16| {
+            for index in 0..Chunk::BLOCK_COUNT as u16 {
+                let id = if index / 2 <= last_id { index / 2 } else { 0 };
+                assert_eq!(
+                    chunk.get_block(Chunk::block_pos(index)),
+                    BlockState::new(id)
+                );
+            }
+        };
+
+        for id in 1..=15 {
+            set_state(&mut chunk, id);
+        }
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk4bits(_)));
+        check_states(&chunk, 15);
+        set_state(&mut chunk, 16);
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk8bits(_)));
+        check_states(&chunk, 16);
+
+        for id in 17..=255 {
+            set_state(&mut chunk, id);
+        }
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk8bits(_)));
+        check_states(&chunk, 255);
+        set_state(&mut chunk, 256);
+        assert!(matches!(chunk.handle, ChunkHandle::ChunkNative(_)));
+        check_states(&chunk, 256);
+    }
+
+    #[test]
+    pub fn the_palette_entries_of_removed_states_are_reused() {
+        let mut chunk = Chunk::new(ChunkPos::ZERO);
+        for id in 1..=15 {
+            chunk.set_block(Chunk::block_pos(id), BlockState::new(id));
+        }
+        //each block is the last of its state, so the 16th state takes its entry
+        for id in 1..=15 {
+            chunk.set_block(Chunk::block_pos(id), BlockState::new(16));
+        }
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk4bits(_)));
+        let blocks: Vec<_> = chunk.iter_blocks().map(|(_, state)| state.id()).collect();
+        assert_eq!(blocks, [16; 15]);
+
+        //a state on two blocks keeps its entry until both are replaced
+        let mut chunk = Chunk::new(ChunkPos::ZERO);
+        for id in 1..=15 {
+            for index in [id * 2, id * 2 + 1] {
+                chunk.set_block(Chunk::block_pos(index), BlockState::new(id));
+            }
+        }
+        chunk.set_block(Chunk::block_pos(6), AIR);
+        chunk.set_block(Chunk::block_pos(7), AIR);
+        chunk.set_block(Chunk::block_pos(100), BlockState::new(16));
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk4bits(_)));
+        chunk.set_block(Chunk::block_pos(2), BlockState::new(17));
+        assert!(matches!(chunk.handle, ChunkHandle::Chunk8bits(_)));
+        assert_eq!(chunk.get_block(Chunk::block_pos(2)), BlockState::new(17));
+        assert_eq!(chunk.get_block(Chunk::block_pos(3)), BlockState::new(1));
+        assert_eq!(chunk.get_block(Chunk::block_pos(100)), BlockState::new(16));
+        assert_eq!(chunk.get_block(Chunk::block_pos(6)), AIR);
+
+        //the counts are kept by the promotion
+        chunk.set_block(Chunk::block_pos(3), BlockState::new(18));
+        chunk.set_block(Chunk::block_pos(4), BlockState::new(19));
+        chunk.set_block(Chunk::block_pos(5), BlockState::new(20));
+        assert_eq!(chunk.get_block(Chunk::block_pos(4)), BlockState::new(19));
+        assert_eq!(chunk.get_block(Chunk::block_pos(8)), BlockState::new(4));
+    }
+
+    #[test]
+    pub fn the_generation_is_bumped_by_the_edits_only() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        assert_eq!(chunk.generation(), 0);
+
+        chunk.set_block_at(1, 2, 3, BlockState::new(4));
+        assert_eq!(chunk.generation(), 1);
+        chunk.apply_delta(&[
+            (BlockPos::new(4, 5, 6), BlockState::new(7)),
+            (BlockPos::new(1, 2, 3), AIR),
+        ]);
+        assert_eq!(chunk.generation(), 3);
+
+        //the reads don't change it, and a copy keeps it
+        chunk.get_block_at(1, 2, 3);
+        assert_eq!(chunk.iter_blocks().count(), 1);
+        chunk.serialize();
+        assert_eq!(chunk.clone().generation(), 3);
+        assert_eq!(chunk.generation(), 3);
+
+        let loaded = Chunk::deserialize(chunk.position(), &chunk.serialize()).unwrap();
+        assert_eq!(loaded.generation(), 0);
+    }
+
+    #[test]
+    pub fn the_aabbs_are_in_blocks_and_in_chunks() {
+        let chunk = Chunk::new(ChunkPos::new(-1, 0, 2));
+        assert_eq!(
+            chunk.get_aabb_in_block(),
+            AABB::new(IVec3::new(-16, 0, 32), IVec3::new(0, 16, 48))
+        );
+        assert_eq!(
+            chunk.get_aabb_in_chunks(),
+            AABB::new(IVec3::new(-1, 0, 2), IVec3::new(0, 1, 3))
+        );
+        assert!(chunk.get_aabb_in_chunks().is_unit());
+    }
+
+    #[test]
+    pub fn iter_blocks_matches_a_full_scan() {
+        let pos = ChunkPos::new(0, 0, 0);
+        let block_arrays = [
+            block_array(|_| 0),
+            //4 bits, with some bytes holding two air blocks and some holding only one
+            block_array(|i| if i % 8 < 3 { 0 } else { 5 }),
+            block_array(|i| i * 7 % 200),
+            block_array(|i| i),
+        ];
+        for blocks in block_arrays {
+            let chunk = Chunk::from_block_array(pos, &blocks);
+
+            let mut expected = Vec::new();
+            for z in 0..Chunk::SIZE {
+                for y in 0..Chunk::SIZE {
+                    for x in 0..Chunk::SIZE {
+                        let state = chunk.get_block_at(x, y, z);
+                        if state != AIR {
+                            expected.push((BlockPos::new(x, y, z), state));
+                        }
+                    }
+                }
+            }
+            let blocks: Vec<_> = chunk.iter_blocks().collect();
+            assert_eq!(blocks, expected);
+            assert_eq!(chunk.block_count(), expected.len());
+        }
+    }
+
+    #[test]
+    pub fn apply_delta_of_diff_gives_the_other_chunk() {
+        let pos = ChunkPos::new(0, 0, 0);
+        let block_arrays = [
+            block_array(|_| 0),
+            block_array(|i| i % 3),
+            block_array(|i| i * 7 % 200),
+            block_array(|i| i),
+        ];
+        for a in &block_arrays {
+            for b in &block_arrays {
+                let mut chunk = Chunk::from_block_array(pos, a);
+                let other = Chunk::from_block_array(pos, b);
+                let delta = chunk.diff(&other);
+                chunk.apply_delta(&delta);
+                assert!(chunk.diff(&other).is_empty());
+                if a == b {
+                    assert!(delta.is_empty());
+                }
+            }
+        }
+
+        //the same blocks in two formats don't differ
+        let mut promoted = Chunk::from_block_array(pos, &block_arrays[1]);
+        promoted.promote();
+        let chunk = Chunk::from_block_array(pos, &block_arrays[1]);
+        assert!(chunk.diff(&promoted).is_empty());
+
+        let mut other = Chunk::from_block_array(pos, &block_arrays[1]);
+        other.set_block_at(1, 2, 3, BlockState::new(9));
+        assert_eq!(
+            chunk.diff(&other),
+            vec![(BlockPos::new(1, 2, 3), BlockState::new(9))]
+        );
+    }
+
+    #[test]
+    pub fn serialize_round_trip_keeps_the_blocks() {
+        let pos = ChunkPos::new(4, -2, 9);
+        for blocks in [
+            block_array(|_| 0),
+            block_array(|i| i % 3),
+            block_array(|i| i * 7 % 200),
+            block_array(|i| i),
+        ] {
+            let chunk = Chunk::from_block_array(pos, &blocks);
+            let loaded = Chunk::deserialize(pos, &chunk.serialize()).unwrap();
+            assert!(loaded.blocks_eq(&chunk));
+            assert!(chunk.blocks_eq(&loaded));
+        }
+
+        //a native chunk and an 8 bits chunk with the same blocks are equal
+        let blocks = block_array(|i| i * 7 % 200);
+        let chunk = Chunk::from_block_array(pos, &blocks);
+        let mut promoted = Chunk::from_block_array(pos, &blocks);
+        promoted.promote();
+        assert!(chunk.blocks_eq(&promoted));
+        promoted.set_block_at(15, 15, 15, BlockState::new(1000));
+        assert!(!chunk.blocks_eq(&promoted));
+        assert!(!Chunk::new(pos).blocks_eq(&chunk));
+    }
+
+    #[test]
+    pub fn serialize_keeps_the_format() {
+        let pos = ChunkPos::new(-3, 0, 1);
+        let blocks = block_array(|i| if i == 1 { 1 } else { 2 + i % 2 });
+        let mut with_free_entries = Chunk::from_block_array(pos, &blocks);
+        with_free_entries.set_block_at(1, 0, 0, AIR); //the only block of the first state
+        let chunks = [
+            Chunk::new(pos),
+            Chunk::from_block_array(pos, &block_array(|i| i)),
+            Chunk::from_block_array(pos, &block_array(|i| i * 7 % 200)),
+            Chunk::from_block_array(pos, &block_array(|i| i % 3)),
+            with_free_entries,
+        ];
+        let sizes = [
+            1,
+            1 + 4096 * 2,
+            2 + 199 * 2 + 4096,
+            2 + 2 * 2 + 2048,
+            2 + 3 * 2 + 2048, //the free entry is kept, so the indices don't change
+        ];
+        for (chunk, size) in chunks.iter().zip(sizes) {
+            assert_eq!(chunk.serialize().len(), size);
+        }
+        for chunk in &chunks {
+            let loaded = Chunk::deserialize(pos, &chunk.serialize()).unwrap();
+            assert_eq!(loaded.format(), chunk.format());
+            assert!(loaded.blocks_eq(chunk));
+            assert_eq!(loaded.block_count(), chunk.block_count());
+        }
+    }
+
+    #[test]
+    pub fn malformed_chunk_data_is_an_error() {
+        let pos = ChunkPos::ZERO;
+        for blocks in [
+            block_array(|i| i),
+            block_array(|i| i % 200),
+            block_array(|i| i % 3),
+        ] {
+            let bytes = Chunk::from_block_array(pos, &blocks).serialize();
+            for len in (0..bytes.len()).step_by(97) {
+                assert!(Chunk::deserialize(pos, &bytes[..len]).is_err());
+            }
+            let mut longer = bytes.clone();
+            longer.push(0);
+            assert_eq!(
+                Chunk::deserialize(pos, &longer).err(),
+                Some(ChunkDecodeError::InvalidLength {
+                    expected: bytes.len(),
+                    found: bytes.len() + 1
+                })
+            );
+        }
+        assert_eq!(
+            Chunk::deserialize(pos, &[]).err(),
+            Some(ChunkDecodeError::InvalidLength {
+                expected: 1,
+                found: 0
+            })
+        );
+        assert_eq!(
+            Chunk::deserialize(pos, &[7]).err(),
+            Some(ChunkDecodeError::UnknownFormat(7))
+        );
+        assert_eq!(
+            Chunk::deserialize(pos, &[3, 16]).err(),
+            Some(ChunkDecodeError::PaletteTooLarge(16))
+        );
+
+        //the 4 bits chunk has 2 palette entries, the packed byte refers to the third one
+        let mut bytes = Chunk::from_block_array(pos, &block_array(|i| i % 3)).serialize();
+        bytes[6] = 0x31;
+        assert_eq!(
+            Chunk::deserialize(pos, &bytes).err(),
+            Some(ChunkDecodeError::InvalidPaletteIndex(3))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "out of the chunk")]
+    pub fn negative_positions_are_rejected() {
+        //(-1, 1, 0) would be read as (15, 0, 0) without the bound checks
+        let mut chunk = Chunk::from_block_array(ChunkPos::ZERO, &block_array(|i| i % 3));
+        assert!(!Chunk::is_in_chunk(BlockPos::new(-1, 1, 0)));
+        assert!(Chunk::is_in_chunk(BlockPos::new(15, 0, 15)));
+        chunk.promote();
+        chunk.get_block_at(-1, 1, 0);
+    }
+
+    #[test]
+    pub fn stats_are_split_by_format() {
+        let pool = ChunkMemoryPool::new();
+        let _native = pool.chunks_native.alloc(ChunkNative::new());
+        let _small_chunks = [
+            pool.chunks4bits.alloc(Chunk4Bits::new()),
+            pool.chunks4bits.alloc(Chunk4Bits::new()),
+        ];
+        assert_eq!(pool.chunk_count(ChunkFormat::Native), 1);
+        assert_eq!(pool.chunk_count(ChunkFormat::Bits8), 0);
+        assert_eq!(pool.chunk_count(ChunkFormat::Bits4), 2);
+
+        let stats = pool.stats_by_format();
+        assert_eq!(stats[2].0, ChunkFormat::Bits4);
+        assert_eq!(
+            stats[2].1.used.bytes(),
+            2 * std::mem::size_of::<Chunk4Bits>()
+        );
+        let (used, _) = pool.stats();
+        assert_eq!(used, stats[0].1.used + stats[2].1.used);
+    }
+
+    #[test]
+    pub fn promotion_gives_the_old_format_back_to_the_pool() {
+        let pool = ChunkMemoryPool::new();
+        let mut chunk = Chunk::new(ChunkPos::ZERO);
+        let counts =
+            |pool: &ChunkMemoryPool| ChunkFormat::ALL.map(|format| pool.chunk_count(format));
+
+        chunk.promote_in(&pool);
+        assert_eq!(counts(&pool), [0, 0, 1]);
+        chunk.promote_in(&pool);
+        assert_eq!(counts(&pool), [0, 1, 0]);
+        chunk.promote_in(&pool);
+        assert_eq!(counts(&pool), [1, 0, 0]);
+        chunk.promote_in(&pool); //already native
+        assert_eq!(counts(&pool), [1, 0, 0]);
+
+        drop(chunk);
+        assert_eq!(counts(&pool), [0, 0, 0]);
+        assert_eq!(pool.stats().0.bytes(), 0);
+    }
+
+    #[test]
+    pub fn demotion_goes_down_to_the_smallest_format() {
+        let pool = ChunkMemoryPool::new();
+        let counts =
+            |pool: &ChunkMemoryPool| ChunkFormat::ALL.map(|format| pool.chunk_count(format));
+        let mut chunk = Chunk::new(ChunkPos::ZERO);
+        for _ in 0..3 {
+            chunk.promote_in(&pool);
+        }
+        for index in 0..200 {
+            chunk.set_block(Chunk::block_pos(index), BlockState::new(1 + index));
+        }
+        assert_eq!(counts(&pool), [1, 0, 0]);
+
+        assert!(chunk.can_demote());
+        assert!(chunk.demote_in(&pool));
+        assert_eq!(counts(&pool), [0, 1, 0]);
+        assert!(!chunk.can_demote());
+
+        //the removed states are dropped from the palette, both halves of the bytes are kept
+        for index in 3..200 {
+            chunk.set_block(Chunk::block_pos(index), AIR);
+        }
+        chunk.set_block(Chunk::block_pos(4000), BlockState::new(2));
+        assert!(chunk.demote_in(&pool));
+        assert_eq!(counts(&pool), [0, 0, 1]);
+        let blocks: Vec<_> = chunk.iter_blocks().map(|(_, state)| state.id()).collect();
+        assert_eq!(blocks, [1, 2, 3, 2]);
+        assert_eq!(chunk.get_block(Chunk::block_pos(4000)), BlockState::new(2));
+        assert!(!chunk.demote_in(&pool));
+
+        //a chunk of air is empty again
+        for index in [0, 1, 2, 4000] {
+            chunk.set_block(Chunk::block_pos(index), AIR);
+        }
+        assert!(!chunk.is_empty());
+        assert!(chunk.demote_in(&pool));
+        assert!(chunk.is_empty());
+        assert_eq!(chunk.format(), None);
+        assert_eq!(counts(&pool), [0, 0, 0]);
     }
 }
@@ -1,12 +1,26 @@
+///ready-made predicates for [`ChunkManager::foreach_chunk_with_predicate`] and the other traversals
+///they are given AABBs in chunk coordinates with an excluded max, the nodes of the tree then the chunks
+pub mod predicates;
+
+use crate::block_registry::BlockFace;
+use crate::block_state::{BlockState, AIR};
 use crate::Chunk;
 use math::aabb::AABB;
-use math::positions::ChunkPos;
+use math::consts::CHUNK_SIZE;
+use math::frustum::CameraFrustum;
+use math::morton::{morton_decode_3d, morton_encode_3d};
+use math::positions::{BlockPos, ChunkPos};
 use math::{I16Vec3, IVec3};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 use utils::array_utils::ArrayUtils;
 use utils::spare_set::{Id, IdTracker};
 
-const NODE_SUBDIVISION: i32 = 8; //power of 2 are nice because they can be optimized by the compiler, this value couldn't really be changed without rewriting the tree_index_iterator function (which is a bit ugly)
+const NODE_SUBDIVISION: i32 = 8; //must be a power of 2, the tree_index_iterator halves the nodes down to the children
 
 ///a node in the octree, it can be a leaf or a branch
 trait Node {
@@ -21,10 +35,35 @@ trait Node {
 
     ///return the child at a given position, this position should be in the range [0, 8 * 2^level[
     fn get_chunk(&self, pos: IVec3) -> Option<&Chunk>;
-    fn get_chunk_mut(&mut self, pos: IVec3) -> Option<&mut Chunk>;
+    ///same as get_chunk, the id is returned to mark the chunk as modified
+    ///the chunk may be shared with a snapshot, it is copied by the caller with Arc::make_mut only when it is written
+    fn get_chunk_mut(&mut self, pos: IVec3) -> Option<(Id, &mut Arc<Chunk>)>;
+    ///the id of the chunk at a given position, to mark it as modified without copying it if it is shared
+    fn get_chunk_id(&self, pos: IVec3) -> Option<Id>;
 
     ///emplace a chunk at a given position, this position should be in the range [0, 8 * 2^level[
-    fn emplace_chunk(&mut self, chunk: Chunk, pos: IVec3, id_tracker: &mut IdTracker) -> Id;
+    ///the id of the chunk is returned with the id of the chunk it replaced, the replaced id isn't freed
+    fn emplace_chunk(
+        &mut self,
+        chunk: Chunk,
+        pos: IVec3,
+        id_tracker: &mut IdTracker,
+    ) -> (Id, Option<Id>);
+
+    ///get the chunk at a given position, or create and emplace it if there is none, in a single descent
+    ///the id of the chunk is returned with whether it was created
+    fn get_or_emplace_chunk(
+        &mut self,
+        pos: IVec3,
+        create: impl FnOnce() -> Chunk,
+        id_tracker: &mut IdTracker,
+    ) -> (Id, bool, &mut Arc<Chunk>);
+
+    ///remove the chunk at a given position, with its id, the children left empty are removed too
+    fn remove_chunk(&mut self, pos: IVec3) -> Option<(Id, Arc<Chunk>)>;
+
+    ///whether the node has no chunk anymore
+    fn is_empty(&self) -> bool;
 
     ///put all loaded chunks that intersect the given AABB in the out vec
     fn for_chunk_in<'a>(&'a self, global_aabb: AABB, out_func: &mut impl FnMut(Id, &'a Chunk));
@@ -38,15 +77,19 @@ trait Node {
     );
 
     ///put all loaded chunks that intersect the given AABB  and that satisfy the predicate in the out vec
+    ///like get_chunk_mut, the chunks aren't copied, even if they are shared
     fn for_chunk_with_predicate_mut<'a>(
         &'a mut self,
         global_aabb: AABB,
         predicate: impl Fn(AABB) -> bool + Copy,
-        out_func: &mut impl FnMut(Id, &'a mut Chunk),
+        out_func: &mut impl FnMut(Id, &'a mut Arc<Chunk>),
     );
 
     ///put all loaded chunks in the node in the out vec
     fn for_all_chunks<'a>(&'a self, out_func: &mut impl FnMut(Id, &'a Chunk));
+
+    ///same as for_all_chunks, but give the shared chunks, to keep them alive out of the node
+    fn for_all_shared_chunks(&self, out_func: &mut impl FnMut(&Arc<Chunk>));
 }
 
 ///get the index of the child with local position
@@ -58,88 +101,97 @@ fn get_index_from_pos(pos: IVec3) -> usize {
     debug_assert!(pos.y >= 0, "y to small");
     debug_assert!(pos.z >= 0, "z to small");
 
-    //using a morton encoding
-    let mut output = 0;
-    for i in 0..4 {
-        output |= (pos.x & (1 << i)) << (i * 2);
-        output |= (pos.y & (1 << i)) << (i * 2 + 1);
-        output |= (pos.z & (1 << i)) << (i * 2 + 2);
-    }
-    output as usize
+    //using a morton encoding, each coordinate fits in log2(NODE_SUBDIVISION) bits
+    morton_encode_3d(pos, NODE_SUBDIVISION.trailing_zeros()) as usize
 }
 
+///the number of times a node is halved down to its children, 3 for 8 children per side
+const TREE_INDEX_DEPTH: usize = NODE_SUBDIVISION.trailing_zeros() as usize;
+const _: () = assert!(
+    NODE_SUBDIVISION >= 2 && NODE_SUBDIVISION.count_ones() == 1,
+    "NODE_SUBDIVISION must be a power of 2"
+);
+
+///the local positions of the 8 halves of a cube, the children are given in this order at each level
+const HALVES: [IVec3; 8] = [
+    IVec3::new(0, 0, 0),
+    IVec3::new(0, 0, 1),
+    IVec3::new(0, 1, 0),
+    IVec3::new(0, 1, 1),
+    IVec3::new(1, 0, 0),
+    IVec3::new(1, 0, 1),
+    IVec3::new(1, 1, 0),
+    IVec3::new(1, 1, 1),
+];
+
 ///an iterator that give the index of the children that intersect the given AABB and satisfy the given predicate
+///the local positions count children, so they are scaled by the side of a child to get the AABBs in chunks
+///the node is halved until the children are reached, and a half is skipped with all its children if it fails the tests
 fn tree_index_iterator(
     global_pos: IVec3,
     global_aabb: AABB,
     child_side_chunk_count: i32,
     predicate: impl Fn(AABB) -> bool + Copy,
 ) -> impl Iterator<Item = usize> {
-    let get_aabb = |pos, cube_size| AABB::new(pos, pos + IVec3::ONE * cube_size);
-    const ITER: [IVec3; 8] = [
-        //all the possible position of the children
-        IVec3::new(0, 0, 0),
-        IVec3::new(0, 0, 1),
-        IVec3::new(0, 1, 0),
-        IVec3::new(0, 1, 1),
-        IVec3::new(1, 0, 0),
-        IVec3::new(1, 0, 1),
-        IVec3::new(1, 1, 0),
-        IVec3::new(1, 1, 1),
-    ];
-
-    //if you got a better way to do this depending on NODE_SUBDIVISION, I'm all ears
-    ITER.iter()
-        .filter_map(move |template_pos| {
-            //first level of iteration
-            let side_child_count = NODE_SUBDIVISION / 2;
-            let local_pos = template_pos.clone() * side_child_count;
-            let aabb = get_aabb(
-                local_pos + global_pos,
-                side_child_count * child_side_chunk_count,
-            );
-            if !global_aabb.intersects(&aabb) || !predicate(aabb) {
-                return None;
-            }
+    let mut iter = TreeIndexIterator {
+        global_pos,
+        global_aabb,
+        child_side_chunk_count,
+        predicate,
+        stack: [(IVec3::ZERO, 0); 7 * TREE_INDEX_DEPTH + 1],
+        len: 0,
+    };
+    iter.push_halves(IVec3::ZERO, NODE_SUBDIVISION);
+    iter
+}
 
-            Some(
-                ITER.iter()
-                    .filter_map(move |template_pos| {
-                        //second level of iteration
-                        let side_child_count = side_child_count / 2;
-                        let local_pos = local_pos + template_pos.clone() * side_child_count;
-                        let aabb = get_aabb(
-                            local_pos + global_pos,
-                            side_child_count * child_side_chunk_count,
-                        );
-                        if !global_aabb.intersects(&aabb) || !predicate(aabb) {
-                            return None;
-                        }
-
-                        Some(ITER.iter().filter_map(move |template_pos| {
-                            //third level of iteration
-                            let side_child_count = side_child_count / 2;
-                            assert_eq!(side_child_count, 1);
-                            let local_pos = local_pos + template_pos.clone() * side_child_count;
-                            let aabb = get_aabb(
-                                local_pos + global_pos,
-                                side_child_count * child_side_chunk_count,
-                            );
-                            if !global_aabb.intersects(&aabb) || !predicate(aabb) {
-                                return None;
-                            }
+struct TreeIndexIterator<P: Fn(AABB) -> bool> {
+    global_pos: IVec3,
+    global_aabb: AABB,
+    child_side_chunk_count: i32,
+    predicate: P,
+    ///the cubes left to visit, their local position and their side in children, the next one on top
+    ///a visited cube is replaced by its 8 halves, so there are at most 7 more cubes per level
+    stack: [(IVec3, i32); 7 * TREE_INDEX_DEPTH + 1],
+    len: usize,
+}
 
-                            return Some(get_index_from_pos(local_pos));
-                        }))
-                    })
-                    .flatten(),
-            ) //remove one level of nesting
-        })
-        .flatten() //remove one level of nesting
+impl<P: Fn(AABB) -> bool> TreeIndexIterator<P> {
+    fn push_halves(&mut self, local_pos: IVec3, side_child_count: i32) {
+        let half = side_child_count / 2;
+        for template_pos in HALVES.iter().rev() {
+            self.stack[self.len] = (local_pos + *template_pos * half, half);
+            self.len += 1;
+        }
+    }
+}
+
+impl<P: Fn(AABB) -> bool> Iterator for TreeIndexIterator<P> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.len > 0 {
+            self.len -= 1;
+            let (local_pos, side_child_count) = self.stack[self.len];
+            let min = self.global_pos + local_pos * self.child_side_chunk_count;
+            let aabb = AABB::new(
+                min,
+                min + IVec3::splat(side_child_count * self.child_side_chunk_count),
+            );
+            if !self.global_aabb.intersects(&aabb) || !(self.predicate)(aabb) {
+                continue;
+            }
+            if side_child_count == 1 {
+                return Some(get_index_from_pos(local_pos));
+            }
+            self.push_halves(local_pos, side_child_count);
+        }
+        None
+    }
 }
 
 struct Leaf {
-    chunk: Chunk,
+    chunk: Arc<Chunk>, //shared with the snapshots, the chunk is copied on write while a snapshot holds it
     id: Id,
 }
 
@@ -172,20 +224,63 @@ impl Node for Level1 {
     fn get_chunk(&self, pos: IVec3) -> Option<&Chunk> {
         let index = get_index_from_pos(pos);
         let leaf = &self.children[index];
-        leaf.as_ref().map(|x| &x.chunk)
+        leaf.as_ref().map(|x| x.chunk.as_ref())
     }
 
-    fn get_chunk_mut(&mut self, pos: IVec3) -> Option<&mut Chunk> {
+    fn get_chunk_mut(&mut self, pos: IVec3) -> Option<(Id, &mut Arc<Chunk>)> {
         let index = get_index_from_pos(pos);
         let leaf = &mut self.children[index];
-        leaf.as_mut().map(|x| &mut x.chunk)
+        leaf.as_mut().map(|x| (x.id, &mut x.chunk))
     }
 
-    fn emplace_chunk(&mut self, chunk: Chunk, pos: IVec3, id_tracker: &mut IdTracker) -> Id {
+    fn get_chunk_id(&self, pos: IVec3) -> Option<Id> {
+        let index = get_index_from_pos(pos);
+        self.children[index].as_ref().map(|x| x.id)
+    }
+
+    fn emplace_chunk(
+        &mut self,
+        chunk: Chunk,
+        pos: IVec3,
+        id_tracker: &mut IdTracker,
+    ) -> (Id, Option<Id>) {
         let index = get_index_from_pos(pos);
         let id = id_tracker.alloc();
-        self.children[index] = Some(Leaf { chunk, id });
-        id
+        let replaced = self.children[index].replace(Leaf {
+            chunk: Arc::new(chunk),
+            id,
+        });
+        (id, replaced.map(|leaf| leaf.id))
+    }
+
+    fn get_or_emplace_chunk(
+        &mut self,
+        pos: IVec3,
+        create: impl FnOnce() -> Chunk,
+        id_tracker: &mut IdTracker,
+    ) -> (Id, bool, &mut Arc<Chunk>) {
+        let index = get_index_from_pos(pos);
+        let leaf = &mut self.children[index];
+        let created = leaf.is_none();
+        if created {
+            *leaf = Some(Leaf {
+                chunk: Arc::new(create()),
+                id: id_tracker.alloc(),
+            });
+        }
+        let leaf = leaf.as_mut().unwrap();
+        (leaf.id, created, &mut leaf.chunk)
+    }
+
+    fn remove_chunk(&mut self, pos: IVec3) -> Option<(Id, Arc<Chunk>)> {
+        let index = get_index_from_pos(pos);
+        self.children[index]
+            .take()
+            .map(|leaf| (leaf.id, leaf.chunk))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.children.iter().all(Option::is_none)
     }
 
     fn for_chunk_in<'a>(&'a self, global_aabb: AABB, out_func: &mut impl FnMut(Id, &'a Chunk)) {
@@ -204,10 +299,9 @@ impl Node for Level1 {
         //algorithm could be improved by calculating the intersection of the aabb with the node aabb, and then take the chunk that intersect this intersection
         for leaf in &self.children {
             if let Some(leaf) = leaf {
-                let chunk = &leaf.chunk;
+                let chunk = leaf.chunk.as_ref();
                 let id = leaf.id;
-                let chunk_aabb = AABB::new(chunk.position(), chunk.position() + IVec3::ONE);
-                if global_aabb.intersects(&chunk_aabb) {
+                if global_aabb.intersects(&chunk.get_aabb_in_chunks()) {
                     out_func(id, chunk);
                 }
             }
@@ -229,7 +323,7 @@ impl Node for Level1 {
         let iter = tree_index_iterator(self.global_pos, global_aabb, 1, predicate);
         for leaf in self.children.create_ref_iter(iter) {
             if let Some(leaf) = leaf {
-                out_func(leaf.id, &leaf.chunk);
+                out_func(leaf.id, leaf.chunk.as_ref());
             }
         }
     }
@@ -238,7 +332,7 @@ impl Node for Level1 {
         &'a mut self,
         global_aabb: AABB,
         predicate: impl Fn(AABB) -> bool + Copy,
-        out_func: &mut impl FnMut(Id, &'a mut Chunk),
+        out_func: &mut impl FnMut(Id, &'a mut Arc<Chunk>),
     ) {
         let this_aabb = self.get_aabb();
 
@@ -247,11 +341,11 @@ impl Node for Level1 {
         }
 
         let iter = tree_index_iterator(self.global_pos, global_aabb, 1, predicate);
-        for leaf in self.children.create_mut_iter(iter) {
+        for leaf in self.children.try_create_mut_iter(iter) {
+            //the tree index iterator gives each child at most once
+            let leaf = leaf.expect("invalid child index");
             if let Some(leaf) = leaf {
-                let chunk = &mut leaf.chunk;
-                let id = leaf.id;
-                out_func(id, chunk);
+                out_func(leaf.id, &mut leaf.chunk);
             }
         }
     }
@@ -259,10 +353,16 @@ impl Node for Level1 {
     fn for_all_chunks<'a>(&'a self, out_func: &mut impl FnMut(Id, &'a Chunk)) {
         for leaf in &self.children {
             if let Some(leaf) = leaf {
-                out_func(leaf.id, &leaf.chunk);
+                out_func(leaf.id, leaf.chunk.as_ref());
             }
         }
     }
+
+    fn for_all_shared_chunks(&self, out_func: &mut impl FnMut(&Arc<Chunk>)) {
+        for leaf in self.children.iter().flatten() {
+            out_func(&leaf.chunk);
+        }
+    }
 }
 
 struct LevelN<CHILD: Node> {
@@ -305,7 +405,7 @@ impl<T: Node> Node for LevelN<T> {
             .and_then(|child| child.get_chunk(pos_in_child))
     }
 
-    fn get_chunk_mut(&mut self, pos: IVec3) -> Option<&mut Chunk> {
+    fn get_chunk_mut(&mut self, pos: IVec3) -> Option<(Id, &mut Arc<Chunk>)> {
         let (local_pos, pos_in_child) = Self::split_pos(pos);
         let index = get_index_from_pos(local_pos);
         self.children[index]
@@ -313,7 +413,20 @@ impl<T: Node> Node for LevelN<T> {
             .and_then(|child| child.get_chunk_mut(pos_in_child))
     }
 
-    fn emplace_chunk(&mut self, chunk: Chunk, pos: IVec3, id_tracker: &mut IdTracker) -> Id {
+    fn get_chunk_id(&self, pos: IVec3) -> Option<Id> {
+        let (local_pos, pos_in_child) = Self::split_pos(pos);
+        let index = get_index_from_pos(local_pos);
+        self.children[index]
+            .as_ref()
+            .and_then(|child| child.get_chunk_id(pos_in_child))
+    }
+
+    fn emplace_chunk(
+        &mut self,
+        chunk: Chunk,
+        pos: IVec3,
+        id_tracker: &mut IdTracker,
+    ) -> (Id, Option<Id>) {
         let (local_pos, pos_in_child) = Self::split_pos(pos);
         let index = get_index_from_pos(local_pos);
 
@@ -322,12 +435,41 @@ impl<T: Node> Node for LevelN<T> {
         } else {
             let global_pos = self.global_pos + local_pos * T::SIDE_CHUNK_COUNT;
             let mut child = Box::new(T::new(global_pos));
-            let id = child.emplace_chunk(chunk, pos_in_child, id_tracker);
+            let ids = child.emplace_chunk(chunk, pos_in_child, id_tracker);
             self.children[index] = Some(child);
-            id
+            ids
         }
     }
 
+    fn get_or_emplace_chunk(
+        &mut self,
+        pos: IVec3,
+        create: impl FnOnce() -> Chunk,
+        id_tracker: &mut IdTracker,
+    ) -> (Id, bool, &mut Arc<Chunk>) {
+        let (local_pos, pos_in_child) = Self::split_pos(pos);
+        let index = get_index_from_pos(local_pos);
+        let global_pos = self.global_pos + local_pos * T::SIDE_CHUNK_COUNT;
+        self.children[index]
+            .get_or_insert_with(|| Box::new(T::new(global_pos)))
+            .get_or_emplace_chunk(pos_in_child, create, id_tracker)
+    }
+
+    fn remove_chunk(&mut self, pos: IVec3) -> Option<(Id, Arc<Chunk>)> {
+        let (local_pos, pos_in_child) = Self::split_pos(pos);
+        let index = get_index_from_pos(local_pos);
+        let child = self.children[index].as_mut()?;
+        let removed = child.remove_chunk(pos_in_child);
+        if child.is_empty() {
+            self.children[index] = None;
+        }
+        removed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.children.iter().all(Option::is_none)
+    }
+
     fn for_chunk_in<'a>(&'a self, global_aabb: AABB, out_func: &mut impl FnMut(Id, &'a Chunk)) {
         //if the local_aabb totally contains the node, we can put all the chunks in the out vec
         let this_aabb = self.get_aabb();
@@ -360,12 +502,8 @@ impl<T: Node> Node for LevelN<T> {
             return;
         }
 
-        let iter = tree_index_iterator(
-            self.global_pos,
-            global_aabb,
-            Self::SIDE_CHUNK_COUNT,
-            predicate,
-        );
+        let iter =
+            tree_index_iterator(self.global_pos, global_aabb, T::SIDE_CHUNK_COUNT, predicate);
         for child in self.children.create_ref_iter(iter) {
             if let Some(child) = child {
                 child.for_chunk_with_predicate(global_aabb, predicate, out_func);
@@ -377,7 +515,7 @@ impl<T: Node> Node for LevelN<T> {
         &'a mut self,
         global_aabb: AABB,
         predicate: impl Fn(AABB) -> bool + Copy,
-        out_func: &mut impl FnMut(Id, &'a mut Chunk),
+        out_func: &mut impl FnMut(Id, &'a mut Arc<Chunk>),
     ) {
         let this_aabb = self.get_aabb();
 
@@ -387,7 +525,8 @@ impl<T: Node> Node for LevelN<T> {
 
         let iter =
             tree_index_iterator(self.global_pos, global_aabb, T::SIDE_CHUNK_COUNT, predicate);
-        for child in self.children.create_mut_iter(iter) {
+        for child in self.children.try_create_mut_iter(iter) {
+            let child = child.expect("invalid child index");
             if let Some(child) = child {
                 child.for_chunk_with_predicate_mut(global_aabb, predicate, out_func);
             }
@@ -401,14 +540,153 @@ impl<T: Node> Node for LevelN<T> {
             }
         }
     }
+
+    fn for_all_shared_chunks(&self, out_func: &mut impl FnMut(&Arc<Chunk>)) {
+        for child in self.children.iter().flatten() {
+            child.for_all_shared_chunks(out_func);
+        }
+    }
 }
 
 type Level2 = LevelN<Level1>;
 type Level3 = LevelN<Level2>;
-//type Level4 = LevelN<Level3>; //bigger level are not needed, the hashmap will take care of the rest
+#[cfg(feature = "deep_sections")]
+type Level4 = LevelN<Level3>; //bigger level are not needed, the hashmap will take care of the rest
 
 ///a section is a 512 chunks wide cube
-type Section = Level3; //also works with Level3
+#[cfg(not(feature = "deep_sections"))]
+type Section = Level3;
+
+///with the deep_sections feature, a section is a 4096 chunks wide cube
+///a big and dense world is stored in less sections, which improves the locality, but a sparse world uses more nodes
+#[cfg(feature = "deep_sections")]
+type Section = Level4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkManagerError {
+    ///the chunk position is outside of [`ChunkManager::MIN_CHUNK_COORD`]..=[`ChunkManager::MAX_CHUNK_COORD`] on some axis
+    OutOfRange(ChunkPos),
+}
+
+impl Error for ChunkManagerError {}
+
+impl Display for ChunkManagerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkManagerError::OutOfRange(pos) => {
+                write!(
+                    f,
+                    "the chunk position ({}, {}, {}) is out of the world",
+                    pos.x, pos.y, pos.z
+                )
+            }
+        }
+    }
+}
+
+///what [`ChunkManager::evict_beyond`] removed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvictionStats {
+    pub chunk_count: usize,
+    ///the sections left empty, they are removed with their last chunk
+    pub section_count: usize,
+}
+
+///a chunk borrowed mutably from the [`ChunkManager`], it is marked as modified the first time it is borrowed mutably
+///reading it never copies it, a chunk shared with a snapshot is only copied the first time it is borrowed mutably
+pub struct ChunkMut<'a> {
+    id: Id,
+    chunk: &'a mut Arc<Chunk>,
+    modified: &'a RefCell<DirtyList>, //shared by the chunks of the same call
+}
+
+impl ChunkMut<'_> {
+    ///the ID of the chunk in the [`ChunkManager`]
+    pub fn id(&self) -> Id {
+        self.id
+    }
+}
+
+impl Deref for ChunkMut<'_> {
+    type Target = Chunk;
+
+    fn deref(&self) -> &Chunk {
+        self.chunk.as_ref()
+    }
+}
+
+impl DerefMut for ChunkMut<'_> {
+    fn deref_mut(&mut self) -> &mut Chunk {
+        self.modified.borrow_mut().push(self.id);
+        Arc::make_mut(self.chunk)
+    }
+}
+
+///the IDs of the chunks modified this tick, each ID is kept once, in the order of its first modification
+///a bit per ID makes a new modification of a chunk O(1) instead of sorting the whole list when it is processed,
+///the IDs are reused by the tracker so the bits stay dense, a hash set was slower than the sort
+#[derive(Default)]
+struct DirtyList {
+    ids: Vec<Id>,
+    contained: Vec<u64>, //indexed by the raw ID
+}
+
+impl DirtyList {
+    fn push(&mut self, id: Id) {
+        let (word, bit) = (id.raw() as usize / 64, 1 << (id.raw() % 64));
+        if word >= self.contained.len() {
+            self.contained.resize(word + 1, 0);
+        }
+        if self.contained[word] & bit == 0 {
+            self.contained[word] |= bit;
+            self.ids.push(id);
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.ids.reserve(additional);
+    }
+
+    fn ids(&self) -> &[Id] {
+        &self.ids
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = Id> + '_ {
+        self.clear_bits();
+        self.ids.drain(..)
+    }
+
+    fn clear(&mut self) {
+        self.clear_bits();
+        self.ids.clear();
+    }
+
+    ///only the words of the listed IDs are cleared, a tick with few modifications doesn't walk all the bits
+    fn clear_bits(&mut self) {
+        for id in &self.ids {
+            self.contained[id.raw() as usize / 64] = 0;
+        }
+    }
+}
+
+///the occupation of the sections of a [`ChunkManager`], to check that the depth of the octree suits the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkManagerStats {
+    pub section_count: usize,
+    pub chunk_count: usize,
+    ///the number of chunks in the fullest section
+    pub max_section_fill: usize,
+}
+
+impl ChunkManagerStats {
+    ///the average number of chunks per section, 0 without section
+    pub fn average_section_fill(&self) -> f32 {
+        if self.section_count == 0 {
+            return 0.0;
+        }
+        self.chunk_count as f32 / self.section_count as f32
+    }
+}
 
 ///this chunks manager cut the world in section of 4096 chunks, it has some cool properties:
 ///for all 32bits blockState position, there is a unique 16 bits region position, because :
@@ -422,49 +700,178 @@ type Section = Level3; //also works with Level3
 /// Using both an Octree and an HashMap seems to be the best solution. The HashMap will be used to store the regions and deal with the huge size of the world, and the QuadTree will be used to store the chunks with a good access locality.
 ///
 ///the Octree have to store 4096 chunks^3, So I chose to split each node in 512 children (8^3), which gives us a depth of 3.
+///the deep_sections feature adds a fourth level, for worlds with huge dense builds, see [`Section`]
 ///the Octree also make chunk insertion and deletion pretty fast, at least faster than in a big HashMap.
 pub struct ChunkManager {
     section_map: HashMap<I16Vec3, Section>, //using an octree to store the entire world would require 11 level of depth, which is a lot, the hashmap skip 6 level of depth, where the nodes are sparse and the hashmap is more efficient
     chunk_id_tracker: IdTracker,            //attribute an unique ID to each chunk
-    chunk_modified: Vec<Id>, //track all the chunks that have been modified, this tick, for various purpose, like caching meshes or packets, or for saving the world
+    chunk_positions: HashMap<Id, ChunkPos>, //the position of the chunk of each ID, to find the modified chunks back
+    chunk_modified: RefCell<DirtyList>, //track all the chunks that have been modified, this tick, for various purpose, like caching meshes or packets, or for saving the world
+    block_edits: Option<HashMap<ChunkPos, BTreeMap<u16, BlockState>>>, //the blocks set since the last drain by chunk and index, only when they are recorded
 }
 
 impl ChunkManager {
+    ///the range of the chunk coordinates on each axis, both included, the chunks out of it can't be stored
+    ///the position of a section is stored in an i16, so with 512 chunks wide sections the range is [-2^24, 2^24[,
+    ///and with the deep_sections feature it is [-2^27, 2^27[, the whole range of the i32 block positions
+    pub const MIN_CHUNK_COORD: i32 = i16::MIN as i32 * Section::SIDE_CHUNK_COUNT;
+    pub const MAX_CHUNK_COORD: i32 = (i16::MAX as i32 + 1) * Section::SIDE_CHUNK_COUNT - 1;
+
     pub fn new() -> Self {
         Self {
             section_map: HashMap::new(),
             chunk_id_tracker: IdTracker::new(),
-            chunk_modified: Vec::new(),
+            chunk_positions: HashMap::new(),
+            chunk_modified: RefCell::default(),
+            block_edits: None,
         }
     }
 
-    ///register a chunk in the World, this function mark the chunk as modified this tick
-    pub fn insert_chunk(&mut self, chunk: Chunk) {
-        let pos = chunk.position();
+    ///split a chunk position in the position of its section and its position in the section
+    ///an error if the section position doesn't fit in an i16, it would wrap and alias a distant section
+    fn split_pos(pos: ChunkPos) -> Result<(I16Vec3, IVec3), ChunkManagerError> {
+        let min = IVec3::splat(Self::MIN_CHUNK_COORD);
+        let max = IVec3::splat(Self::MAX_CHUNK_COORD);
+        if pos.cmplt(min).any() || pos.cmpgt(max).any() {
+            return Err(ChunkManagerError::OutOfRange(pos));
+        }
         let region_pos = pos
             .div_euclid(IVec3::splat(Section::SIDE_CHUNK_COUNT))
             .as_i16vec3(); //euclid division is important here, else the sign of the number will be wrong
         let local_pos = pos.rem_euclid(IVec3::splat(Section::SIDE_CHUNK_COUNT));
+        Ok((region_pos, local_pos))
+    }
 
-        let id = if let Some(section) = self.section_map.get_mut(&region_pos) {
+    ///register a chunk in the World, this function mark the chunk as modified this tick
+    ///a chunk already loaded at the position is replaced, its id is freed like in [`ChunkManager::remove_chunk`]
+    ///an error if the chunk is out of the world, see [`ChunkManager::MIN_CHUNK_COORD`], the chunk is dropped in this case
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Result<(), ChunkManagerError> {
+        let pos = chunk.position();
+        let (region_pos, local_pos) = Self::split_pos(pos)?;
+
+        let (id, replaced) = if let Some(section) = self.section_map.get_mut(&region_pos) {
             section.emplace_chunk(chunk, local_pos, &mut self.chunk_id_tracker)
         } else {
             let global_pos = region_pos.as_ivec3() * Section::SIDE_CHUNK_COUNT;
             let mut section = Section::new(global_pos);
-            let id = section.emplace_chunk(chunk, local_pos, &mut self.chunk_id_tracker);
+            let ids = section.emplace_chunk(chunk, local_pos, &mut self.chunk_id_tracker);
             self.section_map.insert(region_pos, section);
-            id
+            ids
         };
 
+        if let Some(replaced) = replaced {
+            self.chunk_positions.remove(&replaced);
+            self.make_dirty(replaced);
+            self.chunk_id_tracker.free(replaced);
+        }
+        self.chunk_positions.insert(id, pos);
         self.make_dirty(id);
+        Ok(())
+    }
+
+    ///register many chunks at once, like [`ChunkManager::insert_chunk`] but faster for a bulk load
+    ///the section is kept between consecutive chunks of the same section, so it is looked up (or created) once per run
+    ///the chunks are best given section by section, like a generator or a region file does
+    ///stop at the first chunk out of the world with an error, the previous chunks stay inserted
+    pub fn insert_chunks(
+        &mut self,
+        chunks: impl IntoIterator<Item = Chunk>,
+    ) -> Result<(), ChunkManagerError> {
+        let chunks = chunks.into_iter();
+        self.chunk_modified.get_mut().reserve(chunks.size_hint().0);
+        self.chunk_positions.reserve(chunks.size_hint().0);
+
+        let mut current_section: Option<(I16Vec3, &mut Section)> = None;
+        for chunk in chunks {
+            let pos = chunk.position();
+            let (region_pos, local_pos) = Self::split_pos(pos)?;
+
+            let section = match current_section {
+                Some((current_pos, section)) if current_pos == region_pos => section,
+                _ => self.section_map.entry(region_pos).or_insert_with(|| {
+                    Section::new(region_pos.as_ivec3() * Section::SIDE_CHUNK_COUNT)
+                }),
+            };
+            let (id, replaced) =
+                section.emplace_chunk(chunk, local_pos, &mut self.chunk_id_tracker);
+            //the section is borrowed, the replaced chunk is forgotten like in insert_chunk
+            if let Some(replaced) = replaced {
+                self.chunk_positions.remove(&replaced);
+                self.chunk_modified.get_mut().push(replaced);
+                self.chunk_id_tracker.free(replaced);
+            }
+            self.chunk_positions.insert(id, pos);
+            self.chunk_modified.get_mut().push(id);
+            current_section = Some((region_pos, section));
+        }
+        Ok(())
+    }
+
+    ///unload the chunk at the given position and return it, None if it isn't loaded
+    ///its section is removed if it is left empty, and its id is freed, so it can be given to another chunk later
+    ///the id is marked as modified without a position, see [`ChunkManager::get_chunk_pos`], so the caches keyed by id drop the chunk
+    ///a chunk still held by a snapshot is copied
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        let (region_pos, local_pos) = Self::split_pos(pos).ok()?;
+        let section = self.section_map.get_mut(&region_pos)?;
+        let (id, chunk) = section.remove_chunk(local_pos)?;
+        if section.is_empty() {
+            self.section_map.remove(&region_pos);
+        }
+        self.chunk_positions.remove(&id);
+        self.chunk_modified.get_mut().push(id);
+        self.chunk_id_tracker.free(id);
+        Some(Arc::try_unwrap(chunk).unwrap_or_else(|chunk| (*chunk).clone()))
+    }
+
+    ///unload the chunks farther than `radius` chunks from the center, to cap the memory used by the world
+    ///the distance is measured between the chunk positions like in [`ChunkManager::chunks_within`]
+    ///each removed chunk is given to `on_evicted`, for example to save it, the ids of the chunks are freed, see [`ChunkManager::remove_chunk`]
+    pub fn evict_beyond(
+        &mut self,
+        center: ChunkPos,
+        radius: i32,
+        mut on_evicted: impl FnMut(Chunk),
+    ) -> EvictionStats {
+        //the chunks of the world are up to 2^19 chunks apart, their squared distance doesn't fit in an i32
+        let radius_squared = radius as i64 * radius as i64;
+        let is_beyond =
+            |pos: ChunkPos| (pos - center).as_i64vec3().length_squared() > radius_squared;
+        let mut evicted = Vec::new();
+        for section in self.section_map.values() {
+            //the section is kept as is if its farthest chunk is in the radius
+            let aabb = section.get_aabb();
+            let farthest = IVec3::select(
+                (center - aabb.min()).cmpgt(aabb.max() - IVec3::ONE - center),
+                aabb.min(),
+                aabb.max() - IVec3::ONE,
+            );
+            if !is_beyond(farthest) {
+                continue;
+            }
+            section.for_all_chunks(&mut |_, chunk| {
+                if is_beyond(chunk.position()) {
+                    evicted.push(chunk.position());
+                }
+            });
+        }
+
+        let section_count = self.section_map.len();
+        for pos in &evicted {
+            if let Some(chunk) = self.remove_chunk(*pos) {
+                on_evicted(chunk);
+            }
+        }
+        EvictionStats {
+            chunk_count: evicted.len(),
+            section_count: section_count - self.section_map.len(),
+        }
     }
 
     ///get a chunk in the world, this function doesn't mark the chunk as modified
+    ///None if the chunk isn't loaded or is out of the world
     pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
-        let region_pos = pos
-            .div_euclid(IVec3::splat(Section::SIDE_CHUNK_COUNT))
-            .as_i16vec3();
-        let local_pos = pos.rem_euclid(IVec3::splat(Section::SIDE_CHUNK_COUNT));
+        let (region_pos, local_pos) = Self::split_pos(pos).ok()?;
         if let Some(section) = self.section_map.get(&region_pos) {
             section.get_chunk(local_pos)
         } else {
@@ -472,45 +879,217 @@ impl ChunkManager {
         }
     }
 
-    ///get a chunk in the world with mutable capabilities
-    pub fn get_chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
-        let region_pos = pos
-            .div_euclid(IVec3::splat(Section::SIDE_CHUNK_COUNT))
-            .as_i16vec3();
-        let local_pos = pos.rem_euclid(IVec3::splat(Section::SIDE_CHUNK_COUNT));
-        let (section_map, _) = (&mut self.section_map, &mut self.chunk_modified);
-        if let Some(section) = section_map.get_mut(&region_pos) {
-            section.get_chunk_mut(local_pos)
-            //TODO: mark the chunk as modified
-        } else {
-            None
+    ///get the block at a world position, None if its chunk isn't loaded
+    pub fn get_block(&self, pos: BlockPos) -> Option<BlockState> {
+        let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE));
+        let chunk = self.get_chunk(chunk_pos)?;
+        Some(chunk.get_block(pos.rem_euclid(IVec3::splat(CHUNK_SIZE))))
+    }
+
+    ///get a chunk in the world with mutable capabilities, this function mark the chunk as modified this tick
+    ///a chunk shared with a snapshot is only copied when it is borrowed mutably through the [`ChunkMut`]
+    pub fn get_chunk_mut(&mut self, pos: ChunkPos) -> Option<ChunkMut<'_>> {
+        let (region_pos, local_pos) = Self::split_pos(pos).ok()?;
+        let section = self.section_map.get_mut(&region_pos)?;
+        let (id, chunk) = section.get_chunk_mut(local_pos)?;
+        self.chunk_modified.get_mut().push(id);
+        Some(ChunkMut {
+            id,
+            chunk,
+            modified: &self.chunk_modified,
+        })
+    }
+
+    ///get a chunk in the world with mutable capabilities without marking it as modified, for the callers that only read it
+    ///the caches that depend on the chunk aren't refreshed if it is changed, use [`ChunkManager::get_chunk_mut`] to change it
+    pub fn peek_chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        let (region_pos, local_pos) = Self::split_pos(pos).ok()?;
+        let section = self.section_map.get_mut(&region_pos)?;
+        section
+            .get_chunk_mut(local_pos)
+            .map(|(_, chunk)| Arc::make_mut(chunk))
+    }
+
+    ///get a chunk in the world, or create it with the given function and insert it if it isn't loaded, in a single lookup
+    ///a created chunk is marked as modified, an existing one only when it is borrowed mutably through the [`ChunkMut`]
+    ///an error if the position is out of the world, the function isn't called in this case
+    pub fn get_chunk_or_insert_with(
+        &mut self,
+        pos: ChunkPos,
+        create: impl FnOnce() -> Chunk,
+    ) -> Result<ChunkMut<'_>, ChunkManagerError> {
+        let (region_pos, local_pos) = Self::split_pos(pos)?;
+        let section = self
+            .section_map
+            .entry(region_pos)
+            .or_insert_with(|| Section::new(region_pos.as_ivec3() * Section::SIDE_CHUNK_COUNT));
+        let create = || {
+            let chunk = create();
+            debug_assert_eq!(
+                chunk.position(),
+                pos,
+                "the chunk was created at another position"
+            );
+            chunk
+        };
+        let (id, created, chunk) =
+            section.get_or_emplace_chunk(local_pos, create, &mut self.chunk_id_tracker);
+        if created {
+            self.chunk_positions.insert(id, pos);
+            self.chunk_modified.get_mut().push(id);
         }
+        Ok(ChunkMut {
+            id,
+            chunk,
+            modified: &self.chunk_modified,
+        })
+    }
+
+    ///set the block at a world position and mark its chunk as modified, return false if the chunk isn't loaded
+    pub fn set_block(&mut self, pos: BlockPos, state: BlockState) -> bool {
+        let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE));
+        let Some(mut chunk) = self.get_chunk_mut(chunk_pos) else {
+            return false;
+        };
+        let pos_in_chunk = pos.rem_euclid(IVec3::splat(CHUNK_SIZE));
+        chunk.set_block(pos_in_chunk, state);
+        if let Some(block_edits) = &mut self.block_edits {
+            block_edits
+                .entry(chunk_pos)
+                .or_default()
+                .insert(Chunk::block_index(pos_in_chunk), state);
+        }
+        true
+    }
+
+    ///set the block at a world position for an editing tool, the chunk is created empty if it isn't loaded
+    ///like [`ChunkManager::set_block`], the chunk is marked as modified and the edit is recorded,
+    ///and if the block is on the border of its chunk, the loaded chunks sharing the face are marked as modified too, to refresh their faces
+    ///an error if the position is out of the world
+    pub fn edit_block(
+        &mut self,
+        pos: BlockPos,
+        state: BlockState,
+    ) -> Result<(), ChunkManagerError> {
+        let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE));
+        self.get_chunk_or_insert_with(chunk_pos, || Chunk::new(chunk_pos))?;
+        self.set_block(pos, state);
+
+        let pos_in_chunk = pos.rem_euclid(IVec3::splat(CHUNK_SIZE));
+        for face in BlockFace::ALL {
+            let normal = face.normal();
+            let border = IVec3::splat(CHUNK_SIZE - 1) * normal.max(IVec3::ZERO);
+            if (pos_in_chunk * normal.abs()) != border {
+                continue;
+            }
+            let neighbor_pos = chunk_pos + normal;
+            let Ok((region_pos, local_pos)) = Self::split_pos(neighbor_pos) else {
+                continue;
+            };
+            if let Some(id) = self
+                .section_map
+                .get(&region_pos)
+                .and_then(|section| section.get_chunk_id(local_pos))
+            {
+                self.make_dirty(id);
+            }
+        }
+        Ok(())
+    }
+
+    ///start recording the blocks set with [`ChunkManager::set_block`], to send them in batches, see [`ChunkManager::drain_block_edits`]
+    ///the chunks modified through [`ChunkManager::get_chunk_mut`] aren't recorded, they are only marked as modified
+    pub fn record_block_edits(&mut self) {
+        self.block_edits.get_or_insert_with(HashMap::new);
+    }
+
+    ///give the blocks set since the last call grouped by chunk, with their index in the chunk (see [`Chunk::block_index`])
+    ///a block set many times is given once with its last state, the chunks are sorted by position and the blocks by index
+    pub fn drain_block_edits(&mut self) -> Vec<(ChunkPos, Vec<(u16, BlockState)>)> {
+        let Some(block_edits) = &mut self.block_edits else {
+            return Vec::new();
+        };
+        let mut edits = block_edits
+            .drain()
+            .map(|(pos, blocks)| (pos, blocks.into_iter().collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        edits.sort_by_key(|(pos, _)| pos.to_array());
+        edits
+    }
+
+    ///replace the blocks of the `replace` state connected to `start` by their faces with the `with` state
+    ///at most `max_blocks` blocks are replaced, the unloaded chunks stop the fill like walls
+    ///return the number of replaced blocks, the modified chunks are marked as modified
+    pub fn flood_fill(
+        &mut self,
+        start: BlockPos,
+        replace: BlockState,
+        with: BlockState,
+        max_blocks: usize,
+    ) -> usize {
+        if replace == with || max_blocks == 0 || self.get_block(start) != Some(replace) {
+            return 0;
+        }
+
+        //the blocks are replaced when they are queued, so a block can't be queued twice
+        self.set_block(start, with);
+        let mut replaced = 1;
+        let mut queue = VecDeque::from([start]);
+        while let Some(pos) = queue.pop_front() {
+            for face in BlockFace::ALL {
+                if replaced == max_blocks {
+                    return replaced;
+                }
+                let neighbor = pos + face.normal();
+                if self.get_block(neighbor) == Some(replace) {
+                    self.set_block(neighbor, with);
+                    replaced += 1;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        replaced
+    }
+
+    ///get the six chunks sharing a face with the given chunk, indexed by [`BlockFace`]
+    pub fn neighbors(&self, pos: ChunkPos) -> [Option<&Chunk>; 6] {
+        BlockFace::ALL.map(|face| self.get_chunk(pos + face.normal()))
+    }
+
+    ///get the 26 chunks around the given chunk, ordered by z, then y, then x, from -1 to 1
+    pub fn neighbors_including_diagonals(&self, pos: ChunkPos) -> [Option<&Chunk>; 26] {
+        let mut neighbors = [None; 26];
+        let offsets = (-1..=1)
+            .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| IVec3::new(x, y, z))))
+            .filter(|offset| *offset != IVec3::ZERO);
+        for (neighbor, offset) in neighbors.iter_mut().zip(offsets) {
+            *neighbor = self.get_chunk(pos + offset);
+        }
+        neighbors
     }
 
     ///get all loaded chunks in the given AABB, this function doesn't mark the chunks as modified
     pub fn get_chunks_in<'a>(&'a self, chunk_aabb: AABB) -> Vec<&Chunk> {
-        let mut chunks = Vec::with_capacity(chunk_aabb.get_volume() as usize);
+        let mut chunks = Vec::new(); //the AABB can be far bigger than the loaded chunks
         let out_func = &mut |_, chunk: &'a Chunk| chunks.push(chunk);
         self.foreach_chunk_in(chunk_aabb, out_func);
         chunks
     }
 
+    ///give the loaded chunks in the AABB, the order of the sections may depend on their hashing
+    ///see [`ChunkManager::foreach_chunk_in_ordered`] for an order that doesn't
     pub fn foreach_chunk_in<'a>(
         &'a self,
         chunk_aabb: AABB,
         out_func: &mut impl FnMut(Id, &'a Chunk),
     ) {
-        self.section_map.iter().for_each(|(pos, section)| {
-            let section_aabb = AABB::new(
-                pos.as_ivec3() * Section::SIDE_CHUNK_COUNT,
-                (pos.as_ivec3() + IVec3::ONE) * Section::SIDE_CHUNK_COUNT,
-            );
-            if let Some(intersection) = chunk_aabb.get_intersection(&section_aabb) {
-                section.for_chunk_in(intersection, out_func);
-            }
+        self.for_sections_in(chunk_aabb, |_, section, intersection| {
+            section.for_chunk_in(intersection, out_func);
         });
     }
 
+    ///give the loaded chunks in the AABB that satisfy the predicate, in no particular order like [`ChunkManager::foreach_chunk_in`]
+    ///the predicate is tested on the nodes, a node that fails it is skipped with all its chunks
     pub fn foreach_chunk_with_predicate<'a>(
         &'a self,
         chunk_aabb: AABB,
@@ -518,16 +1097,123 @@ impl ChunkManager {
         out_func: impl FnMut(Id, &'a Chunk),
     ) {
         let mut out_func = out_func;
+        self.for_sections_in(chunk_aabb, |_, section, intersection| {
+            section.for_chunk_with_predicate(intersection, predicate, &mut out_func);
+        });
+    }
 
-        self.section_map.iter().for_each(|(pos, section)| {
-            let section_aabb = AABB::new(
-                pos.as_ivec3() * Section::SIDE_CHUNK_COUNT,
-                (pos.as_ivec3() + IVec3::ONE) * Section::SIDE_CHUNK_COUNT,
-            );
-            if let Some(intersection) = chunk_aabb.get_intersection(&section_aabb) {
-                section.for_chunk_with_predicate(intersection, predicate, &mut out_func);
+    ///give the loaded chunks at most `radius` chunks away from the center, the chunks at exactly `radius` are included
+    ///the distance is measured between the chunk positions, so the chunks form a sphere around the center, whatever the camera is
+    pub fn chunks_within<'a>(
+        &'a self,
+        center: ChunkPos,
+        radius: i32,
+        out_func: impl FnMut(Id, &'a Chunk),
+    ) {
+        debug_assert!(radius >= 0, "the radius can't be negative");
+        let chunk_aabb = AABB::new(
+            center - IVec3::splat(radius),
+            center + IVec3::splat(radius + 1),
+        );
+        self.foreach_chunk_with_predicate(chunk_aabb, predicates::sphere(center, radius), out_func);
+    }
+
+    ///give the loaded chunks in the frustum, for example the chunks a player can see, to only stream these ones
+    ///the nodes out of the frustum are skipped with all their chunks, like the terrain renderer does
+    pub fn chunks_in_view<'a>(
+        &'a self,
+        frustum: &CameraFrustum,
+        out_func: impl FnMut(Id, &'a Chunk),
+    ) {
+        self.foreach_chunk_with_predicate(
+            frustum.get_aabb(),
+            predicates::frustum(frustum),
+            out_func,
+        );
+    }
+
+    ///call the function for the loaded sections intersecting the chunk AABB, with their position and the part of the AABB in each
+    ///the cells of the AABB are looked up in the map, by x, then y, then z, unless the map has fewer sections than the AABB has cells,
+    ///then the map is scanned in the order of the hashing, nothing is allocated in both cases
+    ///the part of the AABB out of the world is ignored
+    fn for_sections_in<'a>(
+        &'a self,
+        chunk_aabb: AABB,
+        mut func: impl FnMut(I16Vec3, &'a Section, AABB),
+    ) {
+        let world_aabb = AABB::new(
+            IVec3::splat(Self::MIN_CHUNK_COORD),
+            IVec3::splat(Self::MAX_CHUNK_COORD + 1),
+        );
+        let Some(chunk_aabb) = chunk_aabb.get_intersection(&world_aabb) else {
+            return;
+        };
+        let side = IVec3::splat(Section::SIDE_CHUNK_COUNT);
+        let cells = (chunk_aabb.max() - IVec3::ONE).div_euclid(side)
+            - chunk_aabb.min().div_euclid(side)
+            + IVec3::ONE;
+        let cell_count = cells.x as u64 * cells.y as u64 * cells.z as u64;
+
+        if cell_count <= self.section_map.len() as u64 {
+            for (cell, intersection) in chunk_aabb.subdivide_into_grid(Section::SIDE_CHUNK_COUNT) {
+                let pos = cell.as_i16vec3();
+                if let Some(section) = self.section_map.get(&pos) {
+                    func(pos, section, intersection);
+                }
+            }
+        } else {
+            for (pos, section) in self.section_map.iter() {
+                let min = pos.as_ivec3() * side;
+                if let Some(intersection) = chunk_aabb.get_intersection(&AABB::new(min, min + side))
+                {
+                    func(*pos, section, intersection);
+                }
             }
+        }
+    }
+
+    ///the positions of the loaded sections intersecting the chunk AABB with the part of the AABB in each, sorted by x, then y, then z
+    fn section_intersections(&self, chunk_aabb: AABB) -> Vec<(I16Vec3, AABB)> {
+        let mut intersections = Vec::new();
+        self.for_sections_in(chunk_aabb, |pos, _, intersection| {
+            intersections.push((pos, intersection));
         });
+        //already sorted when the cells are looked up
+        intersections.sort_by_key(|(pos, _)| pos.to_array());
+        intersections
+    }
+
+    ///the loaded sections intersecting the chunk AABB with their intersection, sorted by position (x, then y, then z)
+    fn sections_in(&self, chunk_aabb: AABB) -> impl Iterator<Item = (&Section, AABB)> {
+        self.section_intersections(chunk_aabb)
+            .into_iter()
+            .map(|(pos, intersection)| (&self.section_map[&pos], intersection))
+    }
+
+    ///same as [`ChunkManager::foreach_chunk_in`], but the chunks are always given in the same order, whatever the hashing of the sections
+    ///the sections are sorted by position (x, then y, then z) and the chunks of a section are given in the order of the octree
+    ///the intersected sections are collected and sorted at each call, the traversals run every frame should use the unordered version
+    pub fn foreach_chunk_in_ordered<'a>(
+        &'a self,
+        chunk_aabb: AABB,
+        out_func: &mut impl FnMut(Id, &'a Chunk),
+    ) {
+        for (section, intersection) in self.sections_in(chunk_aabb) {
+            section.for_chunk_in(intersection, out_func);
+        }
+    }
+
+    ///same as [`ChunkManager::foreach_chunk_with_predicate`], with the order of [`ChunkManager::foreach_chunk_in_ordered`]
+    pub fn foreach_chunk_with_predicate_ordered<'a>(
+        &'a self,
+        chunk_aabb: AABB,
+        predicate: impl Fn(AABB) -> bool + Copy,
+        out_func: impl FnMut(Id, &'a Chunk),
+    ) {
+        let mut out_func = out_func;
+        for (section, intersection) in self.sections_in(chunk_aabb) {
+            section.for_chunk_with_predicate(intersection, predicate, &mut out_func);
+        }
     }
 
     ///return all loaded chunks that intersect the given AABB  and that satisfy the predicate, this function doesn't mark the chunks as modified
@@ -536,45 +1222,1202 @@ impl ChunkManager {
         chunk_aabb: AABB,
         predicate: impl Fn(AABB) -> bool + Copy,
     ) -> Vec<&Chunk> {
-        let mut chunks = Vec::with_capacity(chunk_aabb.get_volume() as usize);
+        let mut chunks = Vec::new();
         let out_func = &mut |_, chunk: &'a Chunk| chunks.push(chunk);
         self.foreach_chunk_with_predicate(chunk_aabb, predicate, out_func);
         chunks
     }
 
-    ///return all loaded chunks that intersect the given AABB
-    /// WARNING: right now this function doesn't mark the chunks as modified to avoid useless update, but it should
-    /// you should mark the chunk you modify as modified
+    ///return all loaded chunks that intersect the given AABB and that satisfy the predicate
+    ///a chunk is marked as modified when it is borrowed mutably through its [`ChunkMut`], reading it doesn't mark it
     pub fn get_chunk_with_predicate_mut<'a>(
         &'a mut self,
         chunk_aabb: AABB,
         predicate: impl Fn(AABB) -> bool + Copy,
-    ) -> Vec<&mut Chunk> {
-        let mut chunks = Vec::with_capacity(chunk_aabb.get_volume() as usize);
-        let out_func = &mut |_, chunk: &'a mut Chunk| chunks.push(chunk);
-
-        self.section_map.iter_mut().for_each(|(pos, section)| {
-            let section_aabb = AABB::new(
-                pos.as_ivec3() * Section::SIDE_CHUNK_COUNT,
-                (pos.as_ivec3() + IVec3::ONE) * Section::SIDE_CHUNK_COUNT,
-            );
-            if let Some(intersection) = chunk_aabb.get_intersection(&section_aabb) {
-                section.for_chunk_with_predicate_mut(intersection, predicate, out_func);
+    ) -> Vec<ChunkMut<'a>> {
+        let mut chunks = Vec::new();
+        let modified = &self.chunk_modified;
+        let out_func = &mut |id, chunk: &'a mut Arc<Chunk>| {
+            chunks.push(ChunkMut {
+                id,
+                chunk,
+                modified,
+            })
+        };
+
+        //the sections are matched like for the shared borrows, but the map can't lend them mutably one key at a time,
+        //so they are taken while iterating the map, then put back in the order of the matches
+        let intersections = self.section_intersections(chunk_aabb);
+        if intersections.is_empty() {
+            return chunks;
+        }
+        let mut sections = self
+            .section_map
+            .iter_mut()
+            .filter_map(|(pos, section)| {
+                let index = intersections
+                    .binary_search_by_key(&pos.to_array(), |(pos, _)| pos.to_array())
+                    .ok()?;
+                Some((index, section))
+            })
+            .collect::<Vec<_>>();
+        sections.sort_by_key(|(index, _)| *index);
+        for (index, section) in sections {
+            section.for_chunk_with_predicate_mut(intersections[index].1, predicate, out_func);
+        }
+        chunks
+    }
+
+    ///count the blocks of the given state inside an AABB in block coordinates, the max corner being excluded
+    ///the unloaded chunks count for nothing, even for air
+    pub fn count_blocks(&self, aabb: AABB, state: BlockState) -> u64 {
+        let chunk_size = IVec3::splat(CHUNK_SIZE);
+        let chunk_aabb = AABB::new(
+            aabb.min().div_euclid(chunk_size),
+            (aabb.max() - IVec3::ONE).div_euclid(chunk_size) + IVec3::ONE,
+        );
+
+        let mut count = 0;
+        self.foreach_chunk_in(chunk_aabb, &mut |_, chunk| {
+            if chunk.is_empty() && state != AIR {
+                return;
             }
+            let chunk_aabb = chunk.get_aabb_in_block();
+            let Some(intersection) = aabb.get_intersection(&chunk_aabb) else {
+                return;
+            };
+            //the positions given by the iterator are relative to the chunk
+            let (min, max) = (
+                intersection.min() - chunk_aabb.min(),
+                intersection.max() - chunk_aabb.min(),
+            );
+            let non_air = chunk
+                .iter_blocks()
+                .filter(|(pos, _)| pos.cmpge(min).all() && pos.cmplt(max).all());
+            count += if state == AIR {
+                intersection.get_volume() as u64 - non_air.count() as u64
+            } else {
+                non_air.filter(|(_, block)| *block == state).count() as u64
+            };
         });
-        chunks
+        count
     }
 
     ///get a slice of all the chunks that have been modified this tick, it will also clear the list,
+    ///each chunk is given once, in the order of its first modification, sort the slice if another order is needed
+    ///the modified chunks are demoted first, so a chunk cleared of its blocks is empty again, see [`Chunk::demote`]
     pub fn on_process_modified_chunks(&mut self, func: impl FnOnce(&[Id])) {
-        self.chunk_modified.sort_by(|a, b| a.raw().cmp(&b.raw()));
-        self.chunk_modified.dedup();
-        func(&self.chunk_modified);
-        self.chunk_modified.clear();
+        self.try_demote_modified();
+        func(self.chunk_modified.get_mut().ids());
+        self.chunk_modified.get_mut().clear();
+    }
+
+    ///give the position and the current content of the chunks modified this tick, then clear the list
+    ///like [`ChunkManager::on_process_modified_chunks`], but the chunks are resolved, for example to send them to the clients
+    ///a chunk modified many times is given once
+    pub fn drain_modified(&mut self, mut func: impl FnMut(ChunkPos, &Chunk)) {
+        self.try_demote_modified();
+        let mut positions: Vec<ChunkPos> = self
+            .chunk_modified
+            .get_mut()
+            .drain()
+            .filter_map(|id| self.chunk_positions.get(&id).copied())
+            .collect();
+        positions.sort_by_key(|pos| pos.to_array());
+        for pos in positions {
+            if let Some(chunk) = self.get_chunk(pos) {
+                func(pos, chunk);
+            }
+        }
+    }
+
+    ///move the modified chunks that lost blocks to a smaller format, to give the memory back to the pool
+    ///the chunks are checked before being borrowed mutably, so a chunk shared with a snapshot is only copied if it is demoted
+    fn try_demote_modified(&mut self) {
+        for id in self.chunk_modified.get_mut().ids() {
+            let Some(pos) = self.chunk_positions.get(id) else {
+                continue;
+            };
+            let Ok((region_pos, local_pos)) = Self::split_pos(*pos) else {
+                continue;
+            };
+            let Some(section) = self.section_map.get_mut(&region_pos) else {
+                continue;
+            };
+            if section.get_chunk(local_pos).is_some_and(Chunk::can_demote) {
+                if let Some((_, chunk)) = section.get_chunk_mut(local_pos) {
+                    Arc::make_mut(chunk).demote();
+                }
+            }
+        }
+    }
+
+    ///the position of the chunk with the given ID, None if no chunk has this ID
+    pub fn get_chunk_pos(&self, id: Id) -> Option<ChunkPos> {
+        self.chunk_positions.get(&id).copied()
+    }
+
+    ///count the sections and the chunks in them, it walks all the loaded chunks
+    pub fn debug_stats(&self) -> ChunkManagerStats {
+        let mut stats = ChunkManagerStats {
+            section_count: self.section_map.len(),
+            ..Default::default()
+        };
+        for section in self.section_map.values() {
+            let mut fill = 0;
+            section.for_all_chunks(&mut |_, _| fill += 1);
+            stats.chunk_count += fill;
+            stats.max_section_fill = stats.max_section_fill.max(fill);
+        }
+        stats
+    }
+
+    ///capture the loaded chunks in an immutable view, for example to mesh them on another thread while the world is edited
+    ///the chunks are shared, not copied, a chunk is only copied when it is modified while a snapshot still holds it
+    pub fn snapshot(&self) -> ChunkSnapshot {
+        let mut chunks = HashMap::with_capacity(self.chunk_positions.len());
+        for section in self.section_map.values() {
+            section.for_all_shared_chunks(&mut |chunk| {
+                chunks.insert(chunk.position(), chunk.clone());
+            });
+        }
+        ChunkSnapshot { chunks }
     }
 
     ///mark a chunk as modified, calling this function will likely refresh all caches that depend on the chunk
     pub fn make_dirty(&mut self, id: Id) {
-        self.chunk_modified.push(id);
+        self.chunk_modified.get_mut().push(id);
+    }
+
+    ///pack all the chunks of a section in a region blob, the unit the world is saved in, None if the section isn't loaded
+    ///format: the region position (3 i16), the chunk count (u32), the index of the occupied slots, then the chunks data
+    ///each index entry is the slot (u64 Morton code of the local position) and the length of the chunk data (u32)
+    pub fn save_region(&self, region_pos: I16Vec3) -> Option<Vec<u8>> {
+        let section = self.section_map.get(&region_pos)?;
+        let section_origin = region_pos.as_ivec3() * Section::SIDE_CHUNK_COUNT;
+        let bits = Section::SIDE_CHUNK_COUNT.trailing_zeros();
+
+        let mut chunks = Vec::new();
+        section.for_all_chunks(&mut |_, chunk| {
+            let slot = morton_encode_3d(chunk.position() - section_origin, bits);
+            chunks.push((slot, chunk.serialize()));
+        });
+        chunks.sort_unstable_by_key(|(slot, _)| *slot); //close chunks are stored next to each other
+
+        let mut bytes = Vec::new();
+        for coordinate in region_pos.to_array() {
+            bytes.extend_from_slice(&coordinate.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        for (slot, data) in &chunks {
+            bytes.extend_from_slice(&slot.to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+        for (_, data) in &chunks {
+            bytes.extend_from_slice(data);
+        }
+        Some(bytes)
+    }
+
+    ///insert all the chunks of a region blob made by [`ChunkManager::save_region`], the loaded chunks replace the existing ones
+    ///return the position of the region, None if the blob is malformed, nothing is inserted in this case
+    pub fn load_region(&mut self, bytes: &[u8]) -> Option<I16Vec3> {
+        let mut reader = bytes;
+        let region_pos = I16Vec3::new(
+            i16::from_le_bytes(take_bytes(&mut reader)?),
+            i16::from_le_bytes(take_bytes(&mut reader)?),
+            i16::from_le_bytes(take_bytes(&mut reader)?),
+        );
+        let chunk_count = u32::from_le_bytes(take_bytes(&mut reader)?) as usize;
+        let section_origin = region_pos.as_ivec3() * Section::SIDE_CHUNK_COUNT;
+        let bits = Section::SIDE_CHUNK_COUNT.trailing_zeros();
+
+        let mut index = Vec::new();
+        for _ in 0..chunk_count {
+            let slot = u64::from_le_bytes(take_bytes(&mut reader)?);
+            let len = u32::from_le_bytes(take_bytes(&mut reader)?) as usize;
+            if slot >= 1 << (3 * bits) {
+                return None;
+            }
+            index.push((slot, len));
+        }
+
+        //decode everything before inserting, to not load half a region
+        let mut chunks = Vec::with_capacity(index.len());
+        for (slot, len) in index {
+            if len > reader.len() {
+                return None;
+            }
+            let (data, rest) = reader.split_at(len);
+            reader = rest;
+            let position = section_origin + morton_decode_3d(slot, bits);
+            chunks.push(Chunk::deserialize(position, data).ok()?);
+        }
+        if !reader.is_empty() {
+            return None;
+        }
+
+        self.insert_chunks(chunks)
+            .expect("the chunks of a section are always in the world");
+        Some(region_pos)
+    }
+}
+
+///the chunks of a [`ChunkManager`] at the time the snapshot was taken, see [`ChunkManager::snapshot`]
+///it can be sent to another thread, the later modifications of the manager don't change it
+#[derive(Clone)]
+pub struct ChunkSnapshot {
+    chunks: HashMap<ChunkPos, Arc<Chunk>>,
+}
+
+impl ChunkSnapshot {
+    ///get a chunk of the snapshot, None if it wasn't loaded
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos).map(Arc::as_ref)
+    }
+
+    ///get the block at a world position, None if its chunk wasn't loaded
+    pub fn get_block(&self, pos: BlockPos) -> Option<BlockState> {
+        let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE));
+        let chunk = self.get_chunk(chunk_pos)?;
+        Some(chunk.get_block(pos.rem_euclid(IVec3::splat(CHUNK_SIZE))))
+    }
+
+    ///get the six chunks sharing a face with the given chunk, indexed by [`BlockFace`]
+    pub fn neighbors(&self, pos: ChunkPos) -> [Option<&Chunk>; 6] {
+        BlockFace::ALL.map(|face| self.get_chunk(pos + face.normal()))
+    }
+
+    ///the number of chunks in the snapshot
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    ///iterate over all the chunks of the snapshot, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values().map(Arc::as_ref)
+    }
+}
+
+///read the next N bytes of the slice and advance it
+fn take_bytes<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(N);
+    *bytes = rest;
+    value.try_into().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block_registry::BlockFace;
+    use crate::block_state::{BlockState, AIR};
+    use crate::chunk_manager::{
+        get_index_from_pos, tree_index_iterator, ChunkManager, ChunkManagerError, Node, Section,
+        NODE_SUBDIVISION,
+    };
+    use crate::{Chunk, ChunkFormat};
+    use math::aabb::AABB;
+    use math::consts::CHUNK_SIZE;
+    use math::frustum::compute_frustum;
+    use math::positions::{BlockPos, ChunkPos, EntityPos};
+    use math::{I16Vec3, IVec3, Vec3};
+    use std::f32::consts::FRAC_PI_2;
+
+    ///the iteration before it was generalized, for NODE_SUBDIVISION == 8 only
+    fn hardcoded_tree_index_iterator(
+        global_pos: IVec3,
+        global_aabb: AABB,
+        child_side_chunk_count: i32,
+        predicate: impl Fn(AABB) -> bool + Copy,
+    ) -> impl Iterator<Item = usize> {
+        let get_aabb = move |local_pos: IVec3, side_child_count: i32| {
+            let min = global_pos + local_pos * child_side_chunk_count;
+            AABB::new(
+                min,
+                min + IVec3::splat(side_child_count * child_side_chunk_count),
+            )
+        };
+        const ITER: [IVec3; 8] = [
+            //all the possible position of the children
+            IVec3::new(0, 0, 0),
+            IVec3::new(0, 0, 1),
+            IVec3::new(0, 1, 0),
+            IVec3::new(0, 1, 1),
+            IVec3::new(1, 0, 0),
+            IVec3::new(1, 0, 1),
+            IVec3::new(1, 1, 0),
+            IVec3::new(1, 1, 1),
+        ];
+
+        ITER.iter()
+            .filter_map(move |template_pos| {
+                //first level of iteration
+                let side_child_count = NODE_SUBDIVISION / 2;
+                let local_pos = *template_pos * side_child_count;
+                let aabb = get_aabb(local_pos, side_child_count);
+                if !global_aabb.intersects(&aabb) || !predicate(aabb) {
+                    return None;
+                }
+
+                Some(
+                    ITER.iter()
+                        .filter_map(move |template_pos| {
+                            //second level of iteration
+                            let side_child_count = side_child_count / 2;
+                            let local_pos = local_pos + *template_pos * side_child_count;
+                            let aabb = get_aabb(local_pos, side_child_count);
+                            if !global_aabb.intersects(&aabb) || !predicate(aabb) {
+                                return None;
+                            }
+
+                            Some(ITER.iter().filter_map(move |template_pos| {
+                                //third level of iteration
+                                let side_child_count = side_child_count / 2;
+                                assert_eq!(side_child_count, 1);
+                                let local_pos = local_pos + *template_pos * side_child_count;
+                                let aabb = get_aabb(local_pos, side_child_count);
+                                if !global_aabb.intersects(&aabb) || !predicate(aabb) {
+                                    return None;
+                                }
+
+                                Some(get_index_from_pos(local_pos))
+                            }))
+                        })
+                        .flatten(),
+                ) //remove one level of nesting
+            })
+            .flatten() //remove one level of nesting
+    }
+
+    #[test]
+    pub fn tree_index_iterator_matches_the_hardcoded_levels() {
+        assert_eq!(NODE_SUBDIVISION, 8);
+        let global_pos = IVec3::new(-16, 8, 40);
+        let aabbs = [
+            AABB::new(IVec3::splat(-1000), IVec3::splat(1000)),
+            AABB::new(IVec3::new(-15, 9, 41), IVec3::new(-10, 12, 42)),
+            AABB::new(IVec3::new(-20, 0, 0), IVec3::new(-15, 100, 100)),
+            AABB::new(IVec3::ZERO, IVec3::ONE),
+        ];
+        for child_side_chunk_count in [1, 8] {
+            for aabb in aabbs {
+                let aabb = AABB::new(
+                    global_pos + (aabb.min() - global_pos) * child_side_chunk_count,
+                    global_pos + (aabb.max() - global_pos) * child_side_chunk_count,
+                );
+                let predicates: [&dyn Fn(AABB) -> bool; 3] = [
+                    &|_| true,
+                    &|aabb: AABB| aabb.min().x % 3 != 0,
+                    &|aabb: AABB| aabb.size().x > child_side_chunk_count || aabb.min().y % 2 == 0,
+                ];
+                for predicate in predicates {
+                    let indices: Vec<_> =
+                        tree_index_iterator(global_pos, aabb, child_side_chunk_count, predicate)
+                            .collect();
+                    let expected: Vec<_> = hardcoded_tree_index_iterator(
+                        global_pos,
+                        aabb,
+                        child_side_chunk_count,
+                        predicate,
+                    )
+                    .collect();
+                    assert_eq!(indices, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn debug_stats_count_the_chunks_of_each_section() {
+        let mut manager = ChunkManager::new();
+        assert_eq!(manager.debug_stats().average_section_fill(), 0.0);
+
+        let side = Section::SIDE_CHUNK_COUNT;
+        let positions = [
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(1, 0, 0),
+            ChunkPos::new(side - 1, 5, 0),
+            ChunkPos::new(-1, 0, 0),
+        ];
+        manager.insert_chunks(positions.map(Chunk::new)).unwrap();
+
+        let stats = manager.debug_stats();
+        assert_eq!(stats.section_count, 2);
+        assert_eq!(stats.chunk_count, 4);
+        assert_eq!(stats.max_section_fill, 3);
+        assert_eq!(stats.average_section_fill(), 2.0);
+    }
+
+    #[test]
+    pub fn block_edits_are_batched_by_chunk() {
+        let mut manager = ChunkManager::new();
+        manager
+            .insert_chunks([ChunkPos::new(0, 0, 0), ChunkPos::new(-1, 0, 0)].map(Chunk::new))
+            .unwrap();
+        manager.set_block(BlockPos::new(1, 0, 0), BlockState::new(1)); //not recorded yet
+
+        manager.record_block_edits();
+        manager.set_block(BlockPos::new(2, 1, 0), BlockState::new(2));
+        manager.set_block(BlockPos::new(1, 0, 0), BlockState::new(3));
+        manager.set_block(BlockPos::new(2, 1, 0), BlockState::new(4));
+        manager.set_block(BlockPos::new(-1, 15, 15), BlockState::new(5));
+        manager.set_block(BlockPos::new(100, 0, 0), BlockState::new(6)); //not loaded
+
+        let edits = manager.drain_block_edits();
+        assert_eq!(
+            edits,
+            vec![
+                (
+                    ChunkPos::new(-1, 0, 0),
+                    vec![(
+                        Chunk::block_index(BlockPos::new(15, 15, 15)),
+                        BlockState::new(5)
+                    )]
+                ),
+                (
+                    ChunkPos::new(0, 0, 0),
+                    vec![(1, BlockState::new(3)), (2 + 16, BlockState::new(4))]
+                ),
+            ]
+        );
+        assert_eq!(Chunk::block_pos(2 + 16), BlockPos::new(2, 1, 0));
+        assert!(manager.drain_block_edits().is_empty());
+    }
+
+    #[test]
+    pub fn snapshot_is_not_changed_by_the_later_edits() {
+        let mut manager = ChunkManager::new();
+        manager
+            .insert_chunks([ChunkPos::new(0, 0, 0), ChunkPos::new(-1, 2, 3)].map(Chunk::new))
+            .unwrap();
+        manager.set_block(BlockPos::new(1, 2, 3), BlockState::new(5));
+
+        let snapshot = manager.snapshot();
+        manager.set_block(BlockPos::new(1, 2, 3), BlockState::new(7));
+        manager.set_block(BlockPos::new(-5, 40, 50), BlockState::new(9));
+        manager
+            .insert_chunk(Chunk::new(ChunkPos::new(4, 4, 4)))
+            .unwrap();
+
+        assert_eq!(snapshot.chunk_count(), 2);
+        assert_eq!(
+            snapshot.get_block(BlockPos::new(1, 2, 3)),
+            Some(BlockState::new(5))
+        );
+        assert_eq!(snapshot.get_block(BlockPos::new(-5, 40, 50)), Some(AIR));
+        assert!(snapshot.get_chunk(ChunkPos::new(4, 4, 4)).is_none());
+        assert_eq!(
+            manager.get_block(BlockPos::new(1, 2, 3)),
+            Some(BlockState::new(7))
+        );
+        assert_eq!(
+            manager.get_block(BlockPos::new(-5, 40, 50)),
+            Some(BlockState::new(9))
+        );
+        assert_eq!(manager.snapshot().chunk_count(), 3);
+    }
+
+    #[test]
+    pub fn the_shared_chunks_are_copied_on_the_first_write_only() {
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0)];
+        let mut manager = ChunkManager::new();
+        manager.insert_chunks(positions.map(Chunk::new)).unwrap();
+        let snapshot = manager.snapshot();
+        let is_shared = |manager: &ChunkManager, pos| {
+            std::ptr::eq(
+                snapshot.get_chunk(pos).unwrap(),
+                manager.get_chunk(pos).unwrap(),
+            )
+        };
+
+        //reading the chunks borrowed mutably doesn't copy them
+        let aabb = AABB::new(IVec3::ZERO, IVec3::new(2, 1, 1));
+        let mut chunks = manager.get_chunk_with_predicate_mut(aabb, |_| true);
+        assert!(chunks.iter().all(|chunk| chunk.is_empty()));
+        chunks[1].set_block_at(0, 0, 0, BlockState::new(1));
+        drop(chunks);
+        assert!(is_shared(&manager, positions[0]));
+        assert!(!is_shared(&manager, positions[1]));
+
+        let chunk = manager.get_chunk_mut(positions[0]).unwrap();
+        assert!(chunk.is_empty());
+        assert!(is_shared(&manager, positions[0]));
+    }
+
+    #[test]
+    pub fn region_round_trip() {
+        let mut manager = ChunkManager::new();
+        let positions = [
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(1, 0, 0),
+            ChunkPos::new(37, 200, 511),
+            ChunkPos::new(511, 511, 511),
+        ];
+        for (i, pos) in positions.iter().enumerate() {
+            let mut chunk = Chunk::new(*pos);
+            chunk.set_block_at(i as i32, 3, 15, BlockState::new(1 + i as u16));
+            chunk.set_block_at(0, 15, 7, BlockState::new(1000));
+            manager.insert_chunk(chunk).unwrap();
+        }
+        manager
+            .insert_chunk(Chunk::new(ChunkPos::new(5, 5, 5)))
+            .unwrap(); //an empty chunk is still loaded
+        manager
+            .insert_chunk(Chunk::new(ChunkPos::new(-1, 0, 0)))
+            .unwrap(); //in another region
+
+        assert!(manager.save_region(I16Vec3::new(1, 0, 0)).is_none());
+        let bytes = manager.save_region(I16Vec3::ZERO).unwrap();
+
+        let mut loaded = ChunkManager::new();
+        assert_eq!(loaded.load_region(&bytes), Some(I16Vec3::ZERO));
+        for (i, pos) in positions.iter().enumerate() {
+            let chunk = loaded.get_chunk(*pos).unwrap();
+            assert_eq!(chunk.position(), *pos);
+            assert!(chunk.blocks_eq(manager.get_chunk(*pos).unwrap()));
+            assert_eq!(chunk.get_block_at(i as i32, 3, 15).id(), 1 + i as u16);
+            assert_eq!(chunk.get_block_at(0, 15, 7).id(), 1000);
+            assert_eq!(chunk.get_block_at(1, 1, 1), AIR);
+        }
+        assert!(loaded.get_chunk(ChunkPos::new(5, 5, 5)).unwrap().is_empty());
+        assert!(loaded.get_chunk(ChunkPos::new(2, 0, 0)).is_none());
+        assert!(loaded.get_chunk(ChunkPos::new(-1, 0, 0)).is_none());
+
+        //a truncated region is rejected as a whole
+        let mut loaded = ChunkManager::new();
+        assert!(loaded.load_region(&bytes[..bytes.len() - 1]).is_none());
+        assert!(loaded.get_chunk(ChunkPos::new(0, 0, 0)).is_none());
+    }
+
+    #[test]
+    pub fn neighbors_are_in_face_order() {
+        let mut manager = ChunkManager::new();
+        let center = ChunkPos::new(0, -1, 511); //on the border of four sections
+        manager
+            .insert_chunk(Chunk::new(center + BlockFace::North.normal()))
+            .unwrap();
+        manager
+            .insert_chunk(Chunk::new(center + BlockFace::South.normal()))
+            .unwrap();
+        manager
+            .insert_chunk(Chunk::new(center + ChunkPos::new(1, 1, 1)))
+            .unwrap();
+
+        let neighbors = manager.neighbors(center);
+        for face in BlockFace::ALL {
+            let expected = matches!(face, BlockFace::North | BlockFace::South);
+            assert_eq!(neighbors[face as usize].is_some(), expected);
+        }
+        let south = neighbors[BlockFace::South as usize].unwrap();
+        assert_eq!(south.position(), center + ChunkPos::Z);
+
+        let neighbors = manager.neighbors_including_diagonals(center);
+        assert_eq!(neighbors.iter().flatten().count(), 3);
+        assert_eq!(
+            neighbors[25].unwrap().position(),
+            center + ChunkPos::new(1, 1, 1)
+        );
+    }
+
+    #[test]
+    pub fn count_blocks_only_inside_the_aabb() {
+        let (stone, dirt, ore) = (BlockState::new(1), BlockState::new(2), BlockState::new(7));
+        let mut manager = ChunkManager::new();
+        //a line of ore along x, crossing 3 chunks, and some stone in each chunk
+        for chunk_x in -1..=1 {
+            let mut chunk = Chunk::new(ChunkPos::new(chunk_x, 0, 0));
+            for x in 0..16 {
+                chunk.set_block_at(x, 4, 4, ore);
+            }
+            chunk.set_block_at(0, 0, 0, stone);
+            manager.insert_chunk(chunk).unwrap();
+        }
+        manager
+            .insert_chunk(Chunk::new(ChunkPos::new(0, 1, 0)))
+            .unwrap();
+
+        //the whole line
+        let aabb = AABB::new(IVec3::new(-16, 0, 0), IVec3::new(32, 16, 16));
+        assert_eq!(manager.count_blocks(aabb, ore), 48);
+        assert_eq!(manager.count_blocks(aabb, stone), 3);
+        assert_eq!(manager.count_blocks(aabb, dirt), 0);
+
+        //cut on both ends, the max corner is excluded
+        let aabb = AABB::new(IVec3::new(-3, 4, 4), IVec3::new(20, 5, 5));
+        assert_eq!(manager.count_blocks(aabb, ore), 23);
+        assert_eq!(manager.count_blocks(aabb, stone), 0);
+
+        //the air of the loaded chunks only, the empty chunk above is included but not the unloaded one next to it
+        let aabb = AABB::new(IVec3::new(-2, 3, 4), IVec3::new(2, 20, 5));
+        assert_eq!(manager.count_blocks(aabb, AIR), (4 * 13 - 4) + 2 * 4);
+    }
+
+    #[test]
+    pub fn flood_fill_crosses_chunks_and_marks_them_modified() {
+        let (stone, water) = (BlockState::new(1), BlockState::new(2));
+        let (sand, lava) = (BlockState::new(3), BlockState::new(4));
+        let mut manager = ChunkManager::new();
+        //2x2x1 chunks of water with a stone floor at the bottom of each chunk and a stone wall at z = 0
+        //the floor of the upper chunks splits the water in two rooms of 2x1x1 chunks
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let mut chunk = Chunk::new(ChunkPos::new(x, y, 0));
+            for x in 0..16 {
+                for y in 0..16 {
+                    for z in 0..16 {
+                        let is_wall = y == 0 || z == 0;
+                        chunk.set_block_at(x, y, z, if is_wall { stone } else { water });
+                    }
+                }
+            }
+            manager.insert_chunk(chunk).unwrap();
+        }
+        manager.on_process_modified_chunks(|_| {});
+
+        //bounded by max_blocks
+        let start = BlockPos::new(3, 3, 3);
+        assert_eq!(manager.flood_fill(start, water, sand, 10), 10);
+        assert_eq!(
+            manager.count_blocks(AABB::new(IVec3::ZERO, IVec3::splat(32)), sand),
+            10
+        );
+        assert_eq!(manager.flood_fill(start, sand, lava, usize::MAX), 10);
+        assert_eq!(manager.flood_fill(start, lava, sand, usize::MAX), 10);
+
+        //the whole upper room, crossing the border between its two chunks, the unloaded chunks stop the fill
+        let room = 32 * 15 * 15;
+        assert_eq!(
+            manager.flood_fill(BlockPos::new(30, 30, 15), water, lava, usize::MAX),
+            room
+        );
+        assert_eq!(manager.get_block(BlockPos::new(3, 16, 3)), Some(stone));
+        assert_eq!(manager.get_block(BlockPos::new(20, 3, 3)), Some(water));
+
+        let mut modified = Vec::new();
+        manager.on_process_modified_chunks(|ids| modified = ids.to_vec());
+        assert_eq!(modified.len(), 3);
+
+        //nothing to replace
+        assert_eq!(manager.flood_fill(start, water, lava, usize::MAX), 0);
+        assert_eq!(manager.flood_fill(start, lava, lava, usize::MAX), 0);
+        assert_eq!(
+            manager.flood_fill(BlockPos::new(-5, 3, 3), AIR, lava, usize::MAX),
+            0
+        );
+    }
+
+    #[test]
+    pub fn modified_chunks_are_given_once_in_order() {
+        let mut manager = ChunkManager::new();
+        let positions = [
+            ChunkPos::new(3, 0, 0),
+            ChunkPos::new(-1, 2, 0),
+            ChunkPos::new(0, 0, 7),
+        ];
+        manager
+            .insert_chunks(positions.into_iter().map(Chunk::new))
+            .unwrap();
+        manager.on_process_modified_chunks(|_| ());
+
+        for pos in [positions[2], positions[0], positions[2], positions[2]] {
+            manager.get_chunk_mut(pos).unwrap();
+        }
+        let mut modified = Vec::new();
+        manager.on_process_modified_chunks(|ids| modified = ids.to_vec());
+        let modified: Vec<_> = modified
+            .into_iter()
+            .map(|id| manager.get_chunk_pos(id).unwrap())
+            .collect();
+        assert_eq!(modified, [positions[2], positions[0]]);
+
+        //the list is cleared with the set, a chunk can be modified again
+        manager.get_chunk_mut(positions[2]).unwrap();
+        manager.on_process_modified_chunks(|ids| assert_eq!(ids.len(), 1));
+    }
+
+    #[test]
+    pub fn the_modified_chunks_are_demoted() {
+        let mut manager = ChunkManager::new();
+        let pos = ChunkPos::new(1, -2, 3);
+        manager.insert_chunk(Chunk::new(pos)).unwrap();
+        let block_pos = |index: u16| pos * Chunk::SIZE + Chunk::block_pos(index);
+        for index in 0..200 {
+            manager.set_block(block_pos(index), BlockState::new(1 + index));
+        }
+        manager.on_process_modified_chunks(|_| ());
+        assert_eq!(
+            manager.get_chunk(pos).unwrap().format(),
+            Some(ChunkFormat::Bits8)
+        );
+
+        for index in 2..200 {
+            manager.set_block(block_pos(index), AIR);
+        }
+        manager.on_process_modified_chunks(|_| ());
+        assert_eq!(
+            manager.get_chunk(pos).unwrap().format(),
+            Some(ChunkFormat::Bits4)
+        );
+
+        //the mesher skips the chunk again once it is cleared
+        for index in 0..2 {
+            manager.set_block(block_pos(index), AIR);
+        }
+        let mut empty = false;
+        manager.drain_modified(|_, chunk| empty = chunk.is_empty());
+        assert!(empty);
+    }
+
+    #[test]
+    pub fn insert_chunks_spread_on_many_sections() {
+        let positions = [
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(-1, 0, 0),
+            ChunkPos::new(600, -3, 2),
+            ChunkPos::new(1, 0, 0),
+            ChunkPos::new(-700, 1000, -1),
+        ];
+        let mut manager = ChunkManager::new();
+        manager
+            .insert_chunks(positions.iter().enumerate().map(|(i, pos)| {
+                let mut chunk = Chunk::new(*pos);
+                chunk.set_block_at(1, 2, 3, BlockState::new(1 + i as u16));
+                chunk
+            }))
+            .unwrap();
+
+        for (i, pos) in positions.iter().enumerate() {
+            let chunk = manager.get_chunk(*pos).unwrap();
+            assert_eq!(chunk.get_block_at(1, 2, 3), BlockState::new(1 + i as u16));
+        }
+        let mut modified_count = 0;
+        manager.on_process_modified_chunks(|ids| modified_count = ids.len());
+        assert_eq!(modified_count, positions.len());
+    }
+
+    #[test]
+    pub fn ordered_traversal_does_not_depend_on_the_insertion() {
+        let mut positions = Vec::new();
+        //one chunk in each of 4 * 2 sections
+        let side = Section::SIDE_CHUNK_COUNT;
+        for x in [-side - 88, -1, 0, side + 188] {
+            for z in [-3, side + 1] {
+                positions.push(ChunkPos::new(x, 0, z));
+            }
+        }
+        let mut manager = ChunkManager::new();
+        manager
+            .insert_chunks(positions.iter().map(|pos| Chunk::new(*pos)))
+            .unwrap();
+        let mut reversed_manager = ChunkManager::new();
+        reversed_manager
+            .insert_chunks(positions.iter().rev().map(|pos| Chunk::new(*pos)))
+            .unwrap();
+
+        let aabb = AABB::from_center_half(IVec3::ZERO, IVec3::new(2 * side, 10, 2 * side));
+        let mut order = Vec::new();
+        manager.foreach_chunk_in_ordered(aabb, &mut |_, chunk| order.push(chunk.position()));
+        let mut reversed_order = Vec::new();
+        reversed_manager.foreach_chunk_with_predicate_ordered(
+            aabb,
+            |_| true,
+            |_, chunk| reversed_order.push(chunk.position()),
+        );
+        //one chunk per section, so the chunks are sorted like the sections
+        assert_eq!(order, positions);
+        assert_eq!(reversed_order, positions);
+    }
+
+    #[test]
+    pub fn a_world_wide_aabb_scans_the_loaded_sections() {
+        let side = Section::SIDE_CHUNK_COUNT;
+        let positions = [
+            ChunkPos::new(-3 * side, 5, 0),
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(side, 0, -1),
+            ChunkPos::new(ChunkManager::MAX_CHUNK_COORD, 0, 0),
+        ];
+        let mut manager = ChunkManager::new();
+        manager
+            .insert_chunks(positions.iter().rev().map(|pos| Chunk::new(*pos)))
+            .unwrap();
+
+        //far more cells than sections, the map is scanned
+        let world = AABB::new(
+            IVec3::splat(ChunkManager::MIN_CHUNK_COORD),
+            IVec3::splat(ChunkManager::MAX_CHUNK_COORD + 1),
+        );
+        let mut order = Vec::new();
+        manager.foreach_chunk_in_ordered(world, &mut |_, chunk| order.push(chunk.position()));
+        assert_eq!(order, positions);
+        let mut found = Vec::new();
+        manager.foreach_chunk_in(world, &mut |_, chunk| found.push(chunk.position()));
+        found.sort_by_key(|pos| pos.to_array());
+        assert_eq!(found, positions);
+        let order = manager
+            .get_chunk_with_predicate_mut(world, |_| true)
+            .iter()
+            .map(|chunk| chunk.position())
+            .collect::<Vec<_>>();
+        assert_eq!(order, positions);
+
+        //fewer cells than sections, the cells are looked up, with the same result
+        let small = AABB::new(IVec3::new(-1, -1, -1), IVec3::new(side + 1, 1, 1));
+        let mut order = Vec::new();
+        manager.foreach_chunk_in_ordered(small, &mut |_, chunk| order.push(chunk.position()));
+        assert_eq!(order, positions[1..3]);
+        let count = manager.get_chunk_with_predicate_mut(small, |_| true).len();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    pub fn positions_out_of_the_world_are_rejected() {
+        let min = ChunkManager::MIN_CHUNK_COORD;
+        let max = ChunkManager::MAX_CHUNK_COORD;
+        let mut manager = ChunkManager::new();
+        for pos in [ChunkPos::new(min, 0, max), ChunkPos::new(max, min, 0)] {
+            let mut chunk = Chunk::new(pos);
+            chunk.set_block_at(0, 0, 0, BlockState::new(1));
+            manager.insert_chunk(chunk).unwrap();
+        }
+
+        //just past the limits the section position would wrap and alias the chunks above
+        for pos in [
+            ChunkPos::new(max + 1, 0, max),
+            ChunkPos::new(max, min - 1, 0),
+        ] {
+            assert_eq!(
+                manager.insert_chunk(Chunk::new(pos)),
+                Err(ChunkManagerError::OutOfRange(pos))
+            );
+        }
+        let wrapped = ChunkPos::new(max + 1, min, 0);
+        assert!(manager.get_chunk(wrapped).is_none());
+        assert!(manager.get_chunk_mut(wrapped).is_none());
+        assert!(manager.get_chunk(ChunkPos::new(max, min, 0)).is_some());
+    }
+
+    #[test]
+    pub fn queries_past_the_world_limits_find_the_chunks_inside() {
+        let min = ChunkManager::MIN_CHUNK_COORD;
+        let max = ChunkManager::MAX_CHUNK_COORD;
+        let mut manager = ChunkManager::new();
+        let corner = ChunkPos::new(max, min, 0);
+        manager.insert_chunk(Chunk::new(corner)).unwrap();
+
+        //the AABB goes past the world on x and y, the cells out of it would alias other sections
+        let aabb = AABB::new(corner - IVec3::ONE, corner + IVec3::splat(2));
+        let found = manager.get_chunks_in(aabb);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].position(), corner);
+        assert_eq!(
+            manager.get_chunk_with_predicate_mut(aabb, |_| true).len(),
+            1
+        );
+    }
+
+    #[test]
+    pub fn get_chunk_or_insert_with_marks_only_the_created_chunks() {
+        let stone = BlockState::new(1);
+        let mut manager = ChunkManager::new();
+        let existing = ChunkPos::new(3, -4, 5);
+        manager.insert_chunk(Chunk::new(existing)).unwrap();
+        manager.set_block(existing * CHUNK_SIZE, stone);
+        manager.on_process_modified_chunks(|_| ());
+
+        let chunk = manager
+            .get_chunk_or_insert_with(existing, || panic!("the chunk is already loaded"))
+            .unwrap();
+        assert_eq!(chunk.get_block_at(0, 0, 0), stone);
+        manager.on_process_modified_chunks(|modified| assert!(modified.is_empty()));
+
+        //in a section that doesn't exist yet
+        let created = ChunkPos::new(-1000, 7, 2);
+        let mut chunk = manager
+            .get_chunk_or_insert_with(created, || Chunk::new(created))
+            .unwrap();
+        chunk.set_block_at(1, 2, 3, stone);
+        assert_eq!(
+            manager.get_block(created * CHUNK_SIZE + IVec3::new(1, 2, 3)),
+            Some(stone)
+        );
+        let mut drained = Vec::new();
+        manager.drain_modified(|pos, _| drained.push(pos));
+        assert_eq!(drained, vec![created]);
+
+        let out_of_world = ChunkPos::splat(ChunkManager::MAX_CHUNK_COORD + 1);
+        assert!(manager
+            .get_chunk_or_insert_with(out_of_world, || panic!("the chunk can't be stored"))
+            .is_err());
+    }
+
+    #[test]
+    pub fn a_border_edit_marks_the_neighbor_as_modified() {
+        let stone = BlockState::new(1);
+        let mut manager = ChunkManager::new();
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0)];
+        manager.insert_chunks(positions.map(Chunk::new)).unwrap();
+        manager.on_process_modified_chunks(|_| ());
+
+        let modified = |manager: &mut ChunkManager| {
+            let mut drained = Vec::new();
+            manager.drain_modified(|pos, _| drained.push(pos));
+            drained
+        };
+
+        manager.edit_block(BlockPos::new(15, 3, 4), stone).unwrap();
+        assert_eq!(modified(&mut manager), positions.to_vec());
+        assert_eq!(manager.get_block(BlockPos::new(15, 3, 4)), Some(stone));
+
+        //on the border of (0, 0, 0) too, but the neighbors at y = -1 aren't loaded
+        manager.edit_block(BlockPos::new(16, 0, 4), AIR).unwrap();
+        assert_eq!(modified(&mut manager), positions.to_vec());
+
+        manager.edit_block(BlockPos::new(8, 8, 8), stone).unwrap();
+        assert_eq!(modified(&mut manager), vec![positions[0]]);
+
+        //the chunk is created
+        manager.edit_block(BlockPos::new(-1, 8, 8), stone).unwrap();
+        assert_eq!(
+            modified(&mut manager),
+            vec![ChunkPos::new(-1, 0, 0), positions[0]]
+        );
+        assert_eq!(manager.get_block(BlockPos::new(-1, 8, 8)), Some(stone));
+    }
+
+    #[test]
+    pub fn drain_modified_gives_each_modified_chunk_once() {
+        let stone = BlockState::new(1);
+        let mut manager = ChunkManager::new();
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(-1, 2, 0)];
+        manager.insert_chunks(positions.map(Chunk::new)).unwrap();
+        let mut drained = Vec::new();
+        manager.drain_modified(|pos, chunk| {
+            assert_eq!(chunk.position(), pos);
+            drained.push(pos);
+        });
+        assert_eq!(drained, vec![positions[1], positions[0]]);
+
+        //nothing is modified anymore
+        manager.drain_modified(|_, _| panic!("the list should be empty"));
+
+        //modified twice, but given once with its last content
+        manager.set_block(BlockPos::new(1, 2, 3), stone);
+        manager.set_block(BlockPos::new(4, 5, 6), stone);
+        let mut drained = Vec::new();
+        manager.drain_modified(|pos, chunk| drained.push((pos, chunk.get_block_at(4, 5, 6))));
+        assert_eq!(drained, vec![(positions[0], stone)]);
+    }
+
+    #[test]
+    pub fn chunks_in_view_are_ahead_of_the_camera() {
+        let mut manager = ChunkManager::new();
+        let ahead = [ChunkPos::new(0, 0, 3), ChunkPos::new(1, 0, 5)];
+        let behind = [ChunkPos::new(0, 0, -4), ChunkPos::new(0, 0, 20)]; //the second one is too far
+        manager
+            .insert_chunks(ahead.into_iter().chain(behind).map(Chunk::new))
+            .unwrap();
+
+        //yaw == 0 looks at z+
+        let position = EntityPos::new(IVec3::ZERO, Vec3::splat(8.0));
+        let frustum = compute_frustum(0.0, 0.0, position, FRAC_PI_2, 16.0 / 9.0, 8);
+        let mut found = Vec::new();
+        manager.chunks_in_view(&frustum, |_, chunk| found.push(chunk.position()));
+        found.sort_by_key(|pos| pos.to_array());
+        assert_eq!(found, ahead.to_vec());
+    }
+
+    #[test]
+    pub fn evict_beyond_keeps_the_chunks_in_the_radius() {
+        let mut manager = ChunkManager::new();
+        let center = ChunkPos::new(2, 0, -3);
+        let kept = [center, ChunkPos::new(6, 0, -3), ChunkPos::new(0, 2, -1)];
+        let far = Section::SIDE_CHUNK_COUNT * 3;
+        let evicted = [
+            ChunkPos::new(7, 0, -3),
+            ChunkPos::new(2, 4, 0), //alone in its section, like the next ones
+            ChunkPos::new(far, 0, 0),
+            ChunkPos::new(-far, far, 5),
+        ];
+        manager
+            .insert_chunks(kept.into_iter().chain(evicted).map(Chunk::new))
+            .unwrap();
+        manager.set_block(BlockPos::new(7 * 16, 0, -3 * 16), BlockState::new(3));
+        assert_eq!(manager.debug_stats().section_count, 4);
+
+        let mut saved = Vec::new();
+        let stats = manager.evict_beyond(center, 4, |chunk| saved.push(chunk));
+        assert_eq!(stats.chunk_count, 4);
+        assert_eq!(stats.section_count, 3);
+        assert_eq!(manager.debug_stats().chunk_count, 3);
+        assert_eq!(manager.debug_stats().section_count, 1);
+        for pos in kept {
+            assert!(manager.get_chunk(pos).is_some());
+        }
+        for pos in evicted {
+            assert!(manager.get_chunk(pos).is_none());
+        }
+
+        //the chunks are given with their blocks
+        saved.sort_by_key(|chunk| chunk.position().to_array());
+        let saved_positions: Vec<_> = saved.iter().map(|chunk| chunk.position()).collect();
+        let mut expected = evicted.to_vec();
+        expected.sort_by_key(|pos| pos.to_array());
+        assert_eq!(saved_positions, expected);
+        let edited = saved.iter().find(|chunk| chunk.position() == evicted[0]);
+        assert_eq!(edited.unwrap().get_block_at(0, 0, 0), BlockState::new(3));
+
+        //nothing else to evict, and a removed chunk can be inserted again
+        assert_eq!(manager.evict_beyond(center, 4, |_| ()), Default::default());
+        manager.insert_chunk(Chunk::new(evicted[3])).unwrap();
+        assert!(manager.get_chunk(evicted[3]).is_some());
+    }
+
+    #[test]
+    pub fn evict_beyond_measures_the_far_apart_chunks() {
+        let mut manager = ChunkManager::new();
+        let center = ChunkPos::splat(ChunkManager::MIN_CHUNK_COORD);
+        let far = ChunkPos::splat(ChunkManager::MAX_CHUNK_COORD);
+        manager
+            .insert_chunks([center, far].into_iter().map(Chunk::new))
+            .unwrap();
+
+        let radius = ChunkManager::MAX_CHUNK_COORD;
+        let stats = manager.evict_beyond(center, radius, |_| ());
+        assert_eq!(stats.chunk_count, 1);
+        assert!(manager.get_chunk(center).is_some());
+        assert!(manager.get_chunk(far).is_none());
+    }
+
+    #[test]
+    pub fn removing_all_the_chunks_of_a_section_removes_it() {
+        let mut manager = ChunkManager::new();
+        let positions: Vec<_> = (0..512)
+            .map(|i| ChunkPos::new(i % 8, i / 8 % 8, i / 64))
+            .collect();
+        manager
+            .insert_chunks(positions.iter().map(|pos| Chunk::new(*pos)))
+            .unwrap();
+        manager.on_process_modified_chunks(|_| ());
+        assert_eq!(manager.section_map.len(), 1);
+
+        for (i, pos) in positions.iter().enumerate() {
+            assert!(manager.remove_chunk(*pos).is_some());
+            assert_eq!(manager.section_map.is_empty(), i == positions.len() - 1);
+        }
+        assert!(manager.remove_chunk(positions[0]).is_none());
+        assert_eq!(manager.debug_stats(), Default::default());
+
+        //the freed ids are given as modified, without a position
+        let mut modified = Vec::new();
+        manager.on_process_modified_chunks(|ids| modified = ids.to_vec());
+        assert_eq!(modified.len(), positions.len());
+        assert!(modified
+            .iter()
+            .all(|id| manager.get_chunk_pos(*id).is_none()));
+    }
+
+    #[test]
+    pub fn a_replaced_chunk_frees_its_id() {
+        let mut manager = ChunkManager::new();
+        let pos = ChunkPos::new(3, -2, 9);
+        manager.insert_chunk(Chunk::new(pos)).unwrap();
+        let mut old_id = None;
+        manager.on_process_modified_chunks(|ids| old_id = ids.first().copied());
+        let old_id = old_id.unwrap();
+
+        let mut chunk = Chunk::new(pos);
+        chunk.set_block_at(1, 2, 3, BlockState::new(4));
+        manager.insert_chunk(chunk).unwrap();
+        assert_eq!(manager.get_chunk_pos(old_id), None);
+        assert_eq!(manager.chunk_positions.len(), 1);
+        let chunk = manager.get_chunk(pos).unwrap();
+        assert_eq!(chunk.get_block_at(1, 2, 3), BlockState::new(4));
+
+        //the old id is given as modified without a position, so the caches drop it, the bulk insertion too
+        let mut modified = Vec::new();
+        manager.on_process_modified_chunks(|ids| modified = ids.to_vec());
+        assert_eq!(modified.len(), 2);
+        assert!(modified.contains(&old_id));
+        manager.insert_chunks([Chunk::new(pos)]).unwrap();
+        assert_eq!(manager.chunk_positions.len(), 1);
+    }
+
+    #[test]
+    pub fn the_chunks_written_through_the_predicate_are_modified() {
+        let mut manager = ChunkManager::new();
+        let positions = [
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(1, 0, 0),
+            ChunkPos::new(-1, 3, 0),
+        ];
+        manager
+            .insert_chunks(positions.into_iter().map(Chunk::new))
+            .unwrap();
+        manager.on_process_modified_chunks(|_| ());
+
+        let aabb = AABB::new(ChunkPos::new(-2, -2, -2), ChunkPos::splat(4));
+        let mut chunks = manager.get_chunk_with_predicate_mut(aabb, |_| true);
+        assert_eq!(chunks.len(), 3);
+        let mut written = None;
+        for chunk in chunks.iter_mut() {
+            if chunk.position() == positions[1] {
+                chunk.set_block_at(1, 2, 3, BlockState::new(4));
+                written = Some(chunk.id());
+            }
+        }
+        let mut modified = Vec::new();
+        manager.on_process_modified_chunks(|ids| modified = ids.to_vec());
+        assert_eq!(modified, [written.unwrap()]);
+
+        //the peek doesn't mark the chunk
+        let chunk = manager.peek_chunk_mut(positions[2]).unwrap();
+        chunk.set_block_at(0, 0, 0, BlockState::new(1));
+        assert!(manager.peek_chunk_mut(ChunkPos::new(5, 5, 5)).is_none());
+        manager.on_process_modified_chunks(|ids| assert!(ids.is_empty()));
+        assert_eq!(
+            manager.get_block(BlockPos::new(-16, 48, 0)),
+            Some(BlockState::new(1))
+        );
+    }
+
+    #[test]
+    pub fn chunks_within_includes_the_boundary() {
+        let mut manager = ChunkManager::new();
+        let center = ChunkPos::new(-1, 3, 510);
+        let radius = 5;
+        //on both sides of a section border
+        let inside = [
+            center,
+            center + ChunkPos::new(radius, 0, 0),
+            center - ChunkPos::new(0, 0, radius),
+            center + ChunkPos::new(3, 4, 0),
+        ];
+        let outside = [
+            center + ChunkPos::new(radius + 1, 0, 0),
+            center + ChunkPos::new(0, 0, radius + 1),
+            center + ChunkPos::new(4, 4, 0),
+            center + ChunkPos::new(radius, radius, radius),
+        ];
+        manager
+            .insert_chunks(
+                inside
+                    .iter()
+                    .chain(outside.iter())
+                    .map(|pos| Chunk::new(*pos)),
+            )
+            .unwrap();
+
+        let mut found = Vec::new();
+        manager.chunks_within(center, radius, |_, chunk| found.push(chunk.position()));
+        found.sort_by_key(|pos| pos.to_array());
+        let mut expected = inside.to_vec();
+        expected.sort_by_key(|pos| pos.to_array());
+        assert_eq!(found, expected);
+
+        let mut found = Vec::new();
+        manager.chunks_within(center, 0, |_, chunk| found.push(chunk.position()));
+        assert_eq!(found, vec![center]);
     }
 }
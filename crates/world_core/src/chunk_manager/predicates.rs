@@ -0,0 +1,100 @@
+use math::aabb::AABB;
+use math::frustum::CameraFrustum;
+use math::positions::ChunkPos;
+use math::{IVec2, IVec3};
+use std::ops::Range;
+
+///the AABBs with a chunk at most `radius` chunks away from the center, measured between the chunk positions
+pub fn sphere(center: ChunkPos, radius: i32) -> impl Fn(AABB) -> bool + Copy {
+    //in i64 like in ChunkManager::evict_beyond, the squared distance between far apart chunks doesn't fit in an i32
+    let radius_squared = radius as i64 * radius as i64;
+    move |aabb: AABB| {
+        //the chunk of the AABB the closest to the center
+        let closest = center.clamp(aabb.min(), aabb.max() - IVec3::ONE);
+        (closest - center).as_i64vec3().length_squared() <= radius_squared
+    }
+}
+
+///the AABBs with a chunk in the vertical cylinder, at most `radius` chunks away from the center on x and z, and in the y range
+pub fn cylinder(
+    center_xz: IVec2,
+    radius: i32,
+    y_range: Range<i32>,
+) -> impl Fn(AABB) -> bool + Copy {
+    let radius_squared = radius as i64 * radius as i64;
+    let (y_start, y_end) = (y_range.start, y_range.end);
+    move |aabb: AABB| {
+        if aabb.min().y >= y_end || aabb.max().y <= y_start {
+            return false;
+        }
+        let min = IVec2::new(aabb.min().x, aabb.min().z);
+        let max = IVec2::new(aabb.max().x, aabb.max().z);
+        let closest = center_xz.clamp(min, max - IVec2::ONE);
+        (closest - center_xz).as_i64vec2().length_squared() <= radius_squared
+    }
+}
+
+///the AABBs in the frustum of a camera, see [`CameraFrustum::contains`]
+pub fn frustum(frustum: &CameraFrustum) -> impl Fn(AABB) -> bool + Copy + '_ {
+    |aabb: AABB| frustum.contains(&aabb)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chunk_manager::predicates;
+    use crate::ChunkManager;
+    use math::aabb::AABB;
+    use math::frustum::compute_frustum;
+    use math::positions::{ChunkPos, EntityPos};
+    use math::{IVec2, IVec3, Vec3};
+    use std::f32::consts::FRAC_PI_2;
+
+    fn chunk_at(x: i32, y: i32, z: i32) -> AABB {
+        AABB::new(IVec3::new(x, y, z), IVec3::new(x + 1, y + 1, z + 1))
+    }
+
+    #[test]
+    pub fn sphere_includes_the_boundary() {
+        let sphere = predicates::sphere(ChunkPos::new(1, 2, 3), 4);
+        assert!(sphere(chunk_at(5, 2, 3)));
+        assert!(!sphere(chunk_at(6, 2, 3)));
+        //3² + 3² + 3² = 27 > 16
+        assert!(!sphere(chunk_at(4, 5, 6)));
+        //a node overlapping the sphere is accepted even if its corners are out of it
+        assert!(sphere(AABB::new(IVec3::splat(-10), IVec3::splat(10))));
+        assert!(!sphere(AABB::new(IVec3::splat(8), IVec3::splat(16))));
+
+        //the squared distance between the corners of the world doesn't fit in an i32
+        let (min, max) = (ChunkManager::MIN_CHUNK_COORD, ChunkManager::MAX_CHUNK_COORD);
+        let sphere = predicates::sphere(ChunkPos::splat(min), max);
+        assert!(!sphere(chunk_at(max, max, 0)));
+    }
+
+    #[test]
+    pub fn cylinder_is_limited_in_height() {
+        let cylinder = predicates::cylinder(IVec2::new(0, 0), 3, -2..4);
+        assert!(cylinder(chunk_at(3, -2, 0)));
+        assert!(cylinder(chunk_at(2, 3, -2)));
+        assert!(!cylinder(chunk_at(3, 0, 1)));
+        assert!(!cylinder(chunk_at(0, 4, 0)));
+        assert!(!cylinder(chunk_at(0, -3, 0)));
+        assert!(cylinder(AABB::new(
+            IVec3::new(2, -100, 2),
+            IVec3::new(50, 100, 50)
+        )));
+        let (min, max) = (ChunkManager::MIN_CHUNK_COORD, ChunkManager::MAX_CHUNK_COORD);
+        let cylinder = predicates::cylinder(IVec2::splat(min), max, -2..4);
+        assert!(!cylinder(chunk_at(max, 0, max)));
+    }
+
+    #[test]
+    pub fn frustum_matches_contains() {
+        let position = EntityPos::new(IVec3::ZERO, Vec3::splat(8.0));
+        let frustum = compute_frustum(0.0, 0.0, position, FRAC_PI_2, 16.0 / 9.0, 8);
+        let in_view = predicates::frustum(&frustum);
+        for aabb in [chunk_at(0, 0, 3), chunk_at(0, 0, -4), chunk_at(7, 7, 1)] {
+            assert_eq!(in_view(aabb), frustum.contains(&aabb));
+        }
+        assert!(in_view(chunk_at(0, 0, 3)));
+    }
+}
@@ -1,7 +1,10 @@
 #![doc = include_str!("../README.md")]
+pub mod block_registry;
 pub mod block_state;
 pub mod chunk;
 pub mod chunk_manager;
+pub mod physics;
+pub mod raycast;
 
 pub use chunk::*;
 pub use chunk_manager::*;
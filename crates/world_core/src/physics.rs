@@ -0,0 +1,148 @@
+use crate::block_registry::BlockRegistry;
+use crate::ChunkManager;
+use math::aabb::FloatAABB;
+use math::positions::BlockPos;
+use math::{DVec3, IVec3};
+
+///the gap tolerated between a box and a block, to not get stuck because of the rounding errors
+const EPSILON: f64 = 1e-7;
+
+///move the box by the delta, one axis after the other (y first), and stop it against the first solid block on each axis
+///return the delta that can actually be applied, the unloaded chunks are considered empty
+///a block already intersecting the box doesn't stop it, so an entity stuck in a block can get out
+pub fn resolve_movement(
+    chunk_manager: &ChunkManager,
+    block_registry: &BlockRegistry,
+    aabb: FloatAABB,
+    delta: DVec3,
+) -> DVec3 {
+    let mut aabb = aabb;
+    let mut resolved = DVec3::ZERO;
+    for axis in [1, 0, 2] {
+        let mut axis_delta = delta[axis];
+        if axis_delta == 0.0 {
+            continue;
+        }
+
+        //the blocks the box goes through on this axis
+        let mut swept = aabb;
+        if axis_delta > 0.0 {
+            swept.max[axis] += axis_delta;
+        } else {
+            swept.min[axis] += axis_delta;
+        }
+        let min = swept.min.floor().as_ivec3();
+        let max = swept.max.ceil().as_ivec3() - IVec3::ONE;
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let block_pos = BlockPos::new(x, y, z);
+                    let is_solid = chunk_manager
+                        .get_block(block_pos)
+                        .is_some_and(|state| block_registry.is_solid(state));
+                    if !is_solid {
+                        continue;
+                    }
+
+                    let block_min = block_pos[axis] as f64;
+                    let block_max = block_min + 1.0;
+                    if axis_delta > 0.0 && block_min >= aabb.max[axis] - EPSILON {
+                        axis_delta = axis_delta.min(block_min - aabb.max[axis]).max(0.0);
+                    } else if axis_delta < 0.0 && block_max <= aabb.min[axis] + EPSILON {
+                        axis_delta = axis_delta.max(block_max - aabb.min[axis]).min(0.0);
+                    }
+                }
+            }
+        }
+
+        let mut offset = DVec3::ZERO;
+        offset[axis] = axis_delta;
+        aabb = aabb.translated(offset);
+        resolved[axis] = axis_delta;
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block_registry::BlockRegistry;
+    use crate::physics::resolve_movement;
+    use crate::{Chunk, ChunkManager};
+    use math::aabb::FloatAABB;
+    use math::positions::ChunkPos;
+    use math::DVec3;
+
+    ///a player sized box with its feet at the given position
+    fn player_at(x: f64, y: f64, z: f64) -> FloatAABB {
+        FloatAABB::new(
+            DVec3::new(x - 0.3, y, z - 0.3),
+            DVec3::new(x + 0.3, y + 1.8, z + 0.3),
+        )
+    }
+
+    #[test]
+    pub fn fall_onto_a_flat_floor() {
+        let registry = BlockRegistry::default();
+        let stone = registry.get_by_name("stone").unwrap();
+
+        //a floor at y = 0 spread on 4 chunks, the top of the floor is at y = 1
+        let mut manager = ChunkManager::new();
+        for (chunk_x, chunk_z) in [(-1, -1), (-1, 0), (0, -1), (0, 0)] {
+            let mut chunk = Chunk::new(ChunkPos::new(chunk_x, 0, chunk_z));
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block_at(x, 0, z, stone);
+                }
+            }
+            manager.insert_chunk(chunk).unwrap();
+        }
+
+        //on a chunk corner, the box overlaps the 4 chunks
+        let delta = DVec3::new(0.0, -10.0, 0.0);
+        let resolved = resolve_movement(&manager, &registry, player_at(0.0, 5.0, 0.0), delta);
+        assert_eq!(resolved, DVec3::new(0.0, -4.0, 0.0));
+
+        //a fall shorter than the distance to the floor isn't stopped
+        let delta = DVec3::new(0.5, -2.0, -0.5);
+        let resolved = resolve_movement(&manager, &registry, player_at(0.0, 5.0, 0.0), delta);
+        assert_eq!(resolved, delta);
+
+        //standing on the floor, the box can still walk but not fall
+        let delta = DVec3::new(3.0, -1.0, 0.0);
+        let resolved = resolve_movement(&manager, &registry, player_at(0.0, 1.0, 0.0), delta);
+        assert_eq!(resolved, DVec3::new(3.0, 0.0, 0.0));
+
+        //the unloaded chunks are empty, nothing stops the fall
+        let delta = DVec3::new(0.0, -10.0, 0.0);
+        let resolved = resolve_movement(&manager, &registry, player_at(40.0, 5.0, 0.0), delta);
+        assert_eq!(resolved, delta);
+    }
+
+    #[test]
+    pub fn walls_stop_each_axis_independently() {
+        let registry = BlockRegistry::default();
+        let stone = registry.get_by_name("stone").unwrap();
+
+        //a wall at x = 5, from y = 0 to y = 3
+        let mut chunk = Chunk::new(ChunkPos::ZERO);
+        for y in 0..4 {
+            for z in 0..16 {
+                chunk.set_block_at(5, y, z, stone);
+            }
+        }
+        let mut manager = ChunkManager::new();
+        manager.insert_chunk(chunk).unwrap();
+
+        //moving diagonally against the wall slides along it
+        let delta = DVec3::new(4.0, 0.0, 2.0);
+        let resolved = resolve_movement(&manager, &registry, player_at(2.0, 1.0, 8.0), delta);
+        assert!((resolved.x - 2.7).abs() < 1e-9);
+        assert_eq!(resolved.z, 2.0);
+
+        //moving away from the wall isn't stopped
+        let delta = DVec3::new(-4.0, 0.0, 0.0);
+        let resolved = resolve_movement(&manager, &registry, player_at(4.7, 1.0, 8.0), delta);
+        assert_eq!(resolved, delta);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::block_registry::BlockFace;
+use crate::block_state::{BlockState, AIR};
+use crate::ChunkManager;
+use math::consts::CHUNK_SIZE;
+use math::positions::{BlockPos, EntityPos};
+use math::{IVec3, Vec3};
+
+///the first block hit by a ray
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub block_pos: BlockPos,
+    pub state: BlockState,
+    ///the face the ray entered the block through, None if the ray started inside the block
+    pub face: Option<BlockFace>,
+    ///the distance between the origin and the entry point, in blocks
+    pub distance: f32,
+}
+
+impl ChunkManager {
+    ///walk the blocks crossed by the ray one by one and return the first non-air block, the unloaded chunks are considered empty
+    ///the direction doesn't need to be normalized
+    pub fn raycast(
+        &self,
+        origin: EntityPos,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        let direction = direction.try_normalize()?;
+
+        //the computations are done relative to the origin chunk to keep the float precision
+        let chunk_origin = origin.chunk_pos * CHUNK_SIZE;
+        let start = origin.relative_pos;
+        let mut cell = start.floor().as_ivec3();
+
+        let step = direction.signum().as_ivec3();
+        let next_boundary = cell.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+        //the distance along the ray to cross the next boundary on each axis, and to cross a whole block
+        let mut t_max = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            (next_boundary - start) / direction,
+        );
+        let t_delta = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            direction.recip().abs(),
+        );
+
+        let mut face = None;
+        let mut distance = 0.0;
+        while distance <= max_distance {
+            let block_pos = chunk_origin + cell;
+            let state = self.get_block(block_pos).unwrap_or(AIR);
+            if !state.is_air() {
+                return Some(RaycastHit {
+                    block_pos,
+                    state,
+                    face,
+                    distance,
+                });
+            }
+
+            //move to the closest boundary, the block is entered through the opposite face
+            if t_max.x <= t_max.y && t_max.x <= t_max.z {
+                distance = t_max.x;
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+                face = Some(if step.x > 0 {
+                    BlockFace::West
+                } else {
+                    BlockFace::East
+                });
+            } else if t_max.y <= t_max.z {
+                distance = t_max.y;
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+                face = Some(if step.y > 0 {
+                    BlockFace::Bottom
+                } else {
+                    BlockFace::Top
+                });
+            } else {
+                distance = t_max.z;
+                cell.z += step.z;
+                t_max.z += t_delta.z;
+                face = Some(if step.z > 0 {
+                    BlockFace::North
+                } else {
+                    BlockFace::South
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block_registry::BlockFace;
+    use crate::block_state::BlockState;
+    use crate::raycast::RaycastHit;
+    use crate::{Chunk, ChunkManager};
+    use math::positions::{BlockPos, ChunkPos, EntityPos};
+    use math::Vec3;
+
+    #[test]
+    pub fn ray_stops_on_the_first_block() {
+        let mut manager = ChunkManager::new();
+        let mut chunk = Chunk::new(ChunkPos::new(1, 0, 0));
+        chunk.set_block_at(4, 2, 3, BlockState::new(5)); //the block (20, 2, 3)
+        chunk.set_block_at(8, 2, 3, BlockState::new(6));
+        manager.insert_chunk(chunk).unwrap();
+        manager
+            .insert_chunk(Chunk::new(ChunkPos::new(0, 0, 0)))
+            .unwrap();
+
+        let origin = EntityPos::new(ChunkPos::ZERO, Vec3::new(10.5, 2.5, 3.5));
+        let hit = manager.raycast(origin, Vec3::X, 20.0);
+        assert_eq!(
+            hit,
+            Some(RaycastHit {
+                block_pos: BlockPos::new(20, 2, 3),
+                state: BlockState::new(5),
+                face: Some(BlockFace::West),
+                distance: 9.5,
+            })
+        );
+
+        //the ray is too short, or goes the other way
+        assert!(manager.raycast(origin, Vec3::X, 9.0).is_none());
+        assert!(manager.raycast(origin, Vec3::NEG_X, 100.0).is_none());
+
+        //from above, across the chunk border and an unloaded chunk
+        let origin = EntityPos::new(ChunkPos::new(1, 2, 0), Vec3::new(4.5, 1.0, 3.5));
+        let hit = manager.raycast(origin, Vec3::NEG_Y, 100.0).unwrap();
+        assert_eq!(hit.block_pos, BlockPos::new(20, 2, 3));
+        assert_eq!(hit.face, Some(BlockFace::Top));
+
+        //a diagonal ray starting inside the block
+        let origin = EntityPos::new(ChunkPos::ZERO, Vec3::new(24.2, 2.9, 3.1));
+        let hit = manager.raycast(origin, Vec3::new(1.0, 1.0, 1.0), 10.0);
+        assert_eq!(
+            hit.map(|hit| (hit.state, hit.face)),
+            Some((BlockState::new(6), None))
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use crate::config::ServerConfig;
 use crate::networking;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use crate::networking::NetEvent;
+use crate::world::World;
 use std::sync::{atomic, Arc};
 use std::time::Instant;
 use std::{thread, time::Duration};
@@ -7,20 +9,33 @@ use std::{thread, time::Duration};
 pub struct App {
     should_exit: Arc<atomic::AtomicBool>,
     network_manager: networking::ServerNetworkHandler,
+    world: World,
+    tick_rate: u32,
 }
 
 impl App {
-    pub fn new() -> anyhow::Result<Self> {
-        // todo: do not hardcode the config
-        let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 5000);
-        let network_manager = networking::ServerNetworkHandler::new(socket_addr)?;
+    pub fn new(config: ServerConfig) -> anyhow::Result<Self> {
+        let network_manager = networking::ServerNetworkHandler::new(&config)?;
+        let world = World::new(&config)?;
 
         Ok(Self {
             should_exit: Arc::new(atomic::AtomicBool::new(false)),
             network_manager,
+            world,
+            tick_rate: config.tick_rate,
         })
     }
 
+    ///the number of ticks per second
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    ///the duration of a tick when the server isn't lagging
+    fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate
+    }
+
     fn should_exit(&self) -> bool {
         !self.should_exit.load(atomic::Ordering::SeqCst)
     }
@@ -42,24 +57,32 @@ impl App {
     }
 
     fn running(&mut self) -> anyhow::Result<()> {
-        println!("server running");
+        println!("server running at {} ticks per second", self.tick_rate());
+        let tick_duration = self.tick_duration();
         let mut last_updated = Instant::now();
+        //the time the next tick should start at, the ticks are scheduled from it so the sleep inaccuracies don't add up
+        let mut next_tick = last_updated;
         //main loop
         while self.should_exit() {
             let now = Instant::now();
             let delta_time = now - last_updated;
             last_updated = now;
 
-            //delta_time should be 50ms, if it's not, we're lagging
+            //delta_time should be the tick duration, if it's not, we're lagging
             self.tick(delta_time)?;
 
-            //sleep to complete the 50ms
-            let time_took = now.elapsed();
-            if time_took > Duration::from_millis(50) {
-                println!("server is lagging");
+            //sleep until the next tick
+            next_tick += tick_duration;
+            let now = Instant::now();
+            if now < next_tick {
+                thread::sleep(next_tick - now);
             } else {
-                let time_to_sleep = Duration::from_millis(50) - time_took;
-                thread::sleep(time_to_sleep);
+                //the late ticks aren't caught up, the schedule restarts from now so the lag doesn't pile up
+                println!(
+                    "server is lagging, the tick took {} ms too long",
+                    (now - next_tick).as_millis()
+                );
+                next_tick = now;
             }
         }
 
@@ -74,6 +97,21 @@ impl App {
 
     pub fn tick(&mut self, delta_time: Duration) -> anyhow::Result<()> {
         self.network_manager.tick(delta_time)?;
+        for event in self.network_manager.poll_events() {
+            match event {
+                //todo: spawn and remove the player entities when there will be entities
+                NetEvent::ClientConnected { client_id } => {
+                    println!("Client {client_id} connected")
+                }
+                NetEvent::ClientDisconnected { client_id, reason } => {
+                    println!("Client {client_id} disconnected: {reason}")
+                }
+            }
+        }
+        let changes = self.world.tick(&self.network_manager.player_chunks());
+        self.network_manager
+            .send_world_changes(&self.world, changes);
+        self.network_manager.stream_chunks(&self.world);
         Ok(())
     }
 }
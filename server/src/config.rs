@@ -0,0 +1,121 @@
+use networking::packets::PROTOCOL_ID;
+use networking::socket::DEFAULT_SOCKET_BUFFER_SIZE;
+use renet::transport::NETCODE_KEY_BYTES;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use utils::config_file::ConfigFile;
+
+///how the clients are authenticated by the transport layer
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthenticationMode {
+    ///anyone knowing the protocol id can connect, only for local testing
+    Unsecure,
+    ///the clients must present a connect token signed with this private key
+    Secure {
+        private_key: [u8; NETCODE_KEY_BYTES],
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    ///the address the server socket is bound to
+    pub bind_address: SocketAddr,
+    ///the address the clients use to reach the server, can differ from the bind address behind a NAT
+    pub public_address: SocketAddr,
+    pub max_clients: usize,
+    pub protocol_id: u64,
+    pub authentication: AuthenticationMode,
+    ///the distance in chunks around the players where the chunks are sent to the clients
+    pub view_distance: i32,
+    pub world_seed: i64,
+    ///path to the jar of the world generator, the native generator is used if it can't be loaded
+    pub generator_path: PathBuf,
+    ///the number of ticks per second
+    pub tick_rate: u32,
+    ///the sizes of the receive and send buffers of the socket in bytes, the OS may cap them
+    pub socket_recv_buffer_size: usize,
+    pub socket_send_buffer_size: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 5000);
+        Self {
+            bind_address: address,
+            public_address: address,
+            max_clients: 64,
+            protocol_id: PROTOCOL_ID,
+            authentication: AuthenticationMode::Unsecure,
+            view_distance: 8,
+            world_seed: 0,
+            generator_path: PathBuf::from("crates/gen/build/libs/generator-1.0.0.jar"),
+            tick_rate: 20,
+            socket_recv_buffer_size: DEFAULT_SOCKET_BUFFER_SIZE,
+            socket_send_buffer_size: DEFAULT_SOCKET_BUFFER_SIZE,
+        }
+    }
+}
+
+impl ServerConfig {
+    ///load the config from a `key = value` file, missing keys (or a missing file) keep their default value
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = ConfigFile::load(path)?;
+        let default = Self::default();
+
+        let bind_address = file.get_or("bind_address", default.bind_address)?;
+        let authentication = match file.get::<String>("authentication")?.as_deref() {
+            None | Some("unsecure") => AuthenticationMode::Unsecure,
+            Some("secure") => {
+                let Some(private_key) = file.get::<String>("private_key")? else {
+                    anyhow::bail!("the secure authentication mode requires a private_key");
+                };
+                AuthenticationMode::Secure {
+                    private_key: parse_private_key(&private_key)?,
+                }
+            }
+            Some(mode) => anyhow::bail!("unknown authentication mode: {}", mode),
+        };
+
+        let tick_rate = file.get_or("tick_rate", default.tick_rate)?;
+        if tick_rate == 0 {
+            anyhow::bail!("tick_rate must be at least 1 tick per second");
+        }
+
+        let socket_recv_buffer_size =
+            file.get_or("socket_recv_buffer_size", default.socket_recv_buffer_size)?;
+        let socket_send_buffer_size =
+            file.get_or("socket_send_buffer_size", default.socket_send_buffer_size)?;
+        if socket_recv_buffer_size == 0 || socket_send_buffer_size == 0 {
+            anyhow::bail!("the socket buffer sizes must be at least 1 byte");
+        }
+
+        Ok(Self {
+            bind_address,
+            public_address: file.get_or("public_address", bind_address)?,
+            max_clients: file.get_or("max_clients", default.max_clients)?,
+            protocol_id: file.get_or("protocol_id", default.protocol_id)?,
+            authentication,
+            view_distance: file.get_or("view_distance", default.view_distance)?,
+            world_seed: file.get_or("world_seed", default.world_seed)?,
+            generator_path: file.get_or("generator_path", default.generator_path)?,
+            tick_rate,
+            socket_recv_buffer_size,
+            socket_send_buffer_size,
+        })
+    }
+}
+
+///parse a private key written as an hexadecimal string
+fn parse_private_key(hex: &str) -> anyhow::Result<[u8; NETCODE_KEY_BYTES]> {
+    if hex.len() != NETCODE_KEY_BYTES * 2 || !hex.is_ascii() {
+        anyhow::bail!(
+            "the private key must be {} hexadecimal characters long",
+            NETCODE_KEY_BYTES * 2
+        );
+    }
+    let mut key = [0; NETCODE_KEY_BYTES];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(key)
+}
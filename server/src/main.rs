@@ -1,8 +1,27 @@
 mod app;
+mod config;
 mod networking;
+mod world;
 
 use app::App;
+use config::ServerConfig;
+use networking::ServerNetworkHandler;
 
 fn main() -> anyhow::Result<()> {
-    App::new()?.run()
+    let config = ServerConfig::load("server.cfg")?;
+
+    //server generate-token <client_id> <output_file>
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("generate-token") {
+        let (Some(client_id), Some(path)) = (args.get(2), args.get(3)) else {
+            anyhow::bail!("usage: server generate-token <client_id> <output_file>");
+        };
+        let token =
+            ServerNetworkHandler::generate_connect_token(&config, client_id.parse()?, 3600)?;
+        token.write(&mut std::fs::File::create(path)?)?;
+        println!("connect token for client {} written to {}", client_id, path);
+        return Ok(());
+    }
+
+    App::new(config)?.run()
 }
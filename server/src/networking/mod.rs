@@ -1,37 +1,218 @@
+use crate::config::{AuthenticationMode, ServerConfig};
+use crate::world::{World, WorldChanges};
+use math::positions::{ChunkPos, EntityPos};
+use math::IVec3;
+use networking::c2s::{ChatPacket, PingPacket, PlayerMovePacket};
+use networking::packets::{ByteBuf, Dispatcher, Packet};
+use networking::s2c::{BlockBatchPacket, ChunkDataPacket, PlayerPositionPacket, PongPacket};
+use networking::socket::bind_udp_socket;
 use renet::transport::{
-    NetcodeServerTransport, NetcodeTransportError, ServerAuthentication, ServerConfig,
+    ConnectToken, NetcodeServerTransport, NetcodeTransportError, ServerAuthentication,
+    ServerConfig as NetcodeServerConfig,
 };
-use renet::{DefaultChannel, RenetServer, ServerEvent};
-use std::net::{SocketAddr, UdpSocket};
+use renet::{ClientId, DefaultChannel, DisconnectReason, RenetServer, ServerEvent};
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime};
 
+///what the server knows about a connected client
+pub struct ClientState {
+    ///the position of the player, the chunks are streamed around it
+    pub position: EntityPos,
+    ///the sequence of the last move applied, the older moves arriving late are ignored
+    last_move_sequence: Option<u32>,
+    ///the chunks already sent to the client, they are sent again only if the player comes back after they are forgotten
+    sent_chunks: HashSet<ChunkPos>,
+    ///the chunk of the player when the stream queue was computed
+    stream_center: Option<ChunkPos>,
+    ///the chunks to send, sorted from the farthest to the closest to the player
+    stream_queue: Vec<ChunkPos>,
+}
+
+impl ClientState {
+    fn new(position: EntityPos) -> Self {
+        Self {
+            position,
+            last_move_sequence: None,
+            sent_chunks: HashSet::new(),
+            stream_center: None,
+            stream_queue: Vec::new(),
+        }
+    }
+
+    ///apply the move of the player, false if it is ignored
+    ///the moves older than the last one and the moves out of the world are ignored, the chunks around them can't be computed
+    fn apply_move(&mut self, packet: &PlayerMovePacket) -> bool {
+        //the unreliable channel doesn't keep the order of the packets
+        if self
+            .last_move_sequence
+            .is_some_and(|last| packet.sequence <= last)
+        {
+            return false;
+        }
+        if !World::is_in_world(packet.pos.chunk_pos) {
+            return false;
+        }
+        self.position = packet.pos;
+        self.last_move_sequence = Some(packet.sequence);
+        true
+    }
+
+    ///the chunks farther than the view distance plus this margin are forgotten, the player may come back to them
+    const SENT_CHUNKS_MARGIN: i32 = 2;
+
+    ///compute the stream queue again for the new chunk of the player, and forget the chunks far from it
+    fn update_stream_queue(&mut self, view_distance: i32) {
+        let center = self.position.chunk_pos;
+        let max_distance = view_distance + Self::SENT_CHUNKS_MARGIN;
+        self.sent_chunks
+            .retain(|pos| (*pos - center).abs().max_element() <= max_distance);
+
+        self.stream_queue.clear();
+        for x in -view_distance..=view_distance {
+            for y in -view_distance..=view_distance {
+                for z in -view_distance..=view_distance {
+                    let pos = center + IVec3::new(x, y, z);
+                    //a player at the border of the world has no chunk to receive past it
+                    if World::is_in_world(pos) && !self.sent_chunks.contains(&pos) {
+                        self.stream_queue.push(pos);
+                    }
+                }
+            }
+        }
+        self.stream_queue
+            .sort_by_key(|pos| std::cmp::Reverse((*pos - center).length_squared()));
+        self.stream_center = Some(center);
+    }
+}
+
+///data given to the packet handlers
+pub struct PacketContext {
+    ///the client that sent the packet being handled
+    pub sender: ClientId,
+    pub clients: HashMap<ClientId, ClientState>,
+    ///the packets to send back, they are sent once all the received packets are handled
+    responses: Vec<(ClientId, DefaultChannel, ByteBuf)>,
+}
+
+impl PacketContext {
+    ///queue a packet to send to the sender of the packet being handled
+    pub fn respond(&mut self, channel: DefaultChannel, packet: impl Packet) {
+        self.responses
+            .push((self.sender, channel, packet.serialize().into()));
+    }
+}
+
+///a client connected or disconnected, see [`ServerNetworkHandler::poll_events`]
+pub enum NetEvent {
+    ClientConnected {
+        client_id: ClientId,
+    },
+    ClientDisconnected {
+        client_id: ClientId,
+        reason: DisconnectReason,
+    },
+}
+
 pub struct ServerNetworkHandler {
     packet_transporter: NetcodeServerTransport,
     renet_server: RenetServer,
+    dispatcher: Dispatcher<PacketContext>,
+    context: PacketContext,
+    view_distance: i32,
+    events: Vec<NetEvent>,
 }
 
 impl ServerNetworkHandler {
-    pub fn new(server_address: SocketAddr) -> anyhow::Result<Self> {
-        let udp_socket = UdpSocket::bind(server_address)?;
-        let server_config = ServerConfig {
+    pub fn new(config: &ServerConfig) -> anyhow::Result<Self> {
+        let udp_socket = bind_udp_socket(
+            config.bind_address,
+            config.socket_recv_buffer_size,
+            config.socket_send_buffer_size,
+        )?;
+        let authentication = match config.authentication {
+            AuthenticationMode::Unsecure => ServerAuthentication::Unsecure,
+            AuthenticationMode::Secure { private_key } => {
+                ServerAuthentication::Secure { private_key }
+            }
+        };
+        let server_config = NetcodeServerConfig {
             current_time: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap(),
-            max_clients: 64,
-            protocol_id: 0,
-            public_addresses: vec![server_address],
-            authentication: ServerAuthentication::Unsecure,
+            max_clients: config.max_clients,
+            protocol_id: config.protocol_id,
+            public_addresses: vec![config.public_address],
+            authentication,
         };
         let packet_transporter = NetcodeServerTransport::new(server_config, udp_socket)?;
 
         let renet_server = RenetServer::new(Default::default());
 
+        let mut dispatcher = Dispatcher::new();
+        Self::register_handlers(&mut dispatcher);
+
         Ok(Self {
             packet_transporter,
             renet_server,
+            dispatcher,
+            context: PacketContext {
+                sender: ClientId::from_raw(0),
+                clients: HashMap::new(),
+                responses: Vec::new(),
+            },
+            view_distance: config.view_distance,
+            events: Vec::new(),
         })
     }
 
+    ///generate a connect token signed with the private key of the server, to give to a client
+    pub fn generate_connect_token(
+        config: &ServerConfig,
+        client_id: u64,
+        expire_seconds: u64,
+    ) -> anyhow::Result<ConnectToken> {
+        let AuthenticationMode::Secure { private_key } = &config.authentication else {
+            anyhow::bail!("connect tokens are only used by the secure authentication mode");
+        };
+        let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let token = ConnectToken::generate(
+            current_time,
+            config.protocol_id,
+            expire_seconds,
+            client_id,
+            15, //timeout in seconds
+            vec![config.public_address],
+            None,
+            private_key,
+        )?;
+        Ok(token)
+    }
+
+    fn register_handlers(dispatcher: &mut Dispatcher<PacketContext>) {
+        dispatcher.register_handler(|packet: ChatPacket, context: &mut PacketContext| {
+            println!("[{}] {}", context.sender, packet.message);
+        });
+        dispatcher.register_handler(|packet: PlayerMovePacket, context: &mut PacketContext| {
+            let Some(client) = context.clients.get_mut(&context.sender) else {
+                return;
+            };
+            if !client.apply_move(&packet) {
+                return;
+            }
+            let acknowledgement = PlayerPositionPacket {
+                sequence: packet.sequence,
+                pos: client.position,
+            };
+            context.respond(DefaultChannel::Unreliable, acknowledgement);
+        });
+        dispatcher.register_handler(|packet: PingPacket, context: &mut PacketContext| {
+            let pong = PongPacket {
+                timestamp: packet.timestamp,
+            };
+            context.respond(DefaultChannel::Unreliable, pong);
+        });
+    }
+
     pub fn tick(&mut self, delta_time: Duration) -> Result<(), NetcodeTransportError> {
         self.packet_transporter
             .update(delta_time, &mut self.renet_server)?;
@@ -46,23 +227,128 @@ impl ServerNetworkHandler {
         while let Some(event) = self.renet_server.get_event() {
             match event {
                 ServerEvent::ClientConnected { client_id } => {
-                    println!("Client {client_id} connected")
+                    let state = ClientState::new(EntityPos::from(0.0, 0.0, 0.0)); //the spawn
+                    self.context.clients.insert(client_id, state);
+                    self.events.push(NetEvent::ClientConnected { client_id });
                 }
                 ServerEvent::ClientDisconnected { client_id, reason } => {
-                    println!("Client {client_id} disconnected: {reason}")
+                    self.context.clients.remove(&client_id);
+                    self.events
+                        .push(NetEvent::ClientDisconnected { client_id, reason });
                 }
             }
         }
     }
 
+    ///return the connections and disconnections that happened since the last call
+    pub fn poll_events(&mut self) -> Vec<NetEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     pub fn process_packets(&mut self) {
+        let channels: [u8; 2] = [
+            DefaultChannel::ReliableOrdered.into(),
+            DefaultChannel::Unreliable.into(),
+        ];
         for client_id in self.renet_server.clients_id() {
-            while let Some(packet) = self
-                .renet_server
-                .receive_message(client_id, DefaultChannel::Unreliable)
+            self.context.sender = client_id;
+            for channel in channels {
+                while let Some(packet) = self.renet_server.receive_message(client_id, channel) {
+                    let data = packet.to_vec().into_boxed_slice();
+                    self.dispatcher.dispatch_packet(data, &mut self.context);
+                }
+            }
+        }
+
+        for (client_id, channel, packet) in self.context.responses.drain(..) {
+            self.renet_server.send_message(client_id, channel, packet);
+        }
+    }
+
+    ///the chunks the players are in, the world is generated around them
+    pub fn player_chunks(&self) -> Vec<ChunkPos> {
+        self.context
+            .clients
+            .values()
+            .map(|client| client.position.chunk_pos)
+            .collect()
+    }
+
+    ///send the changes of the tick to the clients that have the chunks, the others receive them when the chunks are streamed
+    ///the block edits are sent in one packet per chunk, the other modified chunks are sent whole
+    pub fn send_world_changes(&mut self, world: &World, changes: WorldChanges) {
+        for (pos, edits) in changes.block_edits {
+            let changes = edits
+                .into_iter()
+                .map(|(index, state)| (index, state.id()))
+                .collect();
+            let packet: ByteBuf = BlockBatchPacket {
+                chunk: pos,
+                changes,
+            }
+            .serialize()
+            .into();
+            self.send_to_holders(pos, packet);
+        }
+
+        for pos in changes.modified_chunks {
+            if !self
+                .context
+                .clients
+                .values()
+                .any(|client| client.sent_chunks.contains(&pos))
             {
-                let str = String::from_utf8_lossy(packet.as_ref());
-                println!("received packet: {}", str);
+                continue; //a chunk just generated, it is streamed later
+            }
+            let Some(chunk) = world.get_chunk(pos) else {
+                continue;
+            };
+            let data = chunk.serialize();
+            let packet: ByteBuf = ChunkDataPacket { pos, data }.serialize().into();
+            self.send_to_holders(pos, packet);
+        }
+    }
+
+    ///send a packet to the clients that have received the chunk at the given position
+    fn send_to_holders(&mut self, pos: ChunkPos, packet: ByteBuf) {
+        for (client_id, client) in self.context.clients.iter() {
+            if client.sent_chunks.contains(&pos) {
+                self.renet_server.send_message(
+                    *client_id,
+                    DefaultChannel::ReliableOrdered,
+                    packet.clone(),
+                );
+            }
+        }
+    }
+
+    ///send to each client the chunks around its player it doesn't have yet, the closest first
+    ///the number of chunks sent per tick is limited to not flood the reliable channel
+    ///the chunks not generated yet stay in the queue, they are sent once the world has generated them
+    pub fn stream_chunks(&mut self, world: &World) {
+        const MAX_CHUNKS_PER_TICK: usize = 16;
+
+        for (client_id, client) in self.context.clients.iter_mut() {
+            if client.stream_center != Some(client.position.chunk_pos) {
+                client.update_stream_queue(self.view_distance);
+            }
+
+            let mut sent = 0;
+            let mut index = client.stream_queue.len();
+            while sent < MAX_CHUNKS_PER_TICK && index > 0 {
+                index -= 1;
+                let pos = client.stream_queue[index];
+                let Some(chunk) = world.get_chunk(pos) else {
+                    continue;
+                };
+                let data = chunk.serialize();
+                let packet: ByteBuf = ChunkDataPacket { pos, data }.serialize().into();
+                self.renet_server
+                    .send_message(*client_id, DefaultChannel::ReliableOrdered, packet);
+                client.sent_chunks.insert(pos);
+                //the chunks after it are the closer ones not generated yet, there are few of them
+                client.stream_queue.remove(index);
+                sent += 1;
             }
         }
     }
@@ -72,3 +358,39 @@ impl ServerNetworkHandler {
             .disconnect_all(&mut self.renet_server);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::networking::ClientState;
+    use math::positions::{ChunkPos, EntityPos};
+    use math::Vec3;
+    use networking::c2s::PlayerMovePacket;
+    use world_core::ChunkManager;
+
+    fn move_to(sequence: u32, chunk_pos: ChunkPos) -> PlayerMovePacket {
+        PlayerMovePacket {
+            sequence,
+            pos: EntityPos::new(chunk_pos, Vec3::splat(8.0)),
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+
+    #[test]
+    pub fn the_moves_out_of_the_world_are_ignored() {
+        let mut client = ClientState::new(EntityPos::from(0.0, 0.0, 0.0));
+        assert!(client.apply_move(&move_to(1, ChunkPos::new(3, 0, -2))));
+        assert!(!client.apply_move(&move_to(2, ChunkPos::new(i32::MAX, 0, 0))));
+        let border = ChunkPos::new(ChunkManager::MIN_CHUNK_COORD - 1, 0, 0);
+        assert!(!client.apply_move(&move_to(3, border)));
+        assert_eq!(client.position.chunk_pos, ChunkPos::new(3, 0, -2));
+
+        //the streaming around the position doesn't overflow
+        client.update_stream_queue(2);
+        assert_eq!(client.stream_queue.len(), 125);
+
+        //the ignored moves don't count in the sequence
+        assert!(client.apply_move(&move_to(2, ChunkPos::new(4, 0, -2))));
+        assert!(!client.apply_move(&move_to(2, ChunkPos::new(5, 0, -2))));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::ServerConfig;
+use gen::WorldGenerator;
+use math::positions::ChunkPos;
+use math::IVec3;
+use std::collections::HashSet;
+use world_core::block_state::BlockState;
+use world_core::{Chunk, ChunkManager};
+
+///what changed in the world during a tick, to send to the clients that have the chunks
+pub struct WorldChanges {
+    ///the blocks set, grouped by chunk, see [`ChunkManager::drain_block_edits`]
+    pub block_edits: Vec<(ChunkPos, Vec<(u16, BlockState)>)>,
+    ///the other modified chunks, their blocks aren't recorded so they are sent whole
+    ///the chunks generated this tick are in it too, no client has them yet
+    pub modified_chunks: Vec<ChunkPos>,
+}
+
+///the world of the server, the chunks around the players are generated during the ticks
+pub struct World {
+    chunk_manager: ChunkManager,
+    generator: Box<dyn WorldGenerator>,
+    view_distance: i32,
+    ///the chunks of the players when the generation queue was computed
+    generation_centers: Vec<ChunkPos>,
+    ///the chunks to generate, sorted from the farthest to the closest to a player, so the closest is popped first
+    generation_queue: Vec<ChunkPos>,
+}
+
+impl World {
+    ///the number of chunks generated per tick, the rest waits for the next ticks
+    const MAX_GENERATED_PER_TICK: usize = 16;
+
+    pub fn new(config: &ServerConfig) -> anyhow::Result<Self> {
+        let mut chunk_manager = ChunkManager::new();
+        chunk_manager.record_block_edits(); //they are sent to the clients in batches
+        Ok(Self {
+            chunk_manager,
+            generator: gen::load_generator(Some(&config.generator_path), config.world_seed),
+            view_distance: config.view_distance,
+            generation_centers: Vec::new(),
+            generation_queue: Vec::new(),
+        })
+    }
+
+    ///get the chunk at the given position, None if it isn't generated yet or out of the world
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunk_manager.get_chunk(pos)
+    }
+
+    ///whether a chunk can exist at the given position, see [`ChunkManager::MIN_CHUNK_COORD`]
+    pub fn is_in_world(pos: ChunkPos) -> bool {
+        let min = IVec3::splat(ChunkManager::MIN_CHUNK_COORD);
+        let max = IVec3::splat(ChunkManager::MAX_CHUNK_COORD);
+        pos.cmpge(min).all() && pos.cmple(max).all()
+    }
+
+    ///update the world once per tick, after the packets of the clients are processed and before the changes are sent
+    ///the chunks around the players are generated, the closest first, then the changes of the tick are given
+    pub fn tick(&mut self, player_chunks: &[ChunkPos]) -> WorldChanges {
+        self.generate_around(player_chunks);
+
+        let block_edits = self.chunk_manager.drain_block_edits();
+        let edited: HashSet<ChunkPos> = block_edits.iter().map(|(pos, _)| *pos).collect();
+        let mut modified_chunks = Vec::new();
+        self.chunk_manager.drain_modified(|pos, _| {
+            if !edited.contains(&pos) {
+                modified_chunks.push(pos);
+            }
+        });
+        WorldChanges {
+            block_edits,
+            modified_chunks,
+        }
+    }
+
+    ///generate the missing chunks in the view distance of the players, at most [`World::MAX_GENERATED_PER_TICK`]
+    ///the queue is only computed again when a player enters another chunk
+    fn generate_around(&mut self, player_chunks: &[ChunkPos]) {
+        let mut centers = player_chunks.to_vec();
+        centers.sort_by_key(|pos| pos.to_array());
+        centers.dedup();
+        if centers != self.generation_centers {
+            self.generation_queue = self.missing_chunks(&centers);
+            self.generation_centers = centers;
+        }
+
+        let mut generated = 0;
+        while generated < Self::MAX_GENERATED_PER_TICK {
+            let Some(pos) = self.generation_queue.pop() else {
+                break;
+            };
+            //the chunk may have been generated since the queue was computed
+            if self.chunk_manager.get_chunk(pos).is_some() {
+                continue;
+            }
+            let chunk = gen::generate_chunk(self.generator.as_mut(), pos);
+            if self.chunk_manager.insert_chunk(chunk).is_ok() {
+                generated += 1;
+            }
+        }
+    }
+
+    ///the chunks of the world not loaded in the view distance of the centers, the closest to a center last
+    fn missing_chunks(&self, centers: &[ChunkPos]) -> Vec<ChunkPos> {
+        let distance = self.view_distance;
+        let mut missing = HashSet::new();
+        for center in centers {
+            for x in -distance..=distance {
+                for y in -distance..=distance {
+                    for z in -distance..=distance {
+                        let pos = *center + IVec3::new(x, y, z);
+                        if Self::is_in_world(pos) && self.chunk_manager.get_chunk(pos).is_none() {
+                            missing.insert(pos);
+                        }
+                    }
+                }
+            }
+        }
+        let mut missing: Vec<ChunkPos> = missing.into_iter().collect();
+        missing.sort_by_key(|pos| {
+            let closest = centers
+                .iter()
+                .map(|center| (*pos - *center).length_squared())
+                .min();
+            std::cmp::Reverse(closest)
+        });
+        missing
+    }
+}